@@ -0,0 +1,526 @@
+use anyhow::{Result, anyhow};
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::path::PathBuf;
+
+use crate::config::{AddOptions, Bin, Config, Group};
+use crate::manifest::{Manifest, ManifestGroup, Roots};
+use crate::scan::SourceDir;
+
+/// One difference between the config and a manifest
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Change {
+    CreateGroup(String),
+    DeleteGroup(String),
+    Add {
+        group: String,
+        name: String,
+        path: PathBuf,
+    },
+    Remove {
+        group: String,
+        name: String,
+    },
+    /// Same name, another file
+    Retarget {
+        group: String,
+        name: String,
+        from: PathBuf,
+        to: PathBuf,
+    },
+    /// Same file, another name
+    Rename {
+        group: String,
+        from: String,
+        to: String,
+    },
+    Enable {
+        group: String,
+        name: String,
+    },
+    Disable {
+        group: String,
+        name: String,
+    },
+    /// `extends` or `exclude` of a group
+    Inheritance {
+        group: String,
+        extends: Vec<String>,
+        exclude: Vec<String>,
+    },
+    /// The source directories of a group or their rules
+    Sources {
+        group: String,
+        dirs: Vec<PathBuf>,
+    },
+    Activate {
+        from: Vec<String>,
+        to: Vec<String>,
+    },
+    /// An archive source that is not unpacked yet, whose executables are
+    /// not known until it is
+    Unpack {
+        group: String,
+        archive: PathBuf,
+        dir: PathBuf,
+    },
+}
+
+impl Change {
+    /// `+` for additions, `-` for removals and `~` for everything else
+    pub fn sign(&self) -> char {
+        match self {
+            Change::CreateGroup(_)
+            | Change::Add { .. }
+            | Change::Enable { .. }
+            | Change::Unpack { .. } => '+',
+            Change::DeleteGroup(_) | Change::Remove { .. } | Change::Disable { .. } => '-',
+            _ => '~',
+        }
+    }
+}
+
+impl fmt::Display for Change {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Change::CreateGroup(group) => write!(f, "create group {}", group),
+            Change::DeleteGroup(group) => write!(f, "delete group {}", group),
+            Change::Add { group, name, path } => {
+                write!(f, "{}/{} -> {}", group, name, path.display())
+            }
+            Change::Remove { group, name } => write!(f, "{}/{}", group, name),
+            Change::Retarget {
+                group,
+                name,
+                from,
+                to,
+            } => write!(
+                f,
+                "{}/{} -> {} (was {})",
+                group,
+                name,
+                to.display(),
+                from.display()
+            ),
+            Change::Rename { group, from, to } => {
+                write!(f, "{}/{} renamed to {}", group, from, to)
+            }
+            Change::Enable { group, name } => write!(f, "enable {}/{}", group, name),
+            Change::Disable { group, name } => write!(f, "disable {}/{}", group, name),
+            Change::Inheritance {
+                group,
+                extends,
+                exclude,
+            } => {
+                write!(f, "{} extends [{}]", group, extends.join(", "))?;
+                if !exclude.is_empty() {
+                    write!(f, " excluding [{}]", exclude.join(", "))?;
+                }
+                Ok(())
+            }
+            Change::Sources { group, dirs } => {
+                let dirs: Vec<String> = dirs.iter().map(|d| d.display().to_string()).collect();
+                write!(f, "{} sources [{}]", group, dirs.join(", "))
+            }
+            Change::Activate { from, to } => {
+                write!(f, "activate {} (was {})", to.join(", "), from.join(", "))
+            }
+            Change::Unpack {
+                group,
+                archive,
+                dir,
+            } => write!(
+                f,
+                "{} unpacks {} to {}",
+                group,
+                archive.display(),
+                dir.display()
+            ),
+        }
+    }
+}
+
+/// The changes that bring a config to the state of a manifest, together
+/// with the resulting config
+pub struct Plan {
+    pub changes: Vec<Change>,
+    target: Config,
+    /// Whether archives were left packed, so that the plan misses their
+    /// executables
+    packed: bool,
+}
+
+impl Plan {
+    /// Apply the changes to `conf`, which must be the config the plan was
+    /// made for, and update the symlinks
+    pub fn apply(self, conf: &mut Config) -> Result<()> {
+        if self.packed {
+            anyhow::bail!("the plan was made without unpacking archives");
+        }
+        self.target.relink_from(conf)?;
+        *conf = self.target;
+        Ok(())
+    }
+}
+
+/// Compare `conf` with `manifest` without changing anything. Only the
+/// writable layer is compared: every group of the manifest is made to hold
+/// exactly the listed sources and executables there, and with `prune` the
+/// groups of that layer missing from the manifest are deleted. Paths that
+/// do not exist and names listed twice are errors, so that a plan is
+/// never applied halfway. Without `unpack`, nothing is written to disk:
+/// archives that are not unpacked yet are planned as `Change::Unpack`
+/// and the plan can only be shown.
+pub fn plan(
+    conf: &Config,
+    manifest: &Manifest,
+    roots: &Roots,
+    prune: bool,
+    unpack: bool,
+) -> Result<Plan> {
+    let mut changes = Vec::new();
+    let mut unpacks = Vec::new();
+    let mut groups = Vec::new();
+    for (gn, mg) in &manifest.groups {
+        let wanted = wanted_group(conf, gn, mg, roots, unpack, &mut unpacks)?;
+        match conf.groups.get(gn) {
+            Some(current) => diff_group(conf, gn, current, &wanted, &mut changes),
+            None => {
+                changes.push(Change::CreateGroup(gn.clone()));
+                diff_group(conf, gn, &Group::default(), &wanted, &mut changes);
+            }
+        }
+        groups.push((gn.clone(), wanted));
+    }
+    let packed = !unpacks.is_empty();
+    changes.extend(unpacks);
+
+    let mut removed: Vec<String> = Vec::new();
+    if prune {
+        removed = conf
+            .groups
+            .iter()
+            .filter(|(gn, g)| g.origin == conf.layer && !manifest.groups.contains_key(*gn))
+            .map(|(gn, _)| gn.clone())
+            .collect();
+        removed.sort();
+        changes.extend(removed.iter().cloned().map(Change::DeleteGroup));
+    }
+
+    if !manifest.active.is_empty() && manifest.active != conf.active_groups() {
+        changes.push(Change::Activate {
+            from: conf.active_groups(),
+            to: manifest.active.clone(),
+        });
+    }
+
+    let mut target = conf.clone();
+    target.replace_groups(groups, &removed, &manifest.active)?;
+    Ok(Plan {
+        changes,
+        target,
+        packed,
+    })
+}
+
+/// The bins and sources `mg` asks for, as they would be added. Archives
+/// that are not unpacked yet are added to `unpacks` instead of being
+/// unpacked, unless `unpack` is set.
+fn wanted_group(
+    conf: &Config,
+    gn: &str,
+    mg: &ManifestGroup,
+    roots: &Roots,
+    unpack: bool,
+    unpacks: &mut Vec<Change>,
+) -> Result<Group> {
+    let mut bins: BTreeMap<String, Bin> = BTreeMap::new();
+    let mut group = Group::with_origin(conf.layer);
+    group.extends = mg.extends.clone();
+    group.exclude = mg.exclude.clone();
+
+    let mut additions = Vec::new();
+    for source in &mg.sources {
+        let (dir, pending) = source.locate(roots, unpack)?;
+        if let Some(archive) = pending {
+            group.sources.push(SourceDir::new(
+                dir.clone(),
+                source.rules.clone(),
+                conf.layer,
+            ));
+            unpacks.push(Change::Unpack {
+                group: gn.to_string(),
+                archive,
+                dir,
+            });
+            continue;
+        }
+        if !dir.is_dir() {
+            anyhow::bail!("directory {} of group {} does not exist", dir.display(), gn);
+        }
+        let options = AddOptions {
+            rules: source.rules.clone(),
+            ..Default::default()
+        };
+        additions.push((dir, options));
+    }
+    for mb in &mg.bins {
+        let path = roots.expand(&mb.path)?;
+        if !path.is_file() {
+            anyhow::bail!(
+                "executable {} of group {} does not exist",
+                path.display(),
+                gn
+            );
+        }
+        let options = AddOptions {
+            name: Some(mb.name.clone()),
+            ..Default::default()
+        };
+        additions.push((path, options));
+    }
+
+    for (path, options) in additions {
+        let (found, source) = conf
+            .collect_bins(&path, &options)
+            .map_err(|e| anyhow!("group {}: cannot add {}: {}", gn, path.display(), e))?;
+        group.sources.extend(source);
+        for bin in found {
+            if let Some(other) = bins.get(&bin.name) {
+                if other.path == bin.path {
+                    continue;
+                }
+                anyhow::bail!(
+                    "executable {} is listed twice in group {}: {} and {}",
+                    bin.name,
+                    gn,
+                    other.path.display(),
+                    bin.path.display()
+                );
+            }
+            bins.insert(bin.name.clone(), bin);
+        }
+    }
+
+    for name in &mg.disabled {
+        bins.get_mut(name)
+            .ok_or_else(|| anyhow!("disabled executable {} is not in group {}", name, gn))?
+            .enabled = false;
+    }
+    group.bins = bins.into_iter().collect();
+    Ok(group)
+}
+
+fn diff_group(conf: &Config, gn: &str, current: &Group, wanted: &Group, changes: &mut Vec<Change>) {
+    let group = || gn.to_string();
+    if current.extends != wanted.extends || current.exclude != wanted.exclude {
+        changes.push(Change::Inheritance {
+            group: group(),
+            extends: wanted.extends.clone(),
+            exclude: wanted.exclude.clone(),
+        });
+    }
+
+    let source_set = |g: &Group| {
+        let mut sources: Vec<_> = g
+            .sources
+            .iter()
+            .filter(|s| s.origin == conf.layer)
+            .map(|s| (s.dir.clone(), s.rules.clone()))
+            .collect();
+        sources.sort_by(|a, b| a.0.cmp(&b.0));
+        sources
+    };
+    let wanted_sources = source_set(wanted);
+    if source_set(current) != wanted_sources {
+        changes.push(Change::Sources {
+            group: group(),
+            dirs: wanted_sources.into_iter().map(|(dir, _)| dir).collect(),
+        });
+    }
+
+    let own: HashMap<&str, &Bin> = current
+        .bins
+        .values()
+        .chain(&current.shadowed)
+        .filter(|b| b.origin == conf.layer)
+        .map(|b| (b.name.as_str(), b))
+        .collect();
+    let mut names: Vec<&str> = own.keys().copied().collect();
+    names.extend(wanted.bins.keys().map(String::as_str));
+    names.sort();
+    names.dedup();
+
+    // a file that only changed its name is a rename rather than a removal
+    // and an addition
+    let mut renamed: HashMap<&str, &str> = HashMap::new();
+    for name in &names {
+        if let (Some(old), None) = (own.get(name), wanted.bins.get(*name))
+            && let Some(new) = wanted.bins.values().find(|b| {
+                b.path == old.path
+                    && !own.contains_key(b.name.as_str())
+                    && !renamed.values().any(|n| *n == b.name)
+            })
+        {
+            renamed.insert(name, &new.name);
+        }
+    }
+
+    for name in names {
+        let state_change = |old: &Bin, new: &Bin| {
+            (old.enabled != new.enabled).then(|| {
+                if new.enabled {
+                    Change::Enable {
+                        group: group(),
+                        name: new.name.clone(),
+                    }
+                } else {
+                    Change::Disable {
+                        group: group(),
+                        name: new.name.clone(),
+                    }
+                }
+            })
+        };
+        match (own.get(name), wanted.bins.get(name)) {
+            (Some(old), Some(new)) => {
+                if old.path != new.path {
+                    changes.push(Change::Retarget {
+                        group: group(),
+                        name: name.to_string(),
+                        from: old.path.clone(),
+                        to: new.path.clone(),
+                    });
+                }
+                changes.extend(state_change(old, new));
+            }
+            (Some(old), None) => match renamed.get(name) {
+                Some(to) => {
+                    changes.push(Change::Rename {
+                        group: group(),
+                        from: name.to_string(),
+                        to: to.to_string(),
+                    });
+                    changes.extend(state_change(old, &wanted.bins[*to]));
+                }
+                None => changes.push(Change::Remove {
+                    group: group(),
+                    name: name.to_string(),
+                }),
+            },
+            (None, Some(new)) => {
+                if renamed.values().any(|n| *n == name) {
+                    continue;
+                }
+                changes.push(Change::Add {
+                    group: group(),
+                    name: name.to_string(),
+                    path: new.path.clone(),
+                });
+                if !new.enabled {
+                    changes.push(Change::Disable {
+                        group: group(),
+                        name: name.to_string(),
+                    });
+                }
+            }
+            (None, None) => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use std::path::Path;
+    use tempfile::TempDir;
+
+    fn write_script(path: &Path) {
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, "#!/bin/sh\n").unwrap();
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            fs::set_permissions(path, fs::Permissions::from_mode(0o755)).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_plan_and_apply_converge() {
+        let temp_dir = TempDir::new().unwrap();
+        let root = temp_dir.path().canonicalize().unwrap();
+        write_script(&root.join("tools").join("fmt"));
+        write_script(&root.join("tools").join("lint"));
+        write_script(&root.join("scripts").join("deploy.sh"));
+
+        let bin_dir = root.join("bin");
+        fs::create_dir_all(&bin_dir).unwrap();
+        let mut conf = Config::new(bin_dir.clone());
+        conf.add("dev", root.join("scripts").join("deploy.sh"), None)
+            .unwrap();
+        conf.add("old", root.join("tools").join("fmt"), None)
+            .unwrap();
+
+        let text = r#"
+            version = 1
+            active = ["dev"]
+
+            [groups.dev]
+            disabled = ["lint"]
+            sources = [{ dir = "tools" }]
+            bins = [{ name = "ship", path = "scripts/deploy.sh" }]
+        "#;
+        let manifest = Manifest::parse(Path::new("x.toml"), text).unwrap();
+        let roots = Roots::default().with_base(&root);
+
+        let plan = super::plan(&conf, &manifest, &roots, true, true).unwrap();
+        assert_eq!(
+            plan.changes,
+            vec![
+                Change::Sources {
+                    group: "dev".to_string(),
+                    dirs: vec![root.join("tools")],
+                },
+                Change::Rename {
+                    group: "dev".to_string(),
+                    from: "deploy".to_string(),
+                    to: "ship".to_string(),
+                },
+                Change::Add {
+                    group: "dev".to_string(),
+                    name: "fmt".to_string(),
+                    path: root.join("tools").join("fmt"),
+                },
+                Change::Add {
+                    group: "dev".to_string(),
+                    name: "lint".to_string(),
+                    path: root.join("tools").join("lint"),
+                },
+                Change::Disable {
+                    group: "dev".to_string(),
+                    name: "lint".to_string(),
+                },
+                Change::DeleteGroup("old".to_string()),
+                Change::Activate {
+                    from: vec!["base".to_string()],
+                    to: vec!["dev".to_string()],
+                },
+            ]
+        );
+        plan.apply(&mut conf).unwrap();
+        assert!(!conf.group_exists("old"));
+        assert!(bin_dir.join("ship").symlink_metadata().is_ok());
+        assert!(bin_dir.join("fmt").symlink_metadata().is_ok());
+        assert!(bin_dir.join("lint").symlink_metadata().is_err());
+
+        // applying again changes nothing
+        let plan = super::plan(&conf, &manifest, &roots, true, true).unwrap();
+        assert!(plan.changes.is_empty(), "{:?}", plan.changes);
+
+        // nothing is planned when a path is missing
+        let text = r#"{"version": 1, "groups": {"dev": {"sources": [{"dir": "gone"}]}}}"#;
+        let manifest = Manifest::parse(Path::new("x.json"), text).unwrap();
+        assert!(super::plan(&conf, &manifest, &roots, false, true).is_err());
+    }
+}
@@ -0,0 +1,122 @@
+use anyhow::{Result, anyhow};
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::config::{get_state_dir, state_dir_path};
+
+/// Hex encoded SHA-256 of a file
+pub fn sha256_file(path: &Path) -> Result<String> {
+    let mut file = fs::File::open(path)?;
+    let mut hasher = Sha256::new();
+    io::copy(&mut file, &mut hasher)?;
+    Ok(hasher
+        .finalize()
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect())
+}
+
+/// Unpack a `.tar`, `.tar.gz` or `.tgz` archive after checking its
+/// checksum, and return the directory it was unpacked to. Archives are
+/// unpacked once into the state directory, keyed by their checksum.
+pub fn unpack(archive: &Path, sha256: &str) -> Result<PathBuf> {
+    unpack_into(&get_state_dir()?.join("archives"), archive, sha256)
+}
+
+/// The directory `unpack` unpacks an archive with this checksum to,
+/// which may not exist yet
+pub fn unpacked_dir(sha256: &str) -> Result<PathBuf> {
+    Ok(state_dir_path()?.join("archives").join(checksum(sha256)?))
+}
+
+/// `sha256` in lowercase, if it is a valid checksum
+fn checksum(sha256: &str) -> Result<String> {
+    let checksum = sha256.trim().to_ascii_lowercase();
+    if checksum.len() != 64 || !checksum.chars().all(|c| c.is_ascii_hexdigit()) {
+        anyhow::bail!("invalid sha256 checksum {}", sha256);
+    }
+    Ok(checksum)
+}
+
+fn unpack_into(cache_dir: &Path, archive: &Path, sha256: &str) -> Result<PathBuf> {
+    let expected = checksum(sha256)?;
+    let dest = cache_dir.join(&expected);
+    if dest.is_dir() {
+        return Ok(dest);
+    }
+
+    let actual = sha256_file(archive)
+        .map_err(|e| anyhow!("cannot read archive {}: {}", archive.display(), e))?;
+    if actual != expected {
+        anyhow::bail!(
+            "checksum mismatch for {}: expected {}, got {}",
+            archive.display(),
+            expected,
+            actual
+        );
+    }
+
+    let file_name = archive
+        .file_name()
+        .map(|n| n.to_string_lossy().to_ascii_lowercase())
+        .unwrap_or_default();
+    let reader: Box<dyn io::Read> = if file_name.ends_with(".tar.gz") || file_name.ends_with(".tgz")
+    {
+        Box::new(flate2::read::GzDecoder::new(fs::File::open(archive)?))
+    } else if file_name.ends_with(".tar") {
+        Box::new(fs::File::open(archive)?)
+    } else {
+        anyhow::bail!(
+            "unsupported archive {}, expected .tar, .tar.gz or .tgz",
+            archive.display()
+        );
+    };
+
+    // unpack next to the destination so an interrupted run leaves no
+    // half filled cache entry behind
+    fs::create_dir_all(cache_dir)?;
+    let partial = cache_dir.join(format!("{}.partial", expected));
+    if partial.exists() {
+        fs::remove_dir_all(&partial)?;
+    }
+    tar::Archive::new(reader).unpack(&partial)?;
+    fs::rename(&partial, &dest)?;
+    Ok(dest)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_unpack_checks_checksum_and_caches() {
+        let temp_dir = TempDir::new().unwrap();
+        let archive = temp_dir.path().join("tools.tar");
+        let mut builder = tar::Builder::new(fs::File::create(&archive).unwrap());
+        let content = b"#!/bin/sh\n";
+        let mut header = tar::Header::new_gnu();
+        header.set_size(content.len() as u64);
+        header.set_mode(0o755);
+        header.set_cksum();
+        builder
+            .append_data(&mut header, "bin/tool", &content[..])
+            .unwrap();
+        builder.finish().unwrap();
+        drop(builder);
+
+        let cache_dir = temp_dir.path().join("cache");
+        let wrong = "0".repeat(64);
+        assert!(unpack_into(&cache_dir, &archive, &wrong).is_err());
+        assert!(unpack_into(&cache_dir, &archive, "abc").is_err());
+
+        let sha256 = sha256_file(&archive).unwrap();
+        let dest = unpack_into(&cache_dir, &archive, &sha256).unwrap();
+        assert!(dest.join("bin").join("tool").is_file());
+        // the cached copy is used even when the archive is gone
+        fs::remove_file(&archive).unwrap();
+        assert_eq!(unpack_into(&cache_dir, &archive, &sha256).unwrap(), dest);
+    }
+}
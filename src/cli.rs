@@ -1,12 +1,43 @@
-use clap::{ArgAction, Parser, Subcommand};
+use clap::{ArgAction, Args, Parser, Subcommand};
+use std::path::PathBuf;
+
+use crate::config::OnConflict;
+use crate::listing::{Column, SortOrder};
+use crate::managers::Manager;
+use crate::search::SortBy;
 
 #[derive(Parser)]
 #[clap(author, version, about, long_about = None)]
 pub struct Cli {
+    #[command(flatten)]
+    pub global: GlobalArgs,
     #[clap(subcommand)]
     pub command: Commands,
 }
 
+/// Options accepted before or after any subcommand
+#[derive(Args)]
+pub struct GlobalArgs {
+    /// Use a named profile instead of the default one
+    #[arg(long = "profile", global = true, env = "X_PROFILE")]
+    pub profile: Option<String>,
+    /// Operate on the system-wide config shared by all users
+    #[arg(long = "system", global = true, action = ArgAction::SetTrue)]
+    pub system: bool,
+    /// Replace or remove files in the bin directory that x did not create
+    #[arg(long = "force-links", global = true, action = ArgAction::SetTrue)]
+    pub force_links: bool,
+}
+
+/// `x <program> [args...]`, a shortcut for `x run`
+#[derive(Parser)]
+pub struct ShortRunCommand {
+    #[command(flatten)]
+    pub global: GlobalArgs,
+    #[command(flatten)]
+    pub run: RunCommand,
+}
+
 #[derive(Subcommand)]
 pub enum Commands {
     /// Run a program
@@ -57,6 +88,80 @@ pub enum Commands {
     /// Search for executables
     #[command()]
     Search(SearchCommand),
+
+    /// Manage profiles
+    #[command()]
+    Profile(ProfileCommand),
+
+    /// Show what switching from one group to another changes
+    #[command()]
+    Diff(DiffCommand),
+
+    /// Check the bin directory for missing, broken and foreign files
+    #[command()]
+    Doctor(DoctorCommand),
+
+    /// Create, copy, rename, delete and describe groups
+    #[command()]
+    Group(GroupCommand),
+
+    /// Make a group inherit the executables of other groups
+    #[command()]
+    Extend(ExtendCommand),
+
+    /// Rescan the directories added to a group
+    #[command()]
+    Sync(SyncCommand),
+
+    /// Keep groups in sync with their directories as files change
+    #[command()]
+    Watch(WatchCommand),
+
+    /// Show the recorded changes of the config
+    #[command()]
+    History(HistoryCommand),
+
+    /// Revert the last change of the config and its symlinks
+    #[command()]
+    Undo(UndoCommand),
+
+    /// Apply an undone change again
+    #[command()]
+    Redo(RedoCommand),
+
+    /// Write groups to a manifest that can be imported elsewhere
+    #[command()]
+    Export(ExportCommand),
+
+    /// Recreate the groups of a manifest
+    #[command()]
+    Import(ImportCommand),
+
+    /// Make the groups match a manifest, adding and removing executables
+    #[command()]
+    Apply(ApplyCommand),
+
+    /// Create a group for every version installed by another version manager
+    #[command()]
+    ImportFrom(ImportFromCommand),
+
+    /// Show the groups selected by version files such as `.nvmrc`
+    #[command()]
+    Versions(VersionsCommand),
+
+    /// Print shell code that runs `x auto` on every directory change
+    #[command()]
+    Hook(HookCommand),
+
+    /// Link the groups selected by the version files here into a directory
+    /// of their own and print it, for the hook to put first on PATH
+    #[command()]
+    Auto(AutoCommand),
+
+    /// Let the project config here add and override executables for
+    /// `x run`, `x list`, `x info` and `x search`
+    #[command()]
+    Trust(TrustCommand),
 }
 
 #[derive(Parser)]
@@ -64,8 +169,9 @@ pub struct RunCommand {
     /// The name of group
     #[arg(short = 'g', long = "group")]
     pub group: Option<String>,
-    /// The arguments to pass to the program
-    #[arg(action=ArgAction::Append, allow_negative_numbers = true, required = true, allow_hyphen_values = true)]
+    /// The program and the arguments to pass to it; without them a picker
+    /// is shown
+    #[arg(action=ArgAction::Append, allow_negative_numbers = true, allow_hyphen_values = true)]
     pub args: Option<Vec<String>>,
 }
 
@@ -81,6 +187,46 @@ pub struct AddCommand {
     /// alias for the executable
     #[arg(short = 'n', long = "name")]
     pub name: Option<String>,
+
+    /// What to do when a name is already taken in the group
+    #[arg(long = "on-conflict", value_enum, default_value_t = OnConflict::Error)]
+    pub on_conflict: OnConflict,
+
+    /// Only add files matching this glob (or `re:<regex>`), can be repeated
+    #[arg(long = "include")]
+    pub include: Vec<String>,
+
+    /// Skip files matching this glob (or `re:<regex>`), can be repeated
+    #[arg(long = "exclude")]
+    pub exclude: Vec<String>,
+
+    /// Name template for the executables of a directory, e.g. `{stem}-18`
+    #[arg(long = "rename")]
+    pub rename: Option<String>,
+
+    /// Prepend this to the name of every executable of a directory
+    #[arg(long = "prefix")]
+    pub prefix: Option<String>,
+
+    /// Append this to the name of every executable of a directory
+    #[arg(long = "suffix")]
+    pub suffix: Option<String>,
+
+    /// Drop file extensions from the names, e.g. `tool.py` becomes `tool`
+    #[arg(long = "strip-ext", action = ArgAction::SetTrue)]
+    pub strip_ext: bool,
+
+    /// Also add executables from subdirectories of a directory
+    #[arg(short = 'r', long = "recursive", action = ArgAction::SetTrue)]
+    pub recursive: bool,
+
+    /// Scan at most this many directory levels, 1 being the directory itself
+    #[arg(long = "max-depth")]
+    pub max_depth: Option<usize>,
+
+    /// Descend into symlinked directories when scanning recursively
+    #[arg(long = "follow-symlinks", action = ArgAction::SetTrue)]
+    pub follow_symlinks: bool,
 }
 
 #[derive(Parser)]
@@ -88,6 +234,18 @@ pub struct ListCommand {
     /// Show all groups
     #[arg(short = 'a', long = "all", action=ArgAction::SetTrue)]
     pub all: bool,
+    /// Show which config each group and executable comes from
+    #[arg(long = "origin", action=ArgAction::SetTrue)]
+    pub origin: bool,
+    /// How to order the executables
+    #[arg(short = 's', long = "sort", value_enum, default_value_t = SortOrder::Name)]
+    pub sort: SortOrder,
+    /// Extra columns to show, separated by commas
+    #[arg(short = 'c', long = "columns", value_enum, value_delimiter = ',')]
+    pub columns: Vec<Column>,
+    /// Group the executables by the directory they were added from
+    #[arg(short = 't', long = "tree", action=ArgAction::SetTrue)]
+    pub tree: bool,
 }
 
 #[derive(Parser)]
@@ -95,6 +253,9 @@ pub struct InitCommand {
     /// Force re-initialize, overwriting existing config file
     #[arg(short='f', long="force", action=ArgAction::SetTrue)]
     pub force: bool,
+    /// Directory for the symlinks instead of the default one
+    #[arg(long = "bin-dir")]
+    pub bin_dir: Option<PathBuf>,
 }
 
 #[derive(Parser)]
@@ -112,8 +273,19 @@ pub struct RmCommand {
 
 #[derive(Parser)]
 pub struct SwitchCommand {
-    /// The name of group
-    pub group: Option<String>,
+    /// The names of the groups to activate, the first one has the highest
+    /// priority; without them a picker is shown
+    pub groups: Vec<String>,
+    /// Keep the active groups and activate these with a lower priority
+    #[arg(short = 'a', long = "add", action = ArgAction::SetTrue, conflicts_with = "remove")]
+    pub add: bool,
+    /// Deactivate these groups and keep the others active
+    #[arg(short = 'r', long = "remove", action = ArgAction::SetTrue)]
+    pub remove: bool,
+    /// Show the symlinks the switch would change, without switching; needs
+    /// the groups, as the picker would switch
+    #[arg(short = 'n', long = "dry-run", action = ArgAction::SetTrue, requires = "groups")]
+    pub dry_run: bool,
 }
 
 #[derive(Parser)]
@@ -156,6 +328,436 @@ pub struct DisableCommand {
 
 #[derive(Parser)]
 pub struct SearchCommand {
-    /// Search query (matches against executable name or path)
-    pub query: String,
+    /// Search query: fuzzy terms matched against the executable name or
+    /// path, `name:`, `path:` or `group:` to match one field only
+    pub query: Vec<String>,
+    /// Treat the terms as regular expressions
+    #[arg(short = 'r', long = "regex")]
+    pub regex: bool,
+    /// Only show enabled executables
+    #[arg(long = "enabled", conflicts_with = "disabled")]
+    pub enabled: bool,
+    /// Only show disabled executables
+    #[arg(long = "disabled")]
+    pub disabled: bool,
+    /// Only show executables whose path no longer exists
+    #[arg(long = "missing")]
+    pub missing: bool,
+    /// How to order the results
+    #[arg(short = 's', long = "sort", value_enum, default_value_t = SortBy::Score)]
+    pub sort: SortBy,
+    /// Show at most this many results
+    #[arg(short = 'n', long = "limit")]
+    pub limit: Option<usize>,
+}
+
+#[derive(Parser)]
+pub struct ExtendCommand {
+    /// The name of the group that inherits, created if missing
+    pub group: String,
+    /// The groups to inherit from, later ones take precedence; none clears
+    /// the inheritance
+    pub parents: Vec<String>,
+    /// An inherited executable to leave out, can be repeated
+    #[arg(short = 'x', long = "exclude")]
+    pub exclude: Vec<String>,
+}
+
+#[derive(Parser)]
+pub struct SyncCommand {
+    /// The name of group, defaults to the active group
+    #[arg(short = 'g', long = "group", conflicts_with = "all")]
+    pub group: Option<String>,
+    /// Sync every group
+    #[arg(short = 'a', long = "all", action = ArgAction::SetTrue)]
+    pub all: bool,
+    /// What to do when a new executable's name is already taken
+    #[arg(long = "on-conflict", value_enum, default_value_t = OnConflict::Skip)]
+    pub on_conflict: OnConflict,
+}
+
+#[derive(Parser)]
+pub struct WatchCommand {
+    /// Milliseconds without changes to wait for before syncing
+    #[arg(long = "debounce", default_value_t = 500)]
+    pub debounce: u64,
+}
+
+#[derive(Parser)]
+pub struct HistoryCommand {
+    /// Show at most this many changes, latest first
+    #[arg(short = 'n', long = "limit", default_value_t = 20)]
+    pub limit: usize,
+}
+
+#[derive(Parser)]
+pub struct UndoCommand {
+    /// Number of changes to revert
+    #[arg(default_value_t = 1)]
+    pub steps: usize,
+}
+
+#[derive(Parser)]
+pub struct RedoCommand {
+    /// Number of changes to apply again
+    #[arg(default_value_t = 1)]
+    pub steps: usize,
+}
+
+#[derive(Parser)]
+pub struct ExportCommand {
+    /// The groups to export, defaults to the active group
+    #[arg(conflicts_with = "all")]
+    pub groups: Vec<String>,
+    /// Export every group
+    #[arg(short = 'a', long = "all", action = ArgAction::SetTrue)]
+    pub all: bool,
+    /// Write to this file instead of stdout, TOML if it ends in `.toml`
+    #[arg(short = 'o', long = "output")]
+    pub output: Option<PathBuf>,
+    /// Write paths under the home directory as `$HOME/...`
+    #[arg(long = "relative-home", action = ArgAction::SetTrue)]
+    pub relative_home: bool,
+    /// Write paths under PATH as `$NAME/...`, can be repeated
+    #[arg(long = "root", value_name = "NAME=PATH")]
+    pub roots: Vec<String>,
+}
+
+#[derive(Parser)]
+pub struct ImportCommand {
+    /// The manifest, TOML if it ends in `.toml` and JSON otherwise
+    pub file: PathBuf,
+    /// Value of a `$NAME` used in the manifest, instead of the environment
+    #[arg(long = "root", value_name = "NAME=PATH")]
+    pub roots: Vec<String>,
+    /// What to do when a name is already taken in a group
+    #[arg(long = "on-conflict", value_enum, default_value_t = OnConflict::Error)]
+    pub on_conflict: OnConflict,
+}
+
+#[derive(Parser)]
+pub struct ApplyCommand {
+    /// The manifest, TOML if it ends in `.toml` and JSON otherwise
+    pub file: PathBuf,
+    /// Only print the changes
+    #[arg(long = "plan", action = ArgAction::SetTrue)]
+    pub plan: bool,
+    /// Also delete the groups that are not in the manifest
+    #[arg(long = "prune", action = ArgAction::SetTrue)]
+    pub prune: bool,
+    /// Value of a `$NAME` used in the manifest, instead of the environment
+    #[arg(long = "root", value_name = "NAME=PATH")]
+    pub roots: Vec<String>,
+}
+
+#[derive(Parser)]
+pub struct ImportFromCommand {
+    /// The version manager whose installs are imported
+    #[arg(value_enum)]
+    pub manager: Manager,
+    /// Where the manager keeps its installs, instead of its default location
+    #[arg(long = "root")]
+    pub root: Option<PathBuf>,
+    /// What to do when a name is already taken in a group
+    #[arg(long = "on-conflict", value_enum, default_value_t = OnConflict::Error)]
+    pub on_conflict: OnConflict,
+}
+
+#[derive(Parser)]
+pub struct VersionsCommand {
+    /// Name the groups of a tool after PATTERN, using {tool}, {version},
+    /// {major} and {minor}; `*` sets the pattern of every other tool
+    #[arg(long = "set-pattern", value_name = "TOOL=PATTERN")]
+    pub set_patterns: Vec<String>,
+    /// Go back to the default pattern `{tool}-{version}` for a tool
+    #[arg(long = "unset-pattern", value_name = "TOOL")]
+    pub unset_patterns: Vec<String>,
+}
+
+#[derive(Parser)]
+pub struct HookCommand {
+    /// The shell to write the hook for, defaults to the current shell
+    #[arg(value_parser = ["bash", "zsh", "fish"])]
+    pub shell: Option<String>,
+}
+
+#[derive(Parser)]
+pub struct AutoCommand {
+    /// Do not report when the selected groups change
+    #[arg(short = 'q', long = "quiet", action = ArgAction::SetTrue)]
+    pub quiet: bool,
+}
+
+#[derive(Parser)]
+pub struct TrustCommand {
+    /// Stop trusting the project config
+    #[arg(long = "revoke", action = ArgAction::SetTrue)]
+    pub revoke: bool,
+}
+
+#[derive(Parser)]
+pub struct DiffCommand {
+    /// The group switched from
+    pub from: String,
+    /// The group switched to
+    pub to: String,
+    /// Run changed executables with --version to compare their versions
+    #[arg(short = 'V', long = "versions", action = ArgAction::SetTrue)]
+    pub versions: bool,
+}
+
+#[derive(Parser)]
+pub struct DoctorCommand {}
+
+#[derive(Parser)]
+pub struct GroupCommand {
+    #[command(subcommand)]
+    pub action: GroupAction,
+}
+
+#[derive(Subcommand)]
+pub enum GroupAction {
+    /// Create an empty group
+    #[command()]
+    New {
+        /// The name of the group
+        name: String,
+        /// What the group is for
+        #[arg(short = 'd', long = "description")]
+        description: Option<String>,
+        /// Tags of the group, can be repeated
+        #[arg(short = 't', long = "tag")]
+        tags: Vec<String>,
+    },
+    /// Copy a group into a new one
+    #[command()]
+    Cp {
+        /// The group to copy from
+        from: String,
+        /// The name of the new group
+        to: String,
+    },
+    /// Rename a group, updating the groups extending it
+    #[command()]
+    Mv {
+        /// The group to rename
+        from: String,
+        /// The new name
+        to: String,
+    },
+    /// Delete a group and the symlinks of its executables
+    #[command()]
+    Rm {
+        /// The name of the group
+        name: String,
+        /// Do not ask for confirmation
+        #[arg(short = 'y', long = "yes", action = ArgAction::SetTrue)]
+        yes: bool,
+    },
+    /// Set the description or the tags of a group
+    #[command()]
+    Describe {
+        /// The name of the group
+        name: String,
+        /// The new description, an empty one removes it
+        description: Option<String>,
+        /// Add a tag, can be repeated
+        #[arg(short = 't', long = "tag")]
+        tag: Vec<String>,
+        /// Remove a tag, can be repeated
+        #[arg(long = "untag")]
+        untag: Vec<String>,
+    },
+    /// Show the details of a group
+    #[command()]
+    Show {
+        /// The name or number of the group, the active group if omitted
+        name: Option<String>,
+    },
+}
+
+#[derive(Parser)]
+pub struct ProfileCommand {
+    #[command(subcommand)]
+    pub action: ProfileAction,
+}
+
+#[derive(Subcommand)]
+pub enum ProfileAction {
+    /// List all profiles
+    #[command(visible_alias = "ls")]
+    List,
+    /// Create an empty profile
+    #[command()]
+    Create {
+        /// The name of the profile
+        name: String,
+    },
+    /// Delete a profile and its symlinks
+    #[command(visible_alias = "rm")]
+    Delete {
+        /// The name of the profile
+        name: String,
+        /// Do not ask for confirmation
+        #[arg(short = 'y', long = "yes", action = ArgAction::SetTrue)]
+        yes: bool,
+    },
+    /// Copy a profile into a new one
+    #[command(visible_alias = "cp")]
+    Copy {
+        /// The profile to copy from
+        from: String,
+        /// The name of the new profile
+        to: String,
+    },
+}
+
+pub static AVAILABLE_SUBCOMMANDS: &[&str] = &[
+    "run",
+    "r",
+    "add",
+    "rm",
+    "list",
+    "ls",
+    "init",
+    "s",
+    "switch",
+    "rename",
+    "info",
+    "enable",
+    "disable",
+    "search",
+    "profile",
+    "group",
+    "diff",
+    "doctor",
+    "extend",
+    "sync",
+    "watch",
+    "history",
+    "undo",
+    "redo",
+    "export",
+    "import",
+    "apply",
+    "import-from",
+    "versions",
+    "hook",
+    "auto",
+    "trust",
+    "help",
+];
+
+/// Subcommands added after `x <program>` existed. When one of these names
+/// comes first and an executable of the active groups has it, the
+/// executable is run, as it was before the subcommand was added.
+pub static NEWER_SUBCOMMANDS: &[&str] = &[
+    "profile",
+    "group",
+    "diff",
+    "doctor",
+    "extend",
+    "sync",
+    "watch",
+    "history",
+    "undo",
+    "redo",
+    "export",
+    "import",
+    "apply",
+    "import-from",
+    "versions",
+    "hook",
+    "auto",
+    "trust",
+];
+
+/// Options that may appear before the subcommand and take a value
+static GLOBAL_OPTIONS_WITH_VALUE: &[&str] = &["--profile"];
+
+/// Options that may appear before the subcommand and take no value
+static GLOBAL_FLAGS: &[&str] = &["--system", "--force-links"];
+
+/// The first argument that is not a global option, used to tell a
+/// subcommand apart from a program name
+pub fn first_command_arg(args: &[String]) -> Option<&str> {
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        if GLOBAL_FLAGS.contains(&arg.as_str()) {
+            continue;
+        }
+        if GLOBAL_OPTIONS_WITH_VALUE.contains(&arg.as_str()) {
+            iter.next();
+            continue;
+        }
+        if GLOBAL_OPTIONS_WITH_VALUE
+            .iter()
+            .any(|opt| arg.starts_with(&format!("{}=", opt)))
+        {
+            continue;
+        }
+        return Some(arg);
+    }
+    None
+}
+
+/// Whether `args`, without the name of x itself, are the short form
+/// `x <program> [args...]` of `x run`. Programs named like a subcommand
+/// have to be run with `x run <program>`.
+pub fn is_short_run(args: &[String]) -> bool {
+    first_command_arg(args).is_some_and(|arg| !AVAILABLE_SUBCOMMANDS.contains(&arg))
+}
+
+/// The newer subcommand `args` start with, which an executable of the same
+/// name takes over. A global option written first keeps the subcommand.
+pub fn newer_subcommand(args: &[String]) -> Option<&str> {
+    args.first()
+        .map(String::as_str)
+        .filter(|arg| NEWER_SUBCOMMANDS.contains(arg))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::CommandFactory;
+
+    #[test]
+    fn test_short_run_dispatch() {
+        let args =
+            |line: &str| -> Vec<String> { line.split_whitespace().map(String::from).collect() };
+        assert!(is_short_run(&args("node --version")));
+        assert!(is_short_run(&args("--profile work node")));
+        assert!(is_short_run(&args("--system 3")));
+        assert!(!is_short_run(&args("")));
+        assert!(!is_short_run(&args("--profile=work list")));
+        // newer subcommands give way to executables of the same name
+        // unless a global option comes first
+        assert_eq!(newer_subcommand(&args("diff a b")), Some("diff"));
+        assert_eq!(newer_subcommand(&args("--profile work diff")), None);
+        assert_eq!(newer_subcommand(&args("list")), None);
+        for name in ["diff", "watch", "sync", "history", "apply", "hook", "auto"] {
+            assert!(!is_short_run(&args(&format!("{} a b", name))));
+            let cli = Cli::try_parse_from(args(&format!("x run {} a b", name))).unwrap();
+            let Commands::Run(run) = cli.command else {
+                panic!("x run {} is not a run", name);
+            };
+            assert_eq!(run.args.unwrap(), [name, "a", "b"]);
+        }
+        // every subcommand and alias is known to the dispatch
+        for sub in Cli::command().get_subcommands() {
+            for name in std::iter::once(sub.get_name()).chain(sub.get_all_aliases()) {
+                assert!(AVAILABLE_SUBCOMMANDS.contains(&name), "{}", name);
+            }
+        }
+        for name in NEWER_SUBCOMMANDS {
+            assert!(AVAILABLE_SUBCOMMANDS.contains(name), "{}", name);
+        }
+    }
+
+    #[test]
+    fn test_switch_dry_run_needs_groups() {
+        // without groups the picker would switch for real
+        assert!(Cli::try_parse_from(["x", "switch", "--dry-run"]).is_err());
+        assert!(Cli::try_parse_from(["x", "switch", "-n", "node18"]).is_ok());
+    }
 }
@@ -1,69 +1,222 @@
 use anyhow::{Result, anyhow};
 use colored::*;
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::ffi::{OsStr, OsString};
 use std::fs;
 use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+use crate::archive::sha256_file;
+use crate::encoding;
+use crate::links::{self, LinkChange, Shadowed, SwitchPreview, Transaction};
+use crate::listing::{self, Column, ListOptions, SortOrder, Usage};
+use crate::scan::{ScanRules, SourceDir, is_runnable_file};
+use crate::search::{Hit, SearchOptions};
+
+/// The config file an entry was loaded from
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Origin {
+    /// The system-wide config shared by every user
+    System,
+    /// The config of the current user (or profile)
+    #[default]
+    User,
+    /// The project config found in the working directory or above
+    Project,
+}
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
+impl std::fmt::Display for Origin {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Origin::System => write!(f, "system"),
+            Origin::User => write!(f, "user"),
+            Origin::Project => write!(f, "project"),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
 #[serde(rename_all = "kebab-case")]
 pub struct Bin {
+    /// The name the bin is known by, with bytes that are not UTF-8
+    /// escaped as `\xHH`
     pub name: String,
+    /// The file name of the symlink when `name` is escaped
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        with = "encoding::option_os_string"
+    )]
+    pub raw_name: Option<OsString>,
+    #[serde(with = "encoding::path")]
     pub path: PathBuf,
+    #[serde(default, with = "encoding::option_path")]
     pub source_dir: Option<PathBuf>,
     #[serde(default = "default_enabled")]
     pub enabled: bool,
+    /// Seconds since the Unix epoch when the bin was added, 0 if unknown
+    #[serde(default, skip_serializing_if = "is_zero")]
+    pub added: u64,
+    #[serde(skip)]
+    pub origin: Origin,
 }
 
 fn default_enabled() -> bool {
     true
 }
 
+fn is_zero(n: &u64) -> bool {
+    *n == 0
+}
+
 impl Bin {
-    pub fn install(&self, dir_path: &Path) -> Result<()> {
-        // Don't install if disabled
-        if !self.enabled {
-            return Ok(());
+    pub fn new(name: OsString, path: PathBuf, source_dir: Option<PathBuf>, origin: Origin) -> Self {
+        let (name, raw_name) = encoding::bin_name(name);
+        Bin {
+            name,
+            raw_name,
+            path,
+            source_dir,
+            enabled: true,
+            added: listing::now(),
+            origin,
         }
+    }
 
-        let symlink_path = dir_path.join(&self.name);
-        if symlink_path.exists() {
-            fs::remove_file(&symlink_path)?;
-        }
+    /// The file name of the symlink in the bin dir
+    pub fn link_name(&self) -> &OsStr {
+        self.raw_name
+            .as_deref()
+            .unwrap_or_else(|| OsStr::new(&self.name))
+    }
 
-        #[cfg(unix)]
-        std::os::unix::fs::symlink(&self.path, &symlink_path)?;
-        #[cfg(windows)]
-        std::os::windows::fs::symlink_file(&self.path, &symlink_path)?;
+    /// Append `suffix` to the name, keeping the raw file name in step
+    fn push_name_suffix(&mut self, suffix: &str) {
+        self.name.push_str(suffix);
+        if let Some(raw_name) = self.raw_name.as_mut() {
+            raw_name.push(suffix);
+        }
+    }
 
-        Ok(())
+    /// Link the bin in `dir_path`, replacing only a symlink to the bin
+    /// itself unless `--force-links` is given
+    pub fn install(&self, dir_path: &Path) -> Result<()> {
+        let owned = HashSet::from([self.path.clone()]);
+        Transaction::run(dir_path, &owned, force_mode(), |tx| tx.install(self))
     }
 
+    /// Remove the symlink of the bin, which has to point at the bin
+    /// unless `--force-links` is given
     pub fn uninstall(&self, dir_path: &Path) -> Result<()> {
-        let symlink_path = dir_path.join(&self.name);
-        if symlink_path.exists() {
-            fs::remove_file(&symlink_path)?;
-        }
-        Ok(())
+        let owned = HashSet::from([self.path.clone()]);
+        Transaction::run(dir_path, &owned, force_mode(), |tx| tx.remove(self))
     }
 }
 
-#[derive(Serialize, Deserialize, Debug, Default)]
+/// What `x add` does with a bin whose name is already taken in the group
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum OnConflict {
+    /// Abort without changing anything
+    #[default]
+    Error,
+    /// Keep the existing bin
+    Skip,
+    /// Overwrite the existing bin
+    Replace,
+    /// Add the new bin under a suffixed name, e.g. `node-2`
+    Rename,
+}
+
+#[derive(Debug, Default, Clone)]
+pub struct AddOptions {
+    /// Name for a single executable instead of its file stem
+    pub name: Option<String>,
+    pub on_conflict: OnConflict,
+    /// Filters and naming rules for a directory, kept for `x sync`
+    pub rules: ScanRules,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Resolution {
+    Skipped,
+    Replaced,
+    Renamed(String),
+}
+
+/// A bin that could not be added under its own name
+#[derive(Debug, Clone)]
+pub struct Conflict {
+    pub name: String,
+    pub existing: PathBuf,
+    pub incoming: PathBuf,
+    pub resolution: Resolution,
+}
+
+#[derive(Debug, Default)]
+pub struct AddReport {
+    /// Number of bins written to the group
+    pub added: usize,
+    pub conflicts: Vec<Conflict>,
+}
+
+#[derive(Debug, Default)]
+pub struct SyncReport {
+    pub added: Vec<String>,
+    pub removed: Vec<String>,
+    pub conflicts: Vec<Conflict>,
+    /// Source directories that no longer exist, their bins are kept
+    pub missing_dirs: Vec<PathBuf>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default, Clone)]
 #[serde(rename_all = "kebab-case")]
 pub struct Group {
+    /// Position of the group among the groups of its config in the order
+    /// they were created, which is how `x list --all` numbers them
+    #[serde(default)]
     pub index: usize,
     pub bins: HashMap<String, Bin>,
+    /// Groups whose bins are inherited, later ones override earlier ones
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub extends: Vec<String>,
+    /// Inherited bins that are left out of this group
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub exclude: Vec<String>,
+    /// Directories added to the group with their rules
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub sources: Vec<SourceDir>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
+    /// Seconds since the Unix epoch when the group was created, 0 if
+    /// unknown
+    #[serde(default, skip_serializing_if = "is_zero")]
+    pub created: u64,
+    #[serde(skip)]
+    pub origin: Origin,
+    /// Bins of lower layers replaced by a bin of the same name
+    #[serde(skip)]
+    pub shadowed: Vec<Bin>,
 }
 
 impl Group {
-    pub fn remove_bin_by_name(&mut self, name: &str, bin_dir: &Path) -> Result<()> {
-        if let Some(bin) = self.bins.remove(name) {
-            bin.uninstall(bin_dir)?;
+    pub fn with_origin(origin: Origin) -> Self {
+        Group {
+            origin,
+            ..Default::default()
         }
-        Ok(())
     }
 
-    pub fn remove_bin_by_path(&mut self, path: &PathBuf, bin_dir: &Path) -> Result<()> {
+    pub fn remove_bin_by_name(&mut self, name: &str) -> Option<Bin> {
+        self.bins.remove(name)
+    }
+
+    /// Remove every bin that was added from the directory `path`, along
+    /// with the directory's rules in the writable `layer`
+    pub fn remove_bin_by_path(&mut self, path: &Path, layer: Origin) -> Vec<Bin> {
+        self.sources.retain(|s| s.dir != path || s.origin != layer);
         let to_remove: Vec<_> = self
             .bins
             .iter()
@@ -75,53 +228,209 @@ impl Group {
             })
             .collect();
 
-        for name in to_remove {
-            if let Some(bin) = self.bins.remove(&name) {
-                bin.uninstall(bin_dir)?;
-            }
-        }
-
-        Ok(())
+        to_remove
+            .into_iter()
+            .filter_map(|name| self.bins.remove(&name))
+            .collect()
     }
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 #[serde(rename_all = "kebab-case")]
 pub struct Config {
+    /// The active group with the highest priority, the default target of
+    /// commands
     pub active_group: String,
+    /// Further active groups in decreasing priority; a name is linked from
+    /// the first active group that provides it
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub also_active: Vec<String>,
+    #[serde(with = "encoding::path")]
     pub bin_dir: PathBuf,
     pub groups: HashMap<String, Group>,
+    /// Group name patterns for version files by tool, `*` for any tool
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub version_patterns: BTreeMap<String, String>,
+    /// The layer that `save` writes to, entries from other layers are
+    /// read-only
+    #[serde(skip)]
+    pub layer: Origin,
+    /// The project config merged into this config, if any
+    #[serde(skip)]
+    pub project_config: Option<PathBuf>,
 }
 
 pub static GLOBAL_DEFAULT_GROUP_NAME: &str = "base";
+pub static DEFAULT_PROFILE_NAME: &str = "default";
+
+pub static DEFAULT_SYSTEM_CONFIG_DIR: &str = "/etc/x";
+pub static DEFAULT_SYSTEM_BIN_DIR: &str = "/usr/local/lib/x/bin";
+
+static PROFILE: OnceLock<Option<String>> = OnceLock::new();
+static SYSTEM_MODE: OnceLock<bool> = OnceLock::new();
+static FORCE_MODE: OnceLock<bool> = OnceLock::new();
+
+/// Make every command in this process operate on the system-wide config
+pub fn set_system_mode(system: bool) -> Result<()> {
+    if system && current_profile().is_some() {
+        anyhow::bail!("profiles cannot be used together with the system config");
+    }
+    SYSTEM_MODE
+        .set(system)
+        .map_err(|_| anyhow!("system mode already selected"))
+}
+
+pub fn system_mode() -> bool {
+    SYSTEM_MODE.get().copied().unwrap_or(false)
+}
+
+/// Let every command in this process replace and remove files in the bin
+/// directory that x did not create
+pub fn set_force_mode(force: bool) -> Result<()> {
+    FORCE_MODE
+        .set(force)
+        .map_err(|_| anyhow!("force mode already selected"))
+}
+
+pub fn force_mode() -> bool {
+    FORCE_MODE.get().copied().unwrap_or(false)
+}
+
+/// Directory of the system-wide config, `X_SYSTEM_CONFIG_DIR` overrides
+/// the default `/etc/x`
+pub fn system_config_dir() -> PathBuf {
+    std::env::var_os("X_SYSTEM_CONFIG_DIR")
+        .map(PathBuf::from)
+        .unwrap_or_else(|| PathBuf::from(DEFAULT_SYSTEM_CONFIG_DIR))
+}
+
+pub fn system_config_path() -> PathBuf {
+    system_config_dir().join("config.json")
+}
+
+/// Select the profile used by every path lookup in this process.
+/// `None` (or "default") selects the default profile.
+pub fn set_profile(profile: Option<String>) -> Result<()> {
+    let profile = profile.filter(|p| p != DEFAULT_PROFILE_NAME);
+    if let Some(p) = &profile {
+        validate_profile_name(p)?;
+    }
+    PROFILE
+        .set(profile)
+        .map_err(|_| anyhow!("profile already selected"))
+}
+
+/// The selected profile, `None` for the default one
+pub fn current_profile() -> Option<&'static str> {
+    PROFILE.get().and_then(|p| p.as_deref())
+}
+
+pub fn validate_profile_name(name: &str) -> Result<()> {
+    if name.is_empty()
+        || name.starts_with('.')
+        || !name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'))
+    {
+        anyhow::bail!(
+            "invalid profile name {}, only letters, digits, '-', '_' and '.' are allowed",
+            name
+        );
+    }
+    Ok(())
+}
+
+fn home_dir() -> Result<PathBuf> {
+    dirs::home_dir().ok_or_else(|| anyhow!("cannot get home dir"))
+}
+
+pub fn profiles_dir_in(home_dir: &Path) -> PathBuf {
+    home_dir.join(".config").join("x").join("profiles")
+}
+
+pub fn config_dir_in(home_dir: &Path, profile: Option<&str>) -> PathBuf {
+    match profile {
+        Some(p) => profiles_dir_in(home_dir).join(p),
+        None => home_dir.join(".config").join("x"),
+    }
+}
+
+pub fn bin_dir_in(home_dir: &Path, profile: Option<&str>) -> PathBuf {
+    let bin_root = home_dir.join(".local").join("bin");
+    match profile {
+        Some(p) => bin_root.join(format!("x-{}", p)),
+        None => bin_root.join("x"),
+    }
+}
+
+/// Where x keeps data that is not configuration, such as the history
+pub fn state_dir_in(home_dir: &Path, profile: Option<&str>) -> PathBuf {
+    let state_root = home_dir.join(".local").join("state").join("x");
+    match profile {
+        Some(p) => state_root.join("profiles").join(p),
+        None => state_root,
+    }
+}
 
 pub fn get_bin_dir() -> Result<PathBuf> {
-    let home_dir = dirs::home_dir().ok_or_else(|| anyhow!("cannot get home dir"))?;
-    let bin_dir = home_dir.join(".local").join("bin").join("x");
+    if system_mode() {
+        let bin_dir = PathBuf::from(DEFAULT_SYSTEM_BIN_DIR);
+        if !bin_dir.exists() {
+            fs::create_dir_all(&bin_dir)?;
+        }
+        return Ok(bin_dir);
+    }
+    get_profile_bin_dir(current_profile())
+}
+
+pub fn get_profile_bin_dir(profile: Option<&str>) -> Result<PathBuf> {
+    let bin_dir = bin_dir_in(&home_dir()?, profile);
     if !bin_dir.exists() {
         fs::create_dir_all(&bin_dir)?;
     }
     Ok(bin_dir)
 }
 
+/// The state directory, which may not exist yet
+pub fn state_dir_path() -> Result<PathBuf> {
+    Ok(if system_mode() {
+        system_config_dir().join("state")
+    } else {
+        state_dir_in(&home_dir()?, current_profile())
+    })
+}
+
+pub fn get_state_dir() -> Result<PathBuf> {
+    let state_dir = state_dir_path()?;
+    if !state_dir.exists() {
+        fs::create_dir_all(&state_dir)?;
+    }
+    Ok(state_dir)
+}
+
 impl Default for Config {
     fn default() -> Self {
         let bin_dir = get_bin_dir();
         if bin_dir.is_err() {
             panic!("cannot get bin dir")
         }
-        let bin_dir = bin_dir.unwrap();
-        Config {
-            active_group: GLOBAL_DEFAULT_GROUP_NAME.to_string(),
-            bin_dir,
-            groups: HashMap::new(),
-        }
+        Config::new(bin_dir.unwrap())
     }
 }
 
 pub fn get_config_path() -> Result<PathBuf> {
-    let home_dir = dirs::home_dir().ok_or_else(|| anyhow!("cannot get home dir"))?;
-    let config_dir = home_dir.join(".config").join("x");
+    if system_mode() {
+        let config_dir = system_config_dir();
+        if !config_dir.exists() {
+            fs::create_dir_all(&config_dir)?;
+        }
+        return Ok(config_dir.join("config.json"));
+    }
+    get_profile_config_path(current_profile())
+}
+
+pub fn get_profile_config_path(profile: Option<&str>) -> Result<PathBuf> {
+    let config_dir = config_dir_in(&home_dir()?, profile);
     if !config_dir.exists() {
         fs::create_dir_all(&config_dir)?;
     }
@@ -129,161 +438,1395 @@ pub fn get_config_path() -> Result<PathBuf> {
 }
 
 impl Config {
+    pub fn new(bin_dir: PathBuf) -> Self {
+        Config {
+            active_group: GLOBAL_DEFAULT_GROUP_NAME.to_string(),
+            also_active: Vec::new(),
+            bin_dir,
+            groups: HashMap::new(),
+            version_patterns: BTreeMap::new(),
+            layer: if system_mode() {
+                Origin::System
+            } else {
+                Origin::User
+            },
+            project_config: None,
+        }
+    }
+
     pub fn load(path: impl AsRef<Path>) -> Result<Self> {
         let bytes = std::fs::read(path)?;
-        Ok(serde_json::from_slice(&bytes)?)
+        let mut conf: Config = serde_json::from_slice(&bytes)?;
+        number_groups(&mut conf.groups);
+        Ok(conf)
     }
 
+    /// Save the entries that belong to `self.layer`
     pub fn save(&self, path: impl AsRef<Path>) -> Result<()> {
-        let bytes = serde_json::to_vec_pretty(self)?;
+        let bytes = serde_json::to_vec_pretty(&self.layer_view())?;
         std::fs::write(path, bytes)?;
         Ok(())
     }
 
+    /// Mark this config and all of its entries as coming from `origin`
+    pub fn set_origin(&mut self, origin: Origin) {
+        self.layer = origin;
+        for g in self.groups.values_mut() {
+            g.origin = origin;
+            for b in g.bins.values_mut() {
+                b.origin = origin;
+            }
+            for source in g.sources.iter_mut() {
+                source.origin = origin;
+            }
+        }
+    }
+
+    /// Merge the groups of a higher priority layer into this config,
+    /// bins of `upper` replace bins of the same name
+    pub fn overlay_groups(&mut self, upper: HashMap<String, Group>) {
+        for (gn, upper_group) in upper {
+            match self.groups.get_mut(&gn) {
+                Some(g) => {
+                    if !upper_group.extends.is_empty() {
+                        g.extends = upper_group.extends;
+                    }
+                    if !upper_group.exclude.is_empty() {
+                        g.exclude = upper_group.exclude;
+                    }
+                    if upper_group.description.is_some() {
+                        g.description = upper_group.description;
+                    }
+                    if !upper_group.tags.is_empty() {
+                        g.tags = upper_group.tags;
+                    }
+                    g.sources.extend(upper_group.sources);
+                    for (bn, b) in upper_group.bins {
+                        if let Some(lower) = g.bins.insert(bn, b) {
+                            g.shadowed.push(lower);
+                        }
+                    }
+                }
+                None => {
+                    self.groups.insert(gn, upper_group);
+                }
+            }
+        }
+    }
+
+    /// A copy of the config holding only the entries of `self.layer`
+    fn layer_view(&self) -> Config {
+        let mut view = Config::new(self.bin_dir.clone());
+        view.active_group = self.active_group.clone();
+        view.also_active = self.also_active.clone();
+        view.version_patterns = self.version_patterns.clone();
+        view.layer = self.layer;
+        for (gn, g) in &self.groups {
+            let mut bins: HashMap<String, Bin> = g
+                .bins
+                .iter()
+                .filter(|(_, b)| b.origin == self.layer)
+                .map(|(bn, b)| (bn.clone(), b.clone()))
+                .collect();
+            // keep what a higher layer hides, it is still part of this layer
+            for b in g.shadowed.iter().filter(|b| b.origin == self.layer) {
+                bins.entry(b.name.clone()).or_insert_with(|| b.clone());
+            }
+            let sources: Vec<SourceDir> = g
+                .sources
+                .iter()
+                .filter(|s| s.origin == self.layer)
+                .cloned()
+                .collect();
+            if g.origin != self.layer && bins.is_empty() && sources.is_empty() {
+                continue;
+            }
+            let mut group = g.clone();
+            group.bins = bins;
+            group.sources = sources;
+            view.groups.insert(gn.clone(), group);
+        }
+        view
+    }
+
+    /// Fail if a bin comes from a layer this config cannot write to
+    fn ensure_writable(&self, group: &str, bin: &Bin) -> Result<()> {
+        if bin.origin != self.layer {
+            anyhow::bail!(
+                "executable {} in group {} comes from the {} config and cannot be changed here",
+                bin.name,
+                group,
+                bin.origin
+            );
+        }
+        Ok(())
+    }
+
     pub fn add(
         &mut self,
         group: impl Into<String>,
         path: impl AsRef<Path>,
         name: Option<String>,
     ) -> Result<usize> {
-        let path = path.as_ref();
+        let options = AddOptions {
+            name,
+            ..Default::default()
+        };
+        Ok(self.add_with_options(group, path, &options)?.added)
+    }
+
+    pub fn add_with_options(
+        &mut self,
+        group: impl Into<String>,
+        path: impl AsRef<Path>,
+        options: &AddOptions,
+    ) -> Result<AddReport> {
         let group_name = group.into();
+        let (bins, source) = self.collect_bins(path.as_ref(), options)?;
+        self.transaction(|conf| {
+            let (planned, report) = conf.plan_bins(&group_name, bins, options.on_conflict)?;
+            let layer = conf.layer;
+            let g = conf.apply_bins(group_name, planned);
+            if let Some(source) = source {
+                // adding a directory again replaces its rules
+                g.sources
+                    .retain(|s| s.origin != layer || s.dir != source.dir);
+                g.sources.push(source);
+            }
+            Ok(report)
+        })
+    }
 
+    /// The bins `add` would add for `path` without changing anything, and
+    /// the source directory to record when `path` is a directory
+    pub fn collect_bins(
+        &self,
+        path: &Path,
+        options: &AddOptions,
+    ) -> Result<(Vec<Bin>, Option<SourceDir>)> {
         if path.is_file() && is_runnable_file(path) {
-            let bin_name = if let Some(name) = name {
-                name
-            } else {
-                executable_name(path)?
+            if !options.rules.is_empty() {
+                anyhow::bail!("filters and naming rules only apply to directories");
+            }
+            let bin_name = match options.name.clone() {
+                Some(name) => name.into(),
+                None => path
+                    .file_stem()
+                    .ok_or_else(|| anyhow!("cannot extract file stem"))?
+                    .to_os_string(),
             };
-            let bin = Bin {
-                name: bin_name.clone(),
-                path: path.to_path_buf().canonicalize().unwrap(),
-                source_dir: None,
-                enabled: true,
+            let bin = Bin::new(bin_name, path.canonicalize()?, None, self.layer);
+            return Ok((vec![bin], None));
+        }
+
+        if path.is_dir() {
+            let source = SourceDir::new(path.canonicalize()?, options.rules.clone(), self.layer);
+            let bins = source
+                .scan()?
+                .into_iter()
+                .map(|(name, file_path)| {
+                    Bin::new(name, file_path, Some(source.dir.clone()), self.layer)
+                })
+                .collect();
+            return Ok((bins, Some(source)));
+        }
+
+        anyhow::bail!("path is neither an executable/script file nor a directory")
+    }
+
+    /// Decide which of `bins` are added to a group, settling names that
+    /// are already taken according to `policy`. Fails if the policy is
+    /// `OnConflict::Error` and any name is taken.
+    fn plan_bins(
+        &self,
+        group: &str,
+        bins: Vec<Bin>,
+        policy: OnConflict,
+    ) -> Result<(Vec<Bin>, AddReport)> {
+        let existing = self.groups.get(group).map(|g| &g.bins);
+        // path of every name in the group once the bins planned so far
+        // are added
+        let mut taken: HashMap<String, PathBuf> = existing
+            .into_iter()
+            .flatten()
+            .map(|(bn, b)| (bn.clone(), b.path.clone()))
+            .collect();
+
+        let mut report = AddReport::default();
+        let mut planned = Vec::new();
+        for mut bin in bins {
+            let Some(existing_path) = taken.get(&bin.name) else {
+                taken.insert(bin.name.clone(), bin.path.clone());
+                planned.push(bin);
+                continue;
             };
+            // adding the same file again is a refresh, not a conflict
+            if same_target(existing_path, &bin.path) {
+                planned.push(bin);
+                continue;
+            }
 
-            if self.active_group == group_name {
-                bin.install(&self.bin_dir)?;
+            let mut conflict = Conflict {
+                name: bin.name.clone(),
+                existing: existing_path.clone(),
+                incoming: bin.path.clone(),
+                resolution: Resolution::Skipped,
+            };
+            match policy {
+                OnConflict::Error | OnConflict::Skip => {}
+                OnConflict::Replace => {
+                    conflict.resolution = Resolution::Replaced;
+                    taken.insert(bin.name.clone(), bin.path.clone());
+                    planned.push(bin);
+                }
+                OnConflict::Rename => {
+                    // renamed by an earlier add of the same file
+                    if let Some((renamed, _)) = taken
+                        .iter()
+                        .find(|(bn, p)| is_name_for(bn, &bin.name) && same_target(p, &bin.path))
+                    {
+                        let suffix = renamed[bin.name.len()..].to_string();
+                        bin.push_name_suffix(&suffix);
+                        planned.push(bin);
+                        continue;
+                    }
+                    let suffix = (2..)
+                        .map(|n| format!("-{}", n))
+                        .find(|suffix| !taken.contains_key(&format!("{}{}", bin.name, suffix)))
+                        .unwrap();
+                    bin.push_name_suffix(&suffix);
+                    conflict.resolution = Resolution::Renamed(bin.name.clone());
+                    taken.insert(bin.name.clone(), bin.path.clone());
+                    planned.push(bin);
+                }
             }
+            report.conflicts.push(conflict);
+        }
 
-            let g = self.groups.entry(group_name).or_default();
-            g.bins.insert(bin_name, bin);
-            g.index = g.bins.len() - 1;
-            return Ok(1);
+        if policy == OnConflict::Error && !report.conflicts.is_empty() {
+            let details: Vec<String> = report
+                .conflicts
+                .iter()
+                .map(|c| {
+                    format!(
+                        "  {}: {} is already {}",
+                        c.name,
+                        c.incoming.display(),
+                        c.existing.display()
+                    )
+                })
+                .collect();
+            anyhow::bail!(
+                "{} name conflict(s) in group {}, use --on-conflict=skip|replace|rename\n{}",
+                report.conflicts.len(),
+                group,
+                details.join("\n")
+            );
         }
 
-        if path.is_dir() {
-            let bins = collect_executables_from_dir(path)?;
-            let nbins = bins.len();
-            let g = self.groups.entry(group_name.clone()).or_default();
-            for (name, file_path) in bins {
-                let bin = Bin {
-                    name: name.clone(),
-                    path: file_path,
-                    source_dir: Some(path.to_path_buf().canonicalize().unwrap()),
-                    enabled: true,
-                };
-                if self.active_group == group_name {
-                    bin.install(&self.bin_dir)?;
-                }
-                g.bins.insert(name, bin);
+        report.added = planned.len();
+        Ok((planned, report))
+    }
+
+    /// Write planned bins to a group, the caller relinks
+    fn apply_bins(&mut self, group: String, planned: Vec<Bin>) -> &mut Group {
+        let g = self.group_entry(group);
+        for mut bin in planned {
+            // a refresh keeps the bin disabled and its place
+            if let Some(old) = g.bins.get(&bin.name)
+                && same_target(&old.path, &bin.path)
+            {
+                bin.enabled = old.enabled;
+                bin.added = old.added;
             }
-            g.index = g.bins.len().saturating_sub(1);
-            return Ok(nbins);
+            g.bins.insert(bin.name.clone(), bin);
         }
+        g
+    }
 
-        anyhow::bail!("path is neither an executable/script file nor a directory")
+    /// The source directories of a group in the writable layer, including
+    /// directories added before their rules were recorded
+    fn sources_of(&self, group: &str) -> Result<Vec<SourceDir>> {
+        let g = self
+            .groups
+            .get(group)
+            .ok_or_else(|| anyhow!("group {} does not exist", group))?;
+        let mut sources: Vec<SourceDir> = g
+            .sources
+            .iter()
+            .filter(|s| s.origin == self.layer)
+            .cloned()
+            .collect();
+        let mut legacy: Vec<&PathBuf> = g
+            .bins
+            .values()
+            .filter(|b| b.origin == self.layer)
+            .filter_map(|b| b.source_dir.as_ref())
+            .filter(|dir| !sources.iter().any(|s| s.dir == **dir))
+            .collect();
+        legacy.sort();
+        legacy.dedup();
+        sources.extend(
+            legacy
+                .into_iter()
+                .map(|dir| SourceDir::new(dir.clone(), ScanRules::default(), self.layer)),
+        );
+        Ok(sources)
     }
 
-    pub fn pretty_print(&self, group: Option<&str>) {
-        let mut groups: Vec<(&String, &Group)> = self.groups.iter().collect();
-        groups.sort_by(|a, b| a.0.cmp(b.0));
+    /// Every source directory of the writable layer with the group it
+    /// belongs to, sorted by group
+    pub fn source_dirs(&self) -> Vec<(String, SourceDir)> {
+        let mut names: Vec<&String> = self.groups.keys().collect();
+        names.sort();
+        names
+            .into_iter()
+            .flat_map(|gn| {
+                self.sources_of(gn)
+                    .unwrap_or_default()
+                    .into_iter()
+                    .map(move |s| (gn.clone(), s))
+            })
+            .collect()
+    }
 
-        for (gn, g) in groups {
-            if let Some(filter) = group
-                && filter != gn
+    /// Scan the source directories of a group again with their rules,
+    /// adding new executables and dropping the ones that are gone or
+    /// filtered out
+    pub fn sync(&mut self, group: &str, policy: OnConflict) -> Result<SyncReport> {
+        let layer = self.layer;
+        let sources = self.sources_of(group)?;
+        // record directories added before rules were kept
+        let g = self.groups.get_mut(group).unwrap();
+        for source in &sources {
+            if !g
+                .sources
+                .iter()
+                .any(|s| s.origin == layer && s.dir == source.dir)
             {
+                g.sources.push(source.clone());
+            }
+        }
+
+        let before = self.linked_bins()?;
+        let mut report = SyncReport::default();
+        let mut incoming = Vec::new();
+        for source in &sources {
+            if !source.dir.is_dir() {
+                report.missing_dirs.push(source.dir.clone());
                 continue;
             }
+            let candidates: Vec<Bin> = source
+                .scan()?
+                .into_iter()
+                .map(|(name, path)| Bin::new(name, path, Some(source.dir.clone()), layer))
+                .collect();
+            let g = self.groups.get_mut(group).unwrap();
+            let mut current: Vec<String> = g
+                .bins
+                .values()
+                .filter(|b| b.origin == layer && b.source_dir.as_ref() == Some(&source.dir))
+                .map(|b| b.name.clone())
+                .collect();
+            current.sort();
+            for bn in current {
+                let path = g.bins[&bn].path.clone();
+                let kept = candidates
+                    .iter()
+                    .any(|c| is_name_for(&bn, &c.name) && same_target(&c.path, &path));
+                if !kept {
+                    g.bins.remove(&bn);
+                    report.removed.push(bn);
+                }
+            }
+            for candidate in candidates {
+                if g.bins.values().any(|b| {
+                    b.origin == layer
+                        && is_name_for(&b.name, &candidate.name)
+                        && same_target(&b.path, &candidate.path)
+                }) {
+                    continue;
+                }
+                incoming.push(candidate);
+            }
+        }
+
+        let (planned, add_report) = self.plan_bins(group, incoming, policy)?;
+        report.conflicts = add_report.conflicts;
+        report.added = planned.iter().map(|b| b.name.clone()).collect();
+        // a renamed bin is removed and added under its new name
+        report.removed.retain(|bn| !report.added.contains(bn));
+        self.apply_bins(group.to_string(), planned);
+        self.relink(&before)?;
+        Ok(report)
+    }
+
+    /// Replace the writable layer's part of each of `groups` with its
+    /// bins, sources, `extends` and `exclude`, creating missing groups,
+    /// then delete the groups in `removed` and activate `active` unless it
+    /// is empty. Nothing is relinked, see `relink_from`.
+    pub fn replace_groups(
+        &mut self,
+        groups: Vec<(String, Group)>,
+        removed: &[String],
+        active: &[String],
+    ) -> Result<()> {
+        let layer = self.layer;
+        let names: Vec<String> = groups.iter().map(|(gn, _)| gn.clone()).collect();
+        for (gn, new) in groups {
+            let g = self.group_entry(gn.clone());
+            if g.origin != layer && (g.extends != new.extends || g.exclude != new.exclude) {
+                anyhow::bail!(
+                    "group {} comes from the {} config and cannot be changed here",
+                    gn,
+                    g.origin
+                );
+            }
+            g.extends = new.extends;
+            g.exclude = new.exclude;
+            g.sources.retain(|s| s.origin != layer);
+            g.sources.extend(new.sources);
+
+            // drop the bins of this layer, bringing back the bins of lower
+            // layers they hid
+            let added: HashMap<(String, PathBuf), u64> = g
+                .bins
+                .values()
+                .chain(g.shadowed.iter())
+                .filter(|b| b.origin == layer)
+                .map(|b| ((b.name.clone(), b.path.clone()), b.added))
+                .collect();
+            g.bins.retain(|_, b| b.origin != layer);
+            for b in std::mem::take(&mut g.shadowed) {
+                if b.origin == layer {
+                    continue;
+                }
+                if g.bins.contains_key(&b.name) {
+                    g.shadowed.push(b);
+                } else {
+                    g.bins.insert(b.name.clone(), b);
+                }
+            }
+            for mut b in new.bins.into_values() {
+                if let Some(added) = added.get(&(b.name.clone(), b.path.clone())) {
+                    b.added = *added;
+                }
+                match g.bins.get(&b.name) {
+                    Some(higher) if higher.origin > layer => g.shadowed.push(b),
+                    _ => {
+                        if let Some(lower) = g.bins.insert(b.name.clone(), b) {
+                            g.shadowed.push(lower);
+                        }
+                    }
+                }
+            }
+        }
+
+        for gn in removed {
+            match self.groups.get(gn) {
+                Some(g) if g.origin != layer => anyhow::bail!(
+                    "group {} comes from the {} config and cannot be deleted here",
+                    gn,
+                    g.origin
+                ),
+                Some(_) => {
+                    self.groups.remove(gn);
+                    self.also_active.retain(|a| a != gn);
+                }
+                None => {}
+            }
+        }
+        for gn in &names {
+            self.resolve(gn)?;
+        }
+
+        if let Some((primary, rest)) = active.split_first() {
+            for gn in active {
+                if !self.group_exists(gn) {
+                    anyhow::bail!("group {} does not exist", gn);
+                }
+                self.resolve(gn)?;
+            }
+            self.active_group = primary.clone();
+            self.also_active = rest.to_vec();
+        }
+        Ok(())
+    }
 
-            if gn == &self.active_group {
-                println!("{} {}", "*".green().bold(), gn.cyan().bold());
+    /// Print the given groups in order, or every group sorted by name.
+    /// The executables of the given groups are numbered on from one group
+    /// to the next, those of every group from 1 in each group.
+    pub fn pretty_print(&self, groups: Option<&[String]>, options: &ListOptions) {
+        let continuous = groups.is_some();
+        let mut next_number = 1;
+        let groups: Vec<(&String, &Group)> = match groups {
+            Some(names) => names
+                .iter()
+                .filter_map(|gn| self.groups.get_key_value(gn))
+                .collect(),
+            None => self.ordered_groups(),
+        };
+        let numbers: HashMap<&String, usize> = self
+            .ordered_groups()
+            .into_iter()
+            .zip(1..)
+            .map(|((gn, _), number)| (gn, number))
+            .collect();
+        let active_groups = self.active_groups();
+        let winners = self.active_bins().unwrap_or_default();
+        let usage = Usage::load();
+        let origin_tag = |origin: Origin| {
+            if options.origin {
+                format!(" ({})", origin).dimmed().to_string()
             } else {
-                println!("  {}", gn.cyan());
+                String::new()
             }
-            let mut count = 1;
-            for (bn, b) in &g.bins {
-                let status = if b.enabled { "" } else { " [disabled]" };
+        };
+
+        for (gn, g) in groups {
+            let mut details = if g.extends.is_empty() {
+                String::new()
+            } else {
+                format!(" [extends: {}]", g.extends.join(", "))
+                    .dimmed()
+                    .to_string()
+            };
+            if !g.tags.is_empty() {
+                details.push_str(
+                    &format!(" [tags: {}]", g.tags.join(", "))
+                        .dimmed()
+                        .to_string(),
+                );
+            }
+            if let Some(description) = &g.description {
+                details.push_str(&format!(" - {}", description).dimmed().to_string());
+            }
+            let priority = active_groups.iter().position(|a| a == gn);
+            if let Some(priority) = priority {
+                let priority = if active_groups.len() > 1 {
+                    format!(" [priority {}]", priority + 1).dimmed().to_string()
+                } else {
+                    String::new()
+                };
+                println!(
+                    "{} {}. {}{}{}{}",
+                    "*".green().bold(),
+                    numbers[gn],
+                    gn.cyan().bold(),
+                    priority,
+                    details,
+                    origin_tag(g.origin)
+                );
+            } else {
                 println!(
-                    "  {:2}. {} -> {}{}",
-                    count,
-                    bn.color(Color::Green),
-                    b.path.display().to_string().color(Color::Green),
-                    status.color(Color::Red),
+                    "  {}. {}{}{}",
+                    numbers[gn],
+                    gn.cyan(),
+                    details,
+                    origin_tag(g.origin)
                 );
-                count += 1;
             }
+
+            if let Err(e) = self.resolve(gn) {
+                println!("  {}", format!("error: {}", e).red());
+            }
+            let bins = self.listed_bins(gn, options.sort, &usage);
+            // numbered by name whatever the order, as `bin_ref` counts them
+            let positions: HashMap<String, usize> = self
+                .listed_bins(gn, SortOrder::Name, &usage)
+                .into_iter()
+                .enumerate()
+                .map(|(i, (bn, _))| (bn, i))
+                .collect();
+            let versions = if options.columns.contains(&Column::Version) {
+                listing::probe_versions(bins.iter().map(|(_, (_, b))| b.path.as_path()))
+            } else {
+                HashMap::new()
+            };
+            let first = next_number;
+            if continuous {
+                next_number += bins.len();
+            }
+
+            struct Row<'a> {
+                number: usize,
+                name: &'a str,
+                bin: &'a Bin,
+                cells: Vec<String>,
+                suffix: String,
+            }
+            let rows: Vec<Row> = bins
+                .iter()
+                .map(|(bn, (provider, b))| {
+                    let number = first + positions[bn];
+                    let mut suffix = String::new();
+                    if !b.enabled {
+                        suffix.push_str(&" [disabled]".red().to_string());
+                    }
+                    if provider != gn {
+                        suffix.push_str(&format!(" (from {})", provider).dimmed().to_string());
+                    }
+                    match winners.get(bn) {
+                        Some((winner, _)) if priority.is_some() && winner != gn => suffix
+                            .push_str(&format!(" (shadowed by {})", winner).yellow().to_string()),
+                        _ => {}
+                    }
+                    suffix.push_str(&origin_tag(b.origin));
+                    Row {
+                        number,
+                        name: bn,
+                        bin: b,
+                        cells: options
+                            .columns
+                            .iter()
+                            .map(|c| listing::cell(*c, b, &versions))
+                            .collect(),
+                        suffix,
+                    }
+                })
+                .collect();
+
+            let width = |f: &dyn Fn(&Row) -> usize| rows.iter().map(f).max().unwrap_or(0);
+            let number_width = width(&|r| r.number.to_string().len()).max(2);
+            let name_width = width(&|r| r.name.chars().count());
+            let cell_widths: Vec<usize> = (0..options.columns.len())
+                .map(|i| width(&|r| r.cells[i].chars().count()))
+                .collect();
+            let mut path_width = width(&|r| r.bin.path.display().to_string().chars().count());
+            if let Some(total) = options.width {
+                let indent = if options.tree { 4 } else { 2 };
+                let fixed = indent
+                    + number_width
+                    + 2
+                    + name_width
+                    + 4
+                    + cell_widths.iter().map(|w| w + 2).sum::<usize>();
+                path_width = path_width.min(total.saturating_sub(fixed).max(20));
+            }
+
+            let print_row = |row: &Row, indent: &str| {
+                let path = listing::truncate_start(&row.bin.path.display().to_string(), path_width);
+                let mut line = format!(
+                    "{}{:>nw$}. {} -> {}",
+                    indent,
+                    row.number,
+                    listing::pad(row.name, name_width).color(Color::Green),
+                    if row.cells.is_empty() {
+                        path
+                    } else {
+                        listing::pad(&path, path_width)
+                    }
+                    .color(Color::Green),
+                    nw = number_width,
+                );
+                for (i, (cell, w)) in row.cells.iter().zip(&cell_widths).enumerate() {
+                    let cell = if i + 1 == row.cells.len() {
+                        cell.clone()
+                    } else {
+                        listing::pad(cell, *w)
+                    };
+                    line.push_str(&format!("  {}", cell.dimmed()));
+                }
+                println!("{}{}", line.trim_end(), row.suffix);
+            };
+
+            if options.tree {
+                // directories in order of their first bin, bins keep their
+                // number so that it means the same as in the flat listing
+                let mut dirs: Vec<Option<&PathBuf>> = Vec::new();
+                for row in &rows {
+                    if !dirs.contains(&row.bin.source_dir.as_ref()) {
+                        dirs.push(row.bin.source_dir.as_ref());
+                    }
+                }
+                dirs.sort_by_key(|d| d.is_none());
+                for dir in dirs {
+                    let title = match dir {
+                        Some(dir) => dir.display().to_string(),
+                        None => "(added one by one)".to_string(),
+                    };
+                    println!("  {}", title.yellow());
+                    for row in rows.iter().filter(|r| r.bin.source_dir.as_ref() == dir) {
+                        print_row(row, "    ");
+                    }
+                }
+            } else {
+                for row in &rows {
+                    print_row(row, "  ");
+                }
+            }
+        }
+    }
+
+    /// The group called `name`, created in the writable layer if missing
+    fn group_entry(&mut self, name: String) -> &mut Group {
+        let layer = self.layer;
+        let index = self
+            .groups
+            .values()
+            .filter(|g| g.origin == layer)
+            .map(|g| g.index + 1)
+            .max()
+            .unwrap_or(0);
+        self.groups.entry(name).or_insert_with(|| Group {
+            index,
+            created: listing::now(),
+            ..Group::with_origin(layer)
+        })
+    }
+
+    /// Every group in the order `x list --all` shows and numbers them: by
+    /// config, then in the order they were created
+    pub fn ordered_groups(&self) -> Vec<(&String, &Group)> {
+        let mut groups: Vec<_> = self.groups.iter().collect();
+        groups.sort_by(|a, b| (a.1.origin, a.1.index, a.0).cmp(&(b.1.origin, b.1.index, b.0)));
+        groups
+    }
+
+    /// `name`, or the group numbered `name` by `x list --all` when no group
+    /// has that name
+    pub fn group_ref(&self, name: &str) -> Result<String> {
+        if self.groups.contains_key(name) {
+            return Ok(name.to_string());
+        }
+        let Ok(number) = name.parse::<usize>() else {
+            return Ok(name.to_string());
+        };
+        let groups = self.ordered_groups();
+        match number.checked_sub(1).and_then(|i| groups.get(i)) {
+            Some((gn, _)) => Ok(gn.to_string()),
+            None => anyhow::bail!(
+                "there is no group number {}, `x list --all` shows {}",
+                number,
+                groups.len()
+            ),
+        }
+    }
+
+    /// The executables `x list` shows for group `gn` in `order`, its own
+    /// executables when the group cannot be resolved
+    fn listed_bins(
+        &self,
+        gn: &str,
+        order: SortOrder,
+        usage: &Usage,
+    ) -> Vec<(String, (&str, &Bin))> {
+        let bins = self.resolve(gn).unwrap_or_else(|_| {
+            self.groups
+                .get_key_value(gn)
+                .map(|(gn, g)| {
+                    g.bins
+                        .iter()
+                        .map(|(bn, b)| (bn.clone(), (gn.as_str(), b)))
+                        .collect()
+                })
+                .unwrap_or_default()
+        });
+        listing::sort_bins(bins, order, usage)
+    }
+
+    /// The group and name of the executable numbered `name` by `x list`,
+    /// or `None` when `name` is not a number or names an executable.
+    /// Without a group the number counts through the active groups like
+    /// `x list`, with one through that group like `x list --all`.
+    pub fn bin_ref(&self, group: Option<&str>, name: &str) -> Result<Option<(String, String)>> {
+        let groups = match group {
+            Some(group) => vec![group.to_string()],
+            None => self.active_groups(),
+        };
+        if groups.iter().any(|gn| self.find(gn, name).is_some()) {
+            return Ok(None);
+        }
+        let Ok(number) = name.parse::<usize>() else {
+            return Ok(None);
+        };
+        let usage = Usage::default();
+        let bins: Vec<(&String, String)> = groups
+            .iter()
+            .flat_map(|gn| {
+                self.listed_bins(gn, SortOrder::Name, &usage)
+                    .into_iter()
+                    .map(move |(bn, _)| (gn, bn))
+            })
+            .collect();
+        match number.checked_sub(1).and_then(|i| bins.get(i)) {
+            Some((gn, bn)) => Ok(Some((gn.to_string(), bn.clone()))),
+            None => anyhow::bail!(
+                "there is no executable number {} in {}, it has {}",
+                number,
+                match group {
+                    Some(group) => format!("group {}", group),
+                    None => "the active groups".to_string(),
+                },
+                bins.len()
+            ),
         }
     }
 
     pub fn remove(&mut self, group: &str, name: Option<&str>, delete: bool) -> Result<()> {
+        if let Some(g) = self.groups.get(group) {
+            let is_path = name.is_some_and(|n| n.contains("/") || n.contains("\\"));
+            let affected: Vec<&Bin> = match name {
+                Some(n) if is_path => g
+                    .bins
+                    .values()
+                    .filter(|b| b.source_dir.as_deref() == Some(Path::new(n)))
+                    .collect(),
+                Some(n) => g.bins.get(n).into_iter().collect(),
+                None if delete => {
+                    if g.origin != self.layer {
+                        anyhow::bail!(
+                            "group {} comes from the {} config and cannot be deleted here",
+                            group,
+                            g.origin
+                        );
+                    }
+                    if let Some((child, _)) = self
+                        .groups
+                        .iter()
+                        .find(|(_, c)| c.extends.iter().any(|p| p == group))
+                    {
+                        anyhow::bail!("group {} is extended by group {}", group, child);
+                    }
+                    g.bins.values().collect()
+                }
+                None => Vec::new(),
+            };
+            for b in affected {
+                self.ensure_writable(group, b)?;
+            }
+        }
+
+        let before = self.linked_bins()?;
+        let layer = self.layer;
         let mut delete_group = false;
         if let Some(g) = self.groups.get_mut(group) {
             if let Some(name) = name {
                 if name.contains("/") || name.contains("\\") {
                     // is path
                     // try to find path in bins
-                    let path = PathBuf::from(name);
-                    g.remove_bin_by_path(&path, &self.bin_dir)?;
+                    g.remove_bin_by_path(Path::new(name), layer);
                 } else {
-                    g.remove_bin_by_name(name, &self.bin_dir)?;
+                    g.remove_bin_by_name(name);
                 }
             } else if delete {
                 delete_group = true;
-                for (_, bin) in g.bins.iter() {
-                    bin.uninstall(&self.bin_dir)?;
-                }
                 g.bins.clear();
             }
         }
         if delete_group {
             self.groups.remove(group);
+            self.also_active.retain(|a| a != group);
         }
-        Ok(())
+        self.relink(&before)
     }
 
+    /// Look up a bin by name in a group, including inherited bins
     pub fn find(&self, group: &str, name: &str) -> Option<&Bin> {
+        match self.resolve(group) {
+            Ok(bins) => bins.get(name).map(|(_, b)| *b),
+            // fall back to the group's own bins when inheritance is broken
+            Err(_) => self.groups.get(group).and_then(|g| g.bins.get(name)),
+        }
+    }
+
+    /// All bins visible in `group` by name, together with the name of the
+    /// group that provides them. Parents listed in `extends` are applied in
+    /// order, then `exclude` drops inherited names and the group's own bins
+    /// override everything.
+    pub fn resolve(&self, group: &str) -> Result<HashMap<String, (&str, &Bin)>> {
+        self.resolve_with_stack(group, &mut Vec::new())
+    }
+
+    fn resolve_with_stack<'a>(
+        &'a self,
+        group: &str,
+        stack: &mut Vec<String>,
+    ) -> Result<HashMap<String, (&'a str, &'a Bin)>> {
+        if let Some(pos) = stack.iter().position(|g| g == group) {
+            let mut cycle = stack[pos..].to_vec();
+            cycle.push(group.to_string());
+            anyhow::bail!("group inheritance cycle: {}", cycle.join(" -> "));
+        }
+        let (gn, g) = self
+            .groups
+            .get_key_value(group)
+            .ok_or_else(|| match stack.last() {
+                Some(child) => anyhow!("group {} extended by {} does not exist", group, child),
+                None => anyhow!("group {} does not exist", group),
+            })?;
+
+        let mut bins = HashMap::new();
+        stack.push(gn.clone());
+        for parent in &g.extends {
+            bins.extend(self.resolve_with_stack(parent, stack)?);
+        }
+        stack.pop();
+        for name in &g.exclude {
+            bins.remove(name);
+        }
+        bins.extend(g.bins.iter().map(|(bn, b)| (bn.clone(), (gn.as_str(), b))));
+        Ok(bins)
+    }
+
+    /// Error for a bin that is not one of the group's own bins
+    fn missing_bin_error(&self, group: &str, name: &str) -> anyhow::Error {
+        match self
+            .resolve(group)
+            .ok()
+            .and_then(|bins| bins.get(name).map(|(p, _)| p.to_string()))
+        {
+            Some(provider) => anyhow!(
+                "executable {} is inherited by group {} from group {}",
+                name,
+                group,
+                provider
+            ),
+            None => anyhow!("executable {} not found in group {}", name, group),
+        }
+    }
+
+    /// Set the groups `group` inherits from and the inherited names it
+    /// leaves out, creating the group if needed
+    pub fn set_extends(
+        &mut self,
+        group: &str,
+        extends: Vec<String>,
+        exclude: Vec<String>,
+    ) -> Result<()> {
         if let Some(g) = self.groups.get(group)
-            && let Some(b) = g.bins.get(name)
+            && g.origin != self.layer
         {
-            return Some(b);
+            anyhow::bail!(
+                "group {} comes from the {} config and cannot be changed here",
+                group,
+                g.origin
+            );
         }
-        None
+
+        let before = self.linked_bins()?;
+        let created = !self.group_exists(group);
+        let g = self.group_entry(group.to_string());
+        let old_extends = std::mem::replace(&mut g.extends, extends);
+        let old_exclude = std::mem::replace(&mut g.exclude, exclude);
+
+        if let Err(e) = self.resolve(group) {
+            if created {
+                self.groups.remove(group);
+            } else {
+                let g = self.groups.get_mut(group).unwrap();
+                g.extends = old_extends;
+                g.exclude = old_exclude;
+            }
+            return Err(e);
+        }
+        self.relink(&before)
+    }
+
+    /// Create an empty group
+    pub fn create_group(
+        &mut self,
+        name: &str,
+        description: Option<String>,
+        tags: Vec<String>,
+    ) -> Result<()> {
+        if self.group_exists(name) {
+            anyhow::bail!("group {} already exists", name);
+        }
+        let g = self.group_entry(name.to_string());
+        g.description = description;
+        g.tags = tags;
+        Ok(())
+    }
+
+    /// Create `to` with the own executables, inheritance, directories and
+    /// metadata of `from`, in the writable layer
+    pub fn copy_group(&mut self, from: &str, to: &str) -> Result<()> {
+        let Some(source) = self.groups.get(from).cloned() else {
+            anyhow::bail!("group {} does not exist", from);
+        };
+        if self.group_exists(to) {
+            anyhow::bail!("group {} already exists", to);
+        }
+        let layer = self.layer;
+        let g = self.group_entry(to.to_string());
+        g.extends = source.extends;
+        g.exclude = source.exclude;
+        g.description = source.description;
+        g.tags = source.tags;
+        g.sources = source.sources;
+        for s in g.sources.iter_mut() {
+            s.origin = layer;
+        }
+        g.bins = source.bins;
+        for b in g.bins.values_mut() {
+            b.origin = layer;
+        }
+        Ok(())
+    }
+
+    /// Rename a group, along with the groups extending it and the active
+    /// groups. The executables stay linked as they are.
+    pub fn rename_group(&mut self, from: &str, to: &str) -> Result<()> {
+        let Some(g) = self.groups.get(from) else {
+            anyhow::bail!("group {} does not exist", from);
+        };
+        if self.group_exists(to) {
+            anyhow::bail!("group {} already exists", to);
+        }
+        if g.origin != self.layer || g.bins.values().any(|b| b.origin != self.layer) {
+            anyhow::bail!(
+                "group {} has entries of the {} config and cannot be renamed here",
+                from,
+                g.origin
+            );
+        }
+        if let Some((child, c)) = self
+            .groups
+            .iter()
+            .find(|(_, c)| c.origin != self.layer && c.extends.iter().any(|p| p == from))
+        {
+            anyhow::bail!(
+                "group {} is extended by group {} of the {} config",
+                from,
+                child,
+                c.origin
+            );
+        }
+
+        let g = self.groups.remove(from).unwrap();
+        self.groups.insert(to.to_string(), g);
+        for c in self.groups.values_mut() {
+            for parent in c.extends.iter_mut().filter(|p| *p == from) {
+                *parent = to.to_string();
+            }
+        }
+        if self.active_group == from {
+            self.active_group = to.to_string();
+        }
+        for active in self.also_active.iter_mut().filter(|a| *a == from) {
+            *active = to.to_string();
+        }
+        Ok(())
+    }
+
+    /// Delete a group that is not the active group
+    pub fn delete_group(&mut self, name: &str) -> Result<()> {
+        if !self.group_exists(name) {
+            anyhow::bail!("group {} does not exist", name);
+        }
+        if self.active_group == name {
+            anyhow::bail!("group {} is active, switch to another group first", name);
+        }
+        self.remove(name, None, true)
+    }
+
+    /// Set the description of a group, `Some("")` removes it, and add and
+    /// remove tags
+    pub fn describe_group(
+        &mut self,
+        name: &str,
+        description: Option<String>,
+        tag: &[String],
+        untag: &[String],
+    ) -> Result<()> {
+        let layer = self.layer;
+        let Some(g) = self.groups.get_mut(name) else {
+            anyhow::bail!("group {} does not exist", name);
+        };
+        if g.origin != layer {
+            anyhow::bail!(
+                "group {} comes from the {} config and cannot be changed here",
+                name,
+                g.origin
+            );
+        }
+        if let Some(description) = description {
+            g.description = Some(description).filter(|d| !d.is_empty());
+        }
+        g.tags.retain(|t| !untag.contains(t));
+        for t in tag {
+            if !g.tags.contains(t) {
+                g.tags.push(t.clone());
+            }
+        }
+        Ok(())
     }
 
     pub fn switch(&mut self, need_active_group_name: &str) -> Result<()> {
-        if self.active_group == need_active_group_name {
+        self.activate(&[need_active_group_name.to_string()])
+    }
+
+    /// Make `groups` the active groups, the first one with the highest
+    /// priority
+    pub fn activate(&mut self, groups: &[String]) -> Result<()> {
+        // broken old groups still get their own links removed
+        let before = self.linked_bins().unwrap_or_else(|_| {
+            self.active_groups()
+                .iter()
+                .filter_map(|gn| self.groups.get(gn))
+                .flat_map(|g| g.bins.clone())
+                .collect()
+        });
+        let previous = (self.active_group.clone(), self.also_active.clone());
+        if !self.set_active(groups)? {
             return Ok(());
         }
+        if let Err(e) = self.relink(&before) {
+            (self.active_group, self.also_active) = previous;
+            return Err(e);
+        }
+        Ok(())
+    }
 
-        if !self.group_exists(need_active_group_name) {
-            anyhow::bail!("group {} does not exist", need_active_group_name);
+    /// What `activate` would do to the bin directory, without doing it
+    pub fn preview_activate(&self, groups: &[String]) -> Result<SwitchPreview> {
+        let mut next = self.clone();
+        next.set_active(groups)?;
+        let before = self.linked_bins().unwrap_or_default();
+        let after = next.linked_bins()?;
+        let changes = links::changes(&before, &after);
+        let unchanged = after.len()
+            - changes
+                .iter()
+                .filter(|c| !matches!(c, LinkChange::Remove(_)))
+                .count();
+
+        let shadowed_before = self.shadowed().unwrap_or_default();
+        let shadowed = next
+            .shadowed()?
+            .into_iter()
+            .filter(|s| !shadowed_before.contains(s))
+            .collect();
+        let owned = self.known_targets(&before);
+        let foreign = changes
+            .iter()
+            .map(|c| c.link_path(&self.bin_dir))
+            .filter(|p| {
+                p.symlink_metadata().is_ok()
+                    && !fs::read_link(p).is_ok_and(|target| owned.contains(&target))
+            })
+            .collect();
+        Ok(SwitchPreview {
+            changes,
+            unchanged,
+            shadowed,
+            foreign,
+        })
+    }
+
+    /// Enabled executables of the active groups hidden by an executable of
+    /// the same name and another target in a group with a higher priority
+    fn shadowed(&self) -> Result<Vec<Shadowed>> {
+        let winners = self.active_bins()?;
+        let mut shadowed = Vec::new();
+        for gn in self.active_groups() {
+            if !self.group_exists(&gn) {
+                continue;
+            }
+            let mut bins: Vec<_> = self.resolve(&gn)?.into_iter().collect();
+            bins.sort_by(|a, b| a.0.cmp(&b.0));
+            for (bn, (_, b)) in bins {
+                match winners.get(&bn) {
+                    Some((winner, w)) if b.enabled && *winner != gn && w.path != b.path => {
+                        shadowed.push(Shadowed {
+                            name: bn,
+                            group: gn.clone(),
+                            by: winner.clone(),
+                        });
+                    }
+                    _ => {}
+                }
+            }
         }
+        Ok(shadowed)
+    }
 
-        let old_group_name = &self.active_group;
-        let bin_dir = &self.bin_dir;
-        let old_groups = self.groups.get_mut(old_group_name).unwrap();
-        for (_, b) in old_groups.bins.iter() {
-            b.uninstall(bin_dir)?;
+    /// Check `groups` and make them the active groups, returning whether
+    /// they changed
+    fn set_active(&mut self, groups: &[String]) -> Result<bool> {
+        let mut wanted: Vec<String> = Vec::new();
+        for gn in groups {
+            if !wanted.contains(gn) {
+                wanted.push(gn.clone());
+            }
         }
-        let new_groups = self.groups.get_mut(need_active_group_name).unwrap();
-        for (_, b) in new_groups.bins.iter() {
-            b.install(bin_dir)?;
+        let Some((primary, rest)) = wanted.split_first() else {
+            anyhow::bail!("no group to activate");
+        };
+        if *primary == self.active_group && rest == self.also_active.as_slice() {
+            return Ok(false);
+        }
+
+        for gn in &wanted {
+            // the default group is active before it is created
+            if !self.group_exists(gn) && gn == GLOBAL_DEFAULT_GROUP_NAME {
+                continue;
+            }
+            if !self.group_exists(gn) {
+                anyhow::bail!("group {} does not exist", gn);
+            }
+            self.resolve(gn)?;
         }
-        self.active_group = need_active_group_name.to_string();
+
+        self.active_group = primary.clone();
+        self.also_active = rest.to_vec();
+        Ok(true)
+    }
+
+    /// The active groups in decreasing priority
+    pub fn active_groups(&self) -> Vec<String> {
+        std::iter::once(&self.active_group)
+            .chain(&self.also_active)
+            .cloned()
+            .collect()
+    }
+
+    pub fn is_active(&self, group: &str) -> bool {
+        self.active_group == group || self.also_active.iter().any(|a| a == group)
+    }
+
+    /// Every name provided by the active groups, with the active group
+    /// that wins it. The first active group with an enabled bin of that
+    /// name wins, or the first one that has it when all are disabled.
+    pub fn active_bins(&self) -> Result<HashMap<String, (String, &Bin)>> {
+        let mut bins: HashMap<String, (String, &Bin)> = HashMap::new();
+        for gn in self.active_groups() {
+            // the active group may never have been created, e.g. right
+            // after `x init`
+            if !self.group_exists(&gn) {
+                continue;
+            }
+            for (bn, (_, b)) in self.resolve(&gn)? {
+                let replace = match bins.get(&bn) {
+                    Some((_, current)) => !current.enabled && b.enabled,
+                    None => true,
+                };
+                if replace {
+                    bins.insert(bn, (gn.clone(), b));
+                }
+            }
+        }
+        Ok(bins)
+    }
+
+    /// Look up a bin by name in the active groups
+    pub fn find_active(&self, name: &str) -> Option<(String, &Bin)> {
+        self.active_bins().ok()?.remove(name)
+    }
+
+    /// Merge the project config found in `dir` or one of its ancestors,
+    /// if any
+    pub fn merge_project_config(&mut self, dir: &Path) -> Result<()> {
+        let Some(project_path) = find_project_config(dir) else {
+            return Ok(());
+        };
+        let groups = load_project_config(&project_path).map_err(|e| {
+            anyhow!(
+                "cannot load project config {}: {}",
+                project_path.display(),
+                e
+            )
+        })?;
+        self.overlay_groups(groups);
+        self.project_config = Some(project_path);
         Ok(())
     }
 
+    /// The enabled bins of the active groups by name, i.e. the symlinks
+    /// that should exist in `bin_dir`. Never computed with a project
+    /// config merged, whose executables are only run through `x run`.
+    pub fn linked_bins(&self) -> Result<HashMap<String, Bin>> {
+        if let Some(project_path) = &self.project_config {
+            anyhow::bail!(
+                "cannot link executables with project config {} merged",
+                project_path.display()
+            );
+        }
+        Ok(self
+            .active_bins()?
+            .into_iter()
+            .filter(|(_, (_, b))| b.enabled)
+            .map(|(bn, (_, b))| (bn, b.clone()))
+            .collect())
+    }
+
+    /// Bring the symlinks in `bin_dir` from `before`, what `linked_bins`
+    /// returned prior to a change, to the current state. Only links that
+    /// differ are touched, and each is replaced in one step. If a symlink
+    /// cannot be changed, the ones changed before it are restored.
+    fn relink(&self, before: &HashMap<String, Bin>) -> Result<()> {
+        let after = self.linked_bins()?;
+        let owned = self.known_targets(before);
+        Transaction::run(&self.bin_dir, &owned, force_mode(), |tx| {
+            for (bn, b) in before {
+                if !after.contains_key(bn) {
+                    tx.remove(b)?;
+                }
+            }
+            for b in after.values() {
+                tx.install(b)?;
+            }
+            Ok(())
+        })
+    }
+
+    /// Every target a symlink made by x can point at: the paths of all
+    /// bins, and of the bins linked before a change
+    pub fn known_targets(&self, before: &HashMap<String, Bin>) -> HashSet<PathBuf> {
+        self.groups
+            .values()
+            .flat_map(|g| g.bins.values().chain(g.shadowed.iter()))
+            .chain(before.values())
+            .map(|b| b.path.clone())
+            .collect()
+    }
+
+    /// Run `change` and relink, leaving the config and the bin directory
+    /// as they were if either fails
+    fn transaction<T>(&mut self, change: impl FnOnce(&mut Self) -> Result<T>) -> Result<T> {
+        let saved = self.clone();
+        let before = self.linked_bins()?;
+        let result = change(self).and_then(|value| {
+            self.relink(&before)?;
+            Ok(value)
+        });
+        if result.is_err() {
+            *self = saved;
+        }
+        result
+    }
+
+    /// Install the symlinks of every bin in the active group. Symlinks x
+    /// made for any group may be replaced.
+    pub fn install_active(&self) -> Result<()> {
+        let linked = self.linked_bins()?;
+        let owned = self.known_targets(&linked);
+        Transaction::run(&self.bin_dir, &owned, force_mode(), |tx| {
+            linked.values().try_for_each(|b| tx.install(b))
+        })
+    }
+
+    /// Replace the symlinks of `previous`, an earlier state of this config,
+    /// with the symlinks of this config
+    pub fn relink_from(&self, previous: &Config) -> Result<()> {
+        if previous.bin_dir != self.bin_dir {
+            previous.uninstall_active()?;
+            return self.install_active();
+        }
+        self.relink(&previous.linked_bins()?)
+    }
+
+    /// Remove the symlinks of every bin in the active group, including
+    /// ones left pointing at another bin x knows
+    pub fn uninstall_active(&self) -> Result<()> {
+        let linked = self.linked_bins()?;
+        let owned = self.known_targets(&linked);
+        Transaction::run(&self.bin_dir, &owned, force_mode(), |tx| {
+            linked.values().try_for_each(|b| tx.remove(b))
+        })
+    }
+
     pub fn group_exists(&self, group: &str) -> bool {
         self.groups.contains_key(group)
     }
@@ -291,96 +1834,70 @@ impl Config {
     pub fn rename(&mut self, group: &str, old_name: &str, new_name: &str) -> Result<()> {
         let g = self
             .groups
-            .get_mut(group)
+            .get(group)
             .ok_or_else(|| anyhow!("group {} does not exist", group))?;
 
         let bin = g
             .bins
-            .remove(old_name)
-            .ok_or_else(|| anyhow!("executable {} not found in group {}", old_name, group))?;
-
+            .get(old_name)
+            .ok_or_else(|| self.missing_bin_error(group, old_name))?;
+        self.ensure_writable(group, bin)?;
         if g.bins.contains_key(new_name) {
-            // Restore the old bin
-            g.bins.insert(old_name.to_string(), bin);
             anyhow::bail!("executable {} already exists in group {}", new_name, group);
         }
 
-        // Uninstall old symlink using the bin's uninstall method
-        if self.active_group == group {
-            bin.uninstall(&self.bin_dir)?;
-        }
-
-        // Create new bin with new name
-        let mut new_bin = bin.clone();
-        new_bin.name = new_name.to_string();
-
-        // Install new symlink if in active group (install() respects the enabled flag)
-        if self.active_group == group {
-            new_bin.install(&self.bin_dir)?;
-        }
-
-        g.bins.insert(new_name.to_string(), new_bin);
-        Ok(())
+        // the old symlink goes away and the new one is created if the
+        // group is active (install() respects the enabled flag)
+        self.transaction(|conf| {
+            let g = conf.groups.get_mut(group).unwrap();
+            let mut new_bin = g.bins.remove(old_name).unwrap();
+            new_bin.name = new_name.to_string();
+            new_bin.raw_name = None;
+            g.bins.insert(new_name.to_string(), new_bin);
+            Ok(())
+        })
     }
 
     pub fn set_enabled(&mut self, group: &str, name: &str, enabled: bool) -> Result<()> {
         let g = self
             .groups
-            .get_mut(group)
+            .get(group)
             .ok_or_else(|| anyhow!("group {} does not exist", group))?;
+        if !g.bins.contains_key(name) {
+            return Err(self.missing_bin_error(group, name));
+        }
 
-        let bin = g
+        let before = self.linked_bins()?;
+        let layer = self.layer;
+        let bin = self
+            .groups
+            .get_mut(group)
+            .unwrap()
             .bins
             .get_mut(name)
-            .ok_or_else(|| anyhow!("executable {} not found in group {}", name, group))?;
+            .unwrap();
 
         if bin.enabled == enabled {
             return Ok(()); // Already in desired state
         }
 
         bin.enabled = enabled;
+        // the state is kept in the writable layer, overriding lower layers
+        bin.origin = layer;
 
         // Update symlink if in active group
-        if self.active_group == group {
-            if enabled {
-                bin.install(&self.bin_dir)?;
-            } else {
-                bin.uninstall(&self.bin_dir)?;
-            }
-        }
-
-        Ok(())
+        self.relink(&before)
     }
 
-    pub fn search(&self, query: &str) -> Vec<(String, String, &Bin)> {
-        let mut results = Vec::new();
-        let query_lower = query.to_lowercase();
-
-        for (group_name, group) in &self.groups {
-            for (bin_name, bin) in &group.bins {
-                if bin_name.to_lowercase().contains(&query_lower)
-                    || bin
-                        .path
-                        .to_string_lossy()
-                        .to_lowercase()
-                        .contains(&query_lower)
-                {
-                    results.push((group_name.clone(), bin_name.clone(), bin));
-                }
-            }
-        }
-
-        results
+    /// The executables of every group matching `query`, see
+    /// [`crate::search::Query`]
+    pub fn search(&self, query: &str, options: &SearchOptions) -> Result<Vec<Hit<'_>>> {
+        crate::search::search(self, query, options)
     }
 
     pub fn get_bin_info(&self, group: &str, name: &str) -> Result<&Bin> {
-        let g = self
-            .groups
-            .get(group)
-            .ok_or_else(|| anyhow!("group {} does not exist", group))?;
-
-        let bin = g
-            .bins
+        let bins = self.resolve(group)?;
+        let (_, bin) = bins
             .get(name)
             .ok_or_else(|| anyhow!("executable {} not found in group {}", name, group))?;
 
@@ -388,85 +1905,223 @@ impl Config {
     }
 }
 
-#[cfg(unix)]
-fn is_executable(p: &Path) -> bool {
-    use std::os::unix::fs::PermissionsExt;
-    if let Ok(meta) = p.metadata() {
-        meta.permissions().mode() & 0o111 != 0
+/// Whether the bin `bin_name` stands for an executable called `name`,
+/// possibly renamed by `--on-conflict=rename`
+fn is_name_for(bin_name: &str, name: &str) -> bool {
+    bin_name == name
+        || bin_name
+            .strip_prefix(name)
+            .and_then(|rest| rest.strip_prefix('-'))
+            .is_some_and(|n| n.parse::<usize>().is_ok())
+}
+
+/// Whether two paths of a bin are the same executable. Configs written
+/// before symlinks were kept as they are hold canonical paths, so paths
+/// that differ are compared once resolved.
+fn same_target(a: &Path, b: &Path) -> bool {
+    a == b || matches!((a.canonicalize(), b.canonicalize()), (Ok(a), Ok(b)) if a == b)
+}
+
+pub static PROJECT_CONFIG_FILE_NAME: &str = ".x.json";
+
+/// The nearest project config in `start` or one of its ancestors
+pub fn find_project_config(start: &Path) -> Option<PathBuf> {
+    start
+        .ancestors()
+        .map(|dir| dir.join(PROJECT_CONFIG_FILE_NAME))
+        .find(|path| path.is_file())
+}
+
+/// A project config only defines groups, the active group and the bin dir
+/// stay with the user
+#[derive(Deserialize)]
+#[serde(rename_all = "kebab-case")]
+struct ProjectConfig {
+    #[serde(default)]
+    groups: HashMap<String, Group>,
+}
+
+/// Give the groups of one config file distinct indexes. Older configs
+/// kept the size of the group there, their groups are numbered by name.
+fn number_groups(groups: &mut HashMap<String, Group>) {
+    let mut indexes: Vec<usize> = groups.values().map(|g| g.index).collect();
+    indexes.sort();
+    indexes.dedup();
+    if indexes.len() == groups.len() {
+        return;
+    }
+    let mut names: Vec<String> = groups.keys().cloned().collect();
+    names.sort();
+    for (index, gn) in names.iter().enumerate() {
+        if let Some(g) = groups.get_mut(gn) {
+            g.index = index;
+        }
+    }
+}
+
+fn load_project_config(path: &Path) -> Result<HashMap<String, Group>> {
+    let bytes = fs::read(path)?;
+    let project: ProjectConfig = serde_json::from_slice(&bytes)?;
+    let project_dir = path.parent().unwrap_or(Path::new("."));
+    let mut groups = project.groups;
+    number_groups(&mut groups);
+    for g in groups.values_mut() {
+        g.origin = Origin::Project;
+        for source in g.sources.iter_mut() {
+            source.origin = Origin::Project;
+            if source.dir.is_relative() {
+                source.dir = project_dir.join(&source.dir);
+            }
+        }
+        for b in g.bins.values_mut() {
+            b.origin = Origin::Project;
+            // paths in a repository are relative to the project config
+            if b.path.is_relative() {
+                b.path = project_dir.join(&b.path);
+            }
+            if let Some(source_dir) = b.source_dir.as_mut()
+                && source_dir.is_relative()
+            {
+                *source_dir = project_dir.join(&*source_dir);
+            }
+        }
+    }
+    Ok(groups)
+}
+
+fn load_system_config() -> Result<Option<Config>> {
+    let path = system_config_path();
+    if !path.exists() {
+        return Ok(None);
+    }
+    let mut conf = Config::load(&path)?;
+    conf.set_origin(Origin::System);
+    Ok(Some(conf))
+}
+
+/// Load the config of the current user layered over the system-wide
+/// config, or only the system-wide config in system mode. The project
+/// config is left out, see `load_config_with_project`.
+pub fn load_config(create: bool) -> Result<Config> {
+    load_config_from(&get_config_path()?, create)
+}
+
+/// `load_config` for the config at `conf_path`, e.g. that of another
+/// profile
+pub fn load_config_from(conf_path: &Path, create: bool) -> Result<Config> {
+    let system = if system_mode() {
+        None
+    } else {
+        load_system_config()?
+    };
+
+    let mut conf = if conf_path.exists() {
+        Config::load(conf_path)?
+    } else if create {
+        let c = Config::default();
+        c.save(conf_path)?;
+        c
+    } else if let Some(system) = &system {
+        // users can rely on the system config without running `x init`
+        Config {
+            active_group: system.active_group.clone(),
+            also_active: system.also_active.clone(),
+            ..Default::default()
+        }
+    } else {
+        anyhow::bail!(
+            "config file does not exist, please run `{}` first",
+            "x init".bold().green()
+        );
+    };
+    conf.set_origin(if system_mode() {
+        Origin::System
     } else {
-        false
+        Origin::User
+    });
+
+    if let Some(mut system) = system {
+        system.overlay_groups(std::mem::take(&mut conf.groups));
+        conf.groups = system.groups;
     }
+    Ok(conf)
 }
 
-#[cfg(windows)]
-fn is_executable(p: &Path) -> bool {
-    p.extension()
-        .and_then(|s| s.to_str())
-        .map(|ext| {
-            matches!(
-                ext.to_ascii_lowercase().as_str(),
-                "exe" | "bat" | "cmd" | "ps1" | "com"
-            )
-        })
-        .unwrap_or(false)
+/// `load_config` with the project config of the working directory merged
+/// on top, for looking up executables. A repository must not be able to
+/// put programs on PATH, so such a config cannot change the symlinks, and
+/// it is only merged once the user trusts it with `x trust`.
+pub fn load_config_with_project() -> Result<Config> {
+    let mut conf = load_config(false)?;
+    merge_trusted_project_config(&mut conf)?;
+    Ok(conf)
 }
 
-fn collect_executables_from_dir(dir: &Path) -> Result<Vec<(String, PathBuf)>> {
-    let mut res = Vec::new();
-    for entry in fs::read_dir(dir)? {
-        let entry = entry?;
-        let file_type = entry.file_type()?;
-        if !file_type.is_file() {
-            continue;
-        }
-        let path = entry.path();
-        if is_runnable_file(&path) {
-            let name = entry
-                .file_name()
-                .into_string()
-                .map_err(|_| anyhow!("non-utf8 filename"))?;
-            res.push((name, path.canonicalize()?));
+/// Merge the project config of the working directory into `conf` as
+/// `load_config_with_project` does, if it is trusted
+pub fn merge_trusted_project_config(conf: &mut Config) -> Result<()> {
+    if !system_mode()
+        && let Ok(cwd) = std::env::current_dir()
+        && let Some(project_config) = find_project_config(&cwd)
+    {
+        if is_trusted_in(&trusted_projects_path()?, &project_config) {
+            conf.merge_project_config(&cwd)?;
+        } else {
+            eprintln!(
+                "Warning: ignoring {}, run `{}` to use it",
+                project_config.display(),
+                "x trust".bold().green()
+            );
         }
     }
-    Ok(res)
+    Ok(())
 }
 
-fn is_supported_script(path: &Path) -> bool {
-    path.extension()
-        .and_then(|s| s.to_str())
-        .map(|ext| matches!(ext.to_ascii_lowercase().as_str(), "sh" | "py"))
-        .unwrap_or(false)
+/// The file keeping the project configs trusted with `x trust`, shared by
+/// all profiles
+fn trusted_projects_path() -> Result<PathBuf> {
+    Ok(state_dir_in(&home_dir()?, None).join("trusted-projects.json"))
 }
 
-fn is_runnable_file(path: &Path) -> bool {
-    is_executable(path) || is_supported_script(path)
+/// The trusted project configs in `file`, each with the SHA-256 of the
+/// contents it was trusted with
+fn read_trusted(file: &Path) -> BTreeMap<PathBuf, String> {
+    fs::read(file)
+        .ok()
+        .and_then(|bytes| serde_json::from_slice(&bytes).ok())
+        .unwrap_or_default()
 }
 
-fn executable_name(path: &Path) -> Result<String> {
-    let stem = path
-        .file_stem()
-        .ok_or_else(|| anyhow!("cannot extract file stem"))?
-        .to_str()
-        .ok_or_else(|| anyhow!("non-utf8 filename"))?;
-    Ok(stem.into())
+/// Whether `project_config` is trusted in `file` as it is now. A changed
+/// project config has to be trusted again.
+pub fn is_trusted_in(file: &Path, project_config: &Path) -> bool {
+    let Ok(project_config) = project_config.canonicalize() else {
+        return false;
+    };
+    read_trusted(file)
+        .get(&project_config)
+        .is_some_and(|sha256| sha256_file(&project_config).is_ok_and(|now| now == *sha256))
 }
 
-pub fn load_config(create: bool) -> Result<Config> {
-    let conf_path = get_config_path()?;
-    if !conf_path.exists() {
-        if create {
-            let c = Config::default();
-            c.save(&conf_path)?;
-            Ok(c)
-        } else {
-            anyhow::bail!(
-                "config file does not exist, please run `{}` first",
-                "x init".bold().green()
-            );
-        }
+/// Trust `project_config` as it is now, or stop trusting it
+pub fn set_trusted_in(file: &Path, project_config: &Path, trusted: bool) -> Result<()> {
+    let project_config = project_config.canonicalize()?;
+    let mut projects = read_trusted(file);
+    if trusted {
+        projects.insert(project_config.clone(), sha256_file(&project_config)?);
     } else {
-        Config::load(&conf_path)
+        projects.remove(&project_config);
     }
+    if let Some(parent) = file.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(file, serde_json::to_vec_pretty(&projects)?)?;
+    Ok(())
+}
+
+/// `set_trusted_in` for the trusted projects of the current user
+pub fn set_trusted(project_config: &Path, trusted: bool) -> Result<()> {
+    set_trusted_in(&trusted_projects_path()?, project_config, trusted)
 }
 
 #[cfg(test)]
@@ -497,8 +2152,7 @@ mod tests {
         // Create config with a disabled executable
         let mut config = Config {
             active_group: "test-group".to_string(),
-            bin_dir: bin_dir.clone(),
-            groups: HashMap::new(),
+            ..Config::new(bin_dir.clone())
         };
 
         let bin = Bin {
@@ -506,11 +2160,13 @@ mod tests {
             path: exe_path.clone(),
             source_dir: None,
             enabled: false,
+            ..Default::default()
         };
 
         let mut group = Group {
             index: 0,
             bins: HashMap::new(),
+            ..Default::default()
         };
         group.bins.insert("test_exe".to_string(), bin);
         config.groups.insert("test-group".to_string(), group);
@@ -520,7 +2176,7 @@ mod tests {
         assert!(result.is_some());
         let found_bin = result.unwrap();
         assert_eq!(found_bin.name, "test_exe");
-        assert_eq!(found_bin.enabled, false);
+        assert!(!found_bin.enabled);
     }
 
     #[test]
@@ -543,8 +2199,7 @@ mod tests {
 
         let mut config = Config {
             active_group: "test-group".to_string(),
-            bin_dir: bin_dir.clone(),
-            groups: HashMap::new(),
+            ..Config::new(bin_dir.clone())
         };
 
         let bin = Bin {
@@ -552,11 +2207,13 @@ mod tests {
             path: exe_path.clone(),
             source_dir: None,
             enabled: true,
+            ..Default::default()
         };
 
         let mut group = Group {
             index: 0,
             bins: HashMap::new(),
+            ..Default::default()
         };
         group.bins.insert("test_exe".to_string(), bin);
         config.groups.insert("test-group".to_string(), group);
@@ -577,16 +2234,15 @@ mod tests {
 
         // Disable the executable
         config.set_enabled("test-group", "test_exe", false).unwrap();
-        assert_eq!(
-            config
+        assert!(
+            !config
                 .groups
                 .get("test-group")
                 .unwrap()
                 .bins
                 .get("test_exe")
                 .unwrap()
-                .enabled,
-            false
+                .enabled
         );
 
         // Check symlink is removed
@@ -594,7 +2250,7 @@ mod tests {
 
         // Enable the executable
         config.set_enabled("test-group", "test_exe", true).unwrap();
-        assert_eq!(
+        assert!(
             config
                 .groups
                 .get("test-group")
@@ -602,8 +2258,7 @@ mod tests {
                 .bins
                 .get("test_exe")
                 .unwrap()
-                .enabled,
-            true
+                .enabled
         );
 
         // Check symlink is created again
@@ -631,8 +2286,7 @@ mod tests {
 
         let mut config = Config {
             active_group: "base".to_string(),
-            bin_dir,
-            groups: HashMap::new(),
+            ..Config::new(bin_dir.clone())
         };
 
         assert_eq!(config.add("scripts", &shell_script, None).unwrap(), 1);
@@ -641,4 +2295,763 @@ mod tests {
         assert!(config.find("scripts", "demo_shell").is_some());
         assert!(config.find("scripts", "demo_python").is_some());
     }
+
+    #[test]
+    fn test_profile_paths() {
+        let home_dir = Path::new("/home/user");
+        assert_eq!(
+            config_dir_in(home_dir, None),
+            PathBuf::from("/home/user/.config/x")
+        );
+        assert_eq!(
+            config_dir_in(home_dir, Some("work")),
+            PathBuf::from("/home/user/.config/x/profiles/work")
+        );
+        assert_eq!(
+            bin_dir_in(home_dir, None),
+            PathBuf::from("/home/user/.local/bin/x")
+        );
+        assert_eq!(
+            bin_dir_in(home_dir, Some("work")),
+            PathBuf::from("/home/user/.local/bin/x-work")
+        );
+        assert_eq!(
+            state_dir_in(home_dir, Some("work")),
+            PathBuf::from("/home/user/.local/state/x/profiles/work")
+        );
+    }
+
+    fn layered_bin(name: &str, origin: Origin) -> Bin {
+        Bin {
+            name: name.to_string(),
+            path: PathBuf::from(format!("/opt/{}/{}", origin, name)),
+            enabled: true,
+            origin,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_user_layer_overrides_system_layer() {
+        let temp_dir = TempDir::new().unwrap();
+        let bin_dir = temp_dir.path().join("bin");
+
+        let mut system = Config::new(bin_dir.clone());
+        let mut shared = Group::with_origin(Origin::System);
+        for name in ["node", "npm"] {
+            shared
+                .bins
+                .insert(name.to_string(), layered_bin(name, Origin::System));
+        }
+        let source = |origin| SourceDir {
+            dir: PathBuf::from("/opt/shared"),
+            origin,
+            ..Default::default()
+        };
+        shared.sources.push(source(Origin::System));
+        system.groups.insert("node18".to_string(), shared);
+
+        let mut user = Config::new(bin_dir.clone());
+        let mut own = Group::with_origin(Origin::User);
+        own.bins
+            .insert("node".to_string(), layered_bin("node", Origin::User));
+        own.sources.push(source(Origin::User));
+        user.groups.insert("node18".to_string(), own);
+
+        system.overlay_groups(std::mem::take(&mut user.groups));
+        user.groups = system.groups;
+
+        assert_eq!(user.find("node18", "node").unwrap().origin, Origin::User);
+        assert_eq!(user.find("node18", "npm").unwrap().origin, Origin::System);
+
+        // only the user entries are written back
+        let view = user.layer_view();
+        let saved = &view.groups["node18"];
+        assert_eq!(saved.bins.len(), 1);
+        assert!(saved.bins.contains_key("node"));
+
+        // system entries are read-only for the user
+        assert!(user.remove("node18", Some("npm"), false).is_err());
+        assert!(user.rename("node18", "npm", "npm18").is_err());
+        assert!(user.remove("node18", Some("node"), false).is_ok());
+        // removing a directory keeps the system layer's rules for it
+        user.remove("node18", Some("/opt/shared"), false).unwrap();
+        let origins: Vec<Origin> = user.groups["node18"]
+            .sources
+            .iter()
+            .map(|s| s.origin)
+            .collect();
+        assert_eq!(origins, [Origin::System]);
+    }
+
+    #[test]
+    fn test_trusted_project_configs() {
+        let temp_dir = TempDir::new().unwrap();
+        let trusted = temp_dir.path().join("state").join("trusted-projects.json");
+        let project_config = temp_dir.path().join(PROJECT_CONFIG_FILE_NAME);
+        fs::write(&project_config, r#"{"groups": {}}"#).unwrap();
+
+        assert!(!is_trusted_in(&trusted, &project_config));
+        set_trusted_in(&trusted, &project_config, true).unwrap();
+        assert!(is_trusted_in(&trusted, &project_config));
+
+        // a changed config is no longer trusted
+        fs::write(&project_config, r#"{"groups": {"node": {}}}"#).unwrap();
+        assert!(!is_trusted_in(&trusted, &project_config));
+        set_trusted_in(&trusted, &project_config, true).unwrap();
+        assert!(is_trusted_in(&trusted, &project_config));
+        set_trusted_in(&trusted, &project_config, false).unwrap();
+        assert!(!is_trusted_in(&trusted, &project_config));
+    }
+
+    #[test]
+    fn test_project_config_discovery_and_relative_paths() {
+        let temp_dir = TempDir::new().unwrap();
+        let project_dir = temp_dir.path().join("repo");
+        let nested_dir = project_dir.join("src").join("deep");
+        fs::create_dir_all(&nested_dir).unwrap();
+
+        assert_eq!(find_project_config(&nested_dir), None);
+
+        let project_config = project_dir.join(PROJECT_CONFIG_FILE_NAME);
+        fs::write(
+            &project_config,
+            r#"{"groups": {"tools": {"index": 0, "bins": {"lint": {"name": "lint", "path": "bin/lint", "source-dir": null}}}}}"#,
+        )
+        .unwrap();
+        assert_eq!(
+            find_project_config(&nested_dir),
+            Some(project_config.clone())
+        );
+
+        let groups = load_project_config(&project_config).unwrap();
+        let tools = &groups["tools"];
+        assert_eq!(tools.origin, Origin::Project);
+        assert_eq!(tools.bins["lint"].origin, Origin::Project);
+        assert_eq!(tools.bins["lint"].path, project_dir.join("bin/lint"));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_project_config_is_never_linked() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let temp_dir = TempDir::new().unwrap();
+        let bin_dir = temp_dir.path().join("bin");
+        fs::create_dir_all(&bin_dir).unwrap();
+        let project_dir = temp_dir.path().join("repo");
+        fs::create_dir_all(&project_dir).unwrap();
+        fs::write(
+            project_dir.join(PROJECT_CONFIG_FILE_NAME),
+            r#"{"groups": {"base": {"bins": {"evil": {"name": "evil", "path": "bin/evil", "source-dir": null}}}}}"#,
+        )
+        .unwrap();
+        let tool = temp_dir.path().join("tool");
+        fs::write(&tool, "#!/bin/sh\n").unwrap();
+        fs::set_permissions(&tool, fs::Permissions::from_mode(0o755)).unwrap();
+
+        // commands that change the config load it without the project
+        let mut config = Config::new(bin_dir.clone());
+        config
+            .add_with_options("base", &tool, &AddOptions::default())
+            .unwrap();
+        let names: Vec<_> = fs::read_dir(&bin_dir)
+            .unwrap()
+            .map(|e| e.unwrap().file_name())
+            .collect();
+        assert_eq!(names, ["tool"]);
+
+        // `x run` sees the project executables but cannot link them
+        let mut merged = config.clone();
+        merged.merge_project_config(&project_dir).unwrap();
+        assert_eq!(
+            merged.find_active("evil").unwrap().1.path,
+            project_dir.join("bin/evil")
+        );
+        assert!(
+            merged
+                .add_with_options("base", &tool, &AddOptions::default())
+                .is_err()
+        );
+        assert!(bin_dir.join("evil").symlink_metadata().is_err());
+    }
+
+    #[test]
+    fn test_shadowed_bins_are_saved_with_their_layer() {
+        let temp_dir = TempDir::new().unwrap();
+        let mut user = Config::new(temp_dir.path().join("bin"));
+        let mut own = Group::with_origin(Origin::User);
+        own.bins
+            .insert("lint".to_string(), layered_bin("lint", Origin::User));
+        user.groups.insert("base".to_string(), own);
+
+        let mut project = Group::with_origin(Origin::Project);
+        project
+            .bins
+            .insert("lint".to_string(), layered_bin("lint", Origin::Project));
+        user.overlay_groups(HashMap::from([("base".to_string(), project)]));
+
+        assert_eq!(user.find("base", "lint").unwrap().origin, Origin::Project);
+        let view = user.layer_view();
+        assert_eq!(view.groups["base"].bins["lint"].origin, Origin::User);
+    }
+
+    fn group_of(origin: Origin, names: &[&str]) -> Group {
+        let mut g = Group::with_origin(origin);
+        for name in names {
+            g.bins.insert(name.to_string(), layered_bin(name, origin));
+        }
+        g
+    }
+
+    #[test]
+    fn test_resolve_group_inheritance() {
+        let temp_dir = TempDir::new().unwrap();
+        let mut config = Config::new(temp_dir.path().join("bin"));
+        config.groups.insert(
+            "node18".to_string(),
+            group_of(Origin::User, &["node", "npm", "npx"]),
+        );
+        config.groups.insert(
+            "common".to_string(),
+            group_of(Origin::System, &["jq", "npm"]),
+        );
+        let mut dev = group_of(Origin::Project, &["node"]);
+        dev.extends = vec!["node18".to_string(), "common".to_string()];
+        dev.exclude = vec!["npx".to_string()];
+        config.groups.insert("node18-dev".to_string(), dev);
+
+        let bins = config.resolve("node18-dev").unwrap();
+        let mut names: Vec<_> = bins.keys().cloned().collect();
+        names.sort();
+        assert_eq!(names, vec!["jq", "node", "npm"]);
+        // own bins override inherited ones, later parents override earlier
+        assert_eq!(bins["node"].0, "node18-dev");
+        assert_eq!(bins["npm"].0, "common");
+        assert_eq!(
+            config.find("node18-dev", "jq").unwrap().origin,
+            Origin::System
+        );
+
+        // deleting a parent would break the child
+        assert!(config.remove("node18", None, true).is_err());
+    }
+
+    #[test]
+    fn test_group_inheritance_cycle() {
+        let temp_dir = TempDir::new().unwrap();
+        let mut config = Config::new(temp_dir.path().join("bin"));
+        config
+            .groups
+            .insert("a".to_string(), group_of(Origin::User, &["one"]));
+        config
+            .set_extends("b", vec!["a".to_string()], Vec::new())
+            .unwrap();
+
+        let err = config
+            .set_extends("a", vec!["b".to_string()], Vec::new())
+            .unwrap_err();
+        assert_eq!(err.to_string(), "group inheritance cycle: a -> b -> a");
+        // the failed change is rolled back
+        assert!(config.groups["a"].extends.is_empty());
+        assert!(config.find("b", "one").is_some());
+
+        assert!(
+            config
+                .set_extends("c", vec!["missing".to_string()], Vec::new())
+                .is_err()
+        );
+        assert!(!config.group_exists("c"));
+    }
+
+    #[test]
+    fn test_activate_multiple_groups_by_priority() {
+        let temp_dir = TempDir::new().unwrap();
+        let bin_dir = temp_dir.path().join("bin");
+        fs::create_dir_all(&bin_dir).unwrap();
+        let mut config = Config::new(bin_dir.clone());
+
+        let mut lang = group_of(Origin::User, &["node", "npm"]);
+        lang.bins.get_mut("npm").unwrap().enabled = false;
+        config.groups.insert("lang".to_string(), lang);
+        let mut utils = group_of(Origin::User, &["jq", "node", "npm"]);
+        for b in utils.bins.values_mut() {
+            b.path = PathBuf::from(format!("/opt/utils/{}", b.name));
+        }
+        config.groups.insert("utils".to_string(), utils);
+
+        config
+            .activate(&["lang".to_string(), "utils".to_string()])
+            .unwrap();
+        assert_eq!(config.active_groups(), vec!["lang", "utils"]);
+        assert!(config.is_active("utils"));
+
+        let bins = config.active_bins().unwrap();
+        assert_eq!(bins["node"].0, "lang");
+        assert_eq!(bins["jq"].0, "utils");
+        // a disabled bin does not hide the same name in a lower group
+        assert_eq!(bins["npm"].0, "utils");
+        assert_eq!(
+            fs::read_link(bin_dir.join("node")).unwrap(),
+            PathBuf::from("/opt/user/node")
+        );
+
+        config.activate(&["utils".to_string()]).unwrap();
+        assert!(config.also_active.is_empty());
+        assert_eq!(
+            fs::read_link(bin_dir.join("node")).unwrap(),
+            PathBuf::from("/opt/utils/node")
+        );
+        assert!(config.activate(&["missing".to_string()]).is_err());
+    }
+
+    fn write_script(path: &Path) {
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, "#!/bin/sh\necho test\n").unwrap();
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            fs::set_permissions(path, fs::Permissions::from_mode(0o755)).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_add_conflict_policies() {
+        let temp_dir = TempDir::new().unwrap();
+        let bin_dir = temp_dir.path().join("bin");
+        fs::create_dir_all(&bin_dir).unwrap();
+        let first = temp_dir.path().join("first");
+        let second = temp_dir.path().join("second");
+        write_script(&first.join("helper"));
+        write_script(&second.join("helper"));
+        write_script(&second.join("tool"));
+
+        let mut config = Config::new(bin_dir);
+        assert_eq!(config.add("g", &first, None).unwrap(), 1);
+        // adding the same directory again is not a conflict
+        assert_eq!(config.add("g", &first, None).unwrap(), 1);
+
+        let err = config.add("g", &second, None).unwrap_err();
+        assert!(err.to_string().contains("helper"));
+        assert!(config.find("g", "tool").is_none());
+
+        let with = |on_conflict| AddOptions {
+            on_conflict,
+            ..Default::default()
+        };
+        let report = config
+            .add_with_options("g", &second, &with(OnConflict::Skip))
+            .unwrap();
+        assert_eq!(report.added, 1);
+        assert_eq!(report.conflicts[0].resolution, Resolution::Skipped);
+        assert!(
+            config
+                .find("g", "helper")
+                .unwrap()
+                .path
+                .starts_with(first.canonicalize().unwrap())
+        );
+
+        let report = config
+            .add_with_options("g", &second, &with(OnConflict::Rename))
+            .unwrap();
+        assert_eq!(
+            report.conflicts[0].resolution,
+            Resolution::Renamed("helper-2".to_string())
+        );
+        assert!(config.find("g", "helper-2").is_some());
+        let report = config
+            .add_with_options("g", &second, &with(OnConflict::Rename))
+            .unwrap();
+        assert!(report.conflicts.is_empty());
+        assert!(config.find("g", "helper-3").is_none());
+
+        let report = config
+            .add_with_options("g", &second, &with(OnConflict::Replace))
+            .unwrap();
+        assert_eq!(report.conflicts[0].resolution, Resolution::Replaced);
+        assert!(
+            config
+                .find("g", "helper")
+                .unwrap()
+                .path
+                .starts_with(second.canonicalize().unwrap())
+        );
+    }
+
+    #[test]
+    fn test_sync_reapplies_directory_rules() {
+        let temp_dir = TempDir::new().unwrap();
+        let bin_dir = temp_dir.path().join("bin");
+        fs::create_dir_all(&bin_dir).unwrap();
+        let llvm = temp_dir.path().join("llvm");
+        write_script(&llvm.join("clang"));
+        write_script(&llvm.join("clang-tidy"));
+        write_script(&llvm.join("llvm-ar"));
+
+        let mut config = Config::new(bin_dir.clone());
+        let options = AddOptions {
+            rules: ScanRules {
+                include: vec!["clang*".to_string()],
+                exclude: vec!["*-tidy".to_string()],
+                suffix: Some("-18".to_string()),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        assert_eq!(
+            config
+                .add_with_options(GLOBAL_DEFAULT_GROUP_NAME, &llvm, &options)
+                .unwrap()
+                .added,
+            1
+        );
+        assert!(config.find(GLOBAL_DEFAULT_GROUP_NAME, "clang-18").is_some());
+        assert!(
+            config
+                .add_with_options(GLOBAL_DEFAULT_GROUP_NAME, llvm.join("clang"), &options)
+                .is_err()
+        );
+
+        // the rules are saved with the group
+        let path = temp_dir.path().join("config.json");
+        config.save(&path).unwrap();
+        let mut config = Config::load(&path).unwrap();
+
+        write_script(&llvm.join("clang-format"));
+        fs::remove_file(llvm.join("clang")).unwrap();
+        let report = config
+            .sync(GLOBAL_DEFAULT_GROUP_NAME, OnConflict::Skip)
+            .unwrap();
+        assert_eq!(report.added, vec!["clang-format-18"]);
+        assert_eq!(report.removed, vec!["clang-18"]);
+        assert!(bin_dir.join("clang-format-18").exists());
+        assert!(bin_dir.join("clang-18").symlink_metadata().is_err());
+        assert!(config.find(GLOBAL_DEFAULT_GROUP_NAME, "llvm-ar").is_none());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_readd_directory_with_symlinks() {
+        let temp_dir = TempDir::new().unwrap();
+        let bin_dir = temp_dir.path().join("bin");
+        fs::create_dir_all(&bin_dir).unwrap();
+        let python = temp_dir.path().join("python");
+        write_script(&python.join("python3.11"));
+        std::os::unix::fs::symlink("python3.11", python.join("python3")).unwrap();
+
+        let mut config = Config::new(bin_dir.clone());
+        let group = GLOBAL_DEFAULT_GROUP_NAME;
+        config
+            .add_with_options(group, &python, &AddOptions::default())
+            .unwrap();
+        config.set_enabled(group, "python3", false).unwrap();
+        // configs written by older versions hold canonical paths
+        let g = config.groups.get_mut(group).unwrap();
+        for b in g.bins.values_mut() {
+            b.path = b.path.canonicalize().unwrap();
+        }
+
+        let report = config
+            .add_with_options(group, &python, &AddOptions::default())
+            .unwrap();
+        assert!(report.conflicts.is_empty());
+        assert!(!config.find(group, "python3").unwrap().enabled);
+        let report = config.sync(group, OnConflict::Error).unwrap();
+        assert!(report.added.is_empty() && report.removed.is_empty());
+        assert!(report.conflicts.is_empty());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_non_utf8_file_names() {
+        use std::os::unix::ffi::OsStringExt;
+        let temp_dir = TempDir::new().unwrap();
+        let bin_dir = temp_dir.path().join("bin");
+        fs::create_dir_all(&bin_dir).unwrap();
+        let tools = temp_dir.path().join("tools");
+        let raw = OsString::from_vec(b"caf\xe9".to_vec());
+        write_script(&tools.join(&raw));
+        write_script(&tools.join("plain"));
+        // escapes like the name above, but is another file
+        write_script(&tools.join("caf\\xe9"));
+
+        let mut config = Config::new(bin_dir.clone());
+        assert_eq!(config.add("g", &tools, None).unwrap(), 3);
+        config.switch("g").unwrap();
+        assert!(bin_dir.join(&raw).symlink_metadata().is_ok());
+
+        let path = temp_dir.path().join("config.json");
+        config.save(&path).unwrap();
+        let mut config = Config::load(&path).unwrap();
+        let bin = config.find("g", "caf\\xe9").unwrap();
+        assert_eq!(bin.link_name(), raw);
+        assert_eq!(bin.path, tools.canonicalize().unwrap().join(&raw));
+        let literal = config.find("g", "caf\\\\xe9").unwrap();
+        assert_eq!(literal.link_name(), "caf\\xe9");
+        assert!(bin_dir.join("caf\\xe9").symlink_metadata().is_ok());
+
+        config.rename("g", "caf\\xe9", "cafe").unwrap();
+        assert!(bin_dir.join(&raw).symlink_metadata().is_err());
+        assert!(bin_dir.join("cafe").exists());
+    }
+
+    #[test]
+    fn test_source_dirs_include_dirs_without_rules() {
+        let temp_dir = TempDir::new().unwrap();
+        let bin_dir = temp_dir.path().join("bin");
+        let mut config = Config::new(bin_dir);
+        let mut legacy = Bin {
+            source_dir: Some(PathBuf::from("/opt/old")),
+            ..layered_bin("old", Origin::User)
+        };
+        let mut group = group_of(Origin::User, &[]);
+        group.bins.insert("old".to_string(), legacy.clone());
+        legacy.name = "older".to_string();
+        group.bins.insert("older".to_string(), legacy);
+        group.sources.push(SourceDir::new(
+            PathBuf::from("/opt/new"),
+            ScanRules::default(),
+            Origin::User,
+        ));
+        config.groups.insert("g".to_string(), group);
+        let mut system = group_of(Origin::System, &[]);
+        system.sources.push(SourceDir::new(
+            PathBuf::from("/opt/system"),
+            ScanRules::default(),
+            Origin::System,
+        ));
+        config.groups.insert("s".to_string(), system);
+
+        let dirs: Vec<(String, PathBuf)> = config
+            .source_dirs()
+            .into_iter()
+            .map(|(gn, s)| (gn, s.dir))
+            .collect();
+        assert_eq!(
+            dirs,
+            vec![
+                ("g".to_string(), PathBuf::from("/opt/new")),
+                ("g".to_string(), PathBuf::from("/opt/old")),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_numbered_references() {
+        let temp_dir = TempDir::new().unwrap();
+        let mut config = Config::new(temp_dir.path().join("bin"));
+        for gn in ["zig", "node18", "base"] {
+            config.set_extends(gn, Vec::new(), Vec::new()).unwrap();
+        }
+        config.groups.get_mut("zig").unwrap().bins = group_of(Origin::User, &["zig", "zls"]).bins;
+        config.groups.get_mut("node18").unwrap().bins =
+            group_of(Origin::User, &["node", "npm"]).bins;
+        config.active_group = "node18".to_string();
+        config.also_active = vec!["zig".to_string()];
+
+        // groups are numbered in the order they were created
+        let order: Vec<&String> = config
+            .ordered_groups()
+            .into_iter()
+            .map(|(gn, _)| gn)
+            .collect();
+        assert_eq!(order, ["zig", "node18", "base"]);
+        assert_eq!(config.group_ref("2").unwrap(), "node18");
+        assert_eq!(config.group_ref("base").unwrap(), "base");
+        assert!(config.group_ref("4").is_err());
+        let bin_ref = |group: Option<&str>, name: &str| config.bin_ref(group, name).unwrap();
+        let found = |gn: &str, bn: &str| Some((gn.to_string(), bn.to_string()));
+        assert_eq!(bin_ref(Some("zig"), "2"), found("zig", "zls"));
+        assert_eq!(bin_ref(Some("zig"), "zls"), None);
+        assert!(config.bin_ref(Some("zig"), "0").is_err());
+        // without a group, numbers go on through the active groups
+        assert_eq!(bin_ref(None, "2"), found("node18", "npm"));
+        assert_eq!(bin_ref(None, "4"), found("zig", "zls"));
+        assert_eq!(bin_ref(None, "zls"), None);
+        assert!(config.bin_ref(None, "5").is_err());
+
+        // older configs kept the group size in the index
+        let path = temp_dir.path().join("config.json");
+        config.groups.get_mut("node18").unwrap().index = 1;
+        config.groups.get_mut("base").unwrap().index = 1;
+        config.save(&path).unwrap();
+        let loaded = Config::load(&path).unwrap();
+        let order: Vec<&String> = loaded
+            .ordered_groups()
+            .into_iter()
+            .map(|(gn, _)| gn)
+            .collect();
+        assert_eq!(order, ["base", "node18", "zig"]);
+    }
+
+    #[test]
+    fn test_group_management() {
+        let temp_dir = TempDir::new().unwrap();
+        let bin_dir = temp_dir.path().join("bin");
+        fs::create_dir_all(&bin_dir).unwrap();
+        let mut config = Config::new(bin_dir);
+        config
+            .create_group(
+                "node16",
+                Some("old node".to_string()),
+                vec!["js".to_string()],
+            )
+            .unwrap();
+        config.groups.get_mut("node16").unwrap().bins = group_of(Origin::User, &["node"]).bins;
+        config
+            .set_extends("dev", vec!["node16".to_string()], Vec::new())
+            .unwrap();
+        config.active_group = "dev".to_string();
+        config.also_active = vec!["node16".to_string()];
+        assert!(config.create_group("dev", None, Vec::new()).is_err());
+
+        config.rename_group("node16", "node18").unwrap();
+        assert!(!config.group_exists("node16"));
+        assert_eq!(config.groups["dev"].extends, ["node18"]);
+        assert_eq!(config.active_groups(), ["dev", "node18"]);
+
+        config.copy_group("node18", "node20").unwrap();
+        config
+            .describe_group(
+                "node20",
+                Some(String::new()),
+                &["lts".to_string()],
+                &["js".to_string()],
+            )
+            .unwrap();
+        let copy = &config.groups["node20"];
+        assert!(copy.bins.contains_key("node"));
+        assert_eq!(copy.description, None);
+        assert_eq!(copy.tags, ["lts"]);
+        assert_eq!(
+            config.groups["node18"].description.as_deref(),
+            Some("old node")
+        );
+
+        assert!(config.delete_group("dev").is_err());
+        config.delete_group("node20").unwrap();
+        assert!(!config.group_exists("node20"));
+    }
+
+    #[test]
+    fn test_preview_activate() {
+        let temp_dir = TempDir::new().unwrap();
+        let bin_dir = temp_dir.path().join("bin");
+        fs::create_dir_all(&bin_dir).unwrap();
+        let mut config = Config::new(bin_dir.clone());
+        config
+            .groups
+            .insert("g1".to_string(), group_of(Origin::User, &["tool", "old"]));
+        let mut g2 = group_of(Origin::User, &["tool", "new"]);
+        g2.bins.get_mut("tool").unwrap().path = PathBuf::from("/g2/tool");
+        config.groups.insert("g2".to_string(), g2);
+        config.activate(&["g1".to_string()]).unwrap();
+        fs::write(bin_dir.join("new"), "mine").unwrap();
+
+        let preview = config
+            .preview_activate(&["g2".to_string(), "g1".to_string()])
+            .unwrap();
+        let names: Vec<&str> = preview.changes.iter().map(|c| c.name()).collect();
+        assert_eq!(names, ["new", "tool"]);
+        assert_eq!(preview.unchanged, 1);
+        assert_eq!(
+            preview.shadowed,
+            [Shadowed {
+                name: "tool".to_string(),
+                group: "g1".to_string(),
+                by: "g2".to_string(),
+            }]
+        );
+        assert_eq!(preview.foreign, [bin_dir.join("new")]);
+        // nothing was switched or linked
+        assert_eq!(config.active_groups(), ["g1"]);
+        assert_eq!(fs::read_to_string(bin_dir.join("new")).unwrap(), "mine");
+        assert!(config.preview_activate(&["g3".to_string()]).is_err());
+    }
+
+    #[test]
+    fn test_failed_switch_rolls_back() {
+        let temp_dir = TempDir::new().unwrap();
+        let bin_dir = temp_dir.path().join("bin");
+        fs::create_dir_all(&bin_dir).unwrap();
+        let mut config = Config::new(bin_dir.clone());
+        config
+            .groups
+            .insert("g1".to_string(), group_of(Origin::User, &["a", "b"]));
+        let mut g2 = group_of(Origin::User, &["a", "c", "d"]);
+        g2.bins.get_mut("a").unwrap().path = PathBuf::from("/g2/a");
+        config.groups.insert("g2".to_string(), g2);
+        config.activate(&["g1".to_string()]).unwrap();
+        let own_a = fs::read_link(bin_dir.join("a")).unwrap();
+
+        // a directory in the way of d makes the switch fail at some point
+        fs::create_dir(bin_dir.join("d")).unwrap();
+        assert!(config.activate(&["g2".to_string()]).is_err());
+        assert_eq!(config.active_groups(), ["g1"]);
+        assert_eq!(fs::read_link(bin_dir.join("a")).unwrap(), own_a);
+        assert!(bin_dir.join("b").symlink_metadata().is_ok());
+        assert!(bin_dir.join("c").symlink_metadata().is_err());
+        let mut names: Vec<_> = fs::read_dir(&bin_dir)
+            .unwrap()
+            .map(|e| e.unwrap().file_name().into_string().unwrap())
+            .collect();
+        names.sort();
+        assert_eq!(names, ["a", "b", "d"]);
+
+        // renaming onto the directory leaves the config untouched as well
+        config
+            .activate(&["g2".to_string(), "g1".to_string()])
+            .unwrap_err();
+        assert!(config.rename("g1", "b", "d").is_err());
+        assert!(config.groups["g1"].bins.contains_key("b"));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_install_active_replaces_links_of_other_groups() {
+        let temp_dir = TempDir::new().unwrap();
+        let bin_dir = temp_dir.path().join("bin");
+        fs::create_dir_all(&bin_dir).unwrap();
+        let mut config = Config::new(bin_dir.clone());
+        config
+            .groups
+            .insert("base".to_string(), group_of(Origin::User, &["tool"]));
+        let mut g2 = group_of(Origin::User, &["tool"]);
+        g2.bins.get_mut("tool").unwrap().path = PathBuf::from("/g2/tool");
+        config.groups.insert("g2".to_string(), g2);
+        // left by g2, e.g. in a profile copied from one with g2 active
+        std::os::unix::fs::symlink("/g2/tool", bin_dir.join("tool")).unwrap();
+
+        config.install_active().unwrap();
+        let own = config.groups["base"].bins["tool"].path.clone();
+        assert_eq!(fs::read_link(bin_dir.join("tool")).unwrap(), own);
+
+        fs::remove_file(bin_dir.join("tool")).unwrap();
+        std::os::unix::fs::symlink("/g2/tool", bin_dir.join("tool")).unwrap();
+        config.uninstall_active().unwrap();
+        assert!(bin_dir.join("tool").symlink_metadata().is_err());
+    }
+
+    #[test]
+    fn test_foreign_files_are_kept() {
+        let temp_dir = TempDir::new().unwrap();
+        let bin_dir = temp_dir.path().join("bin");
+        fs::create_dir_all(&bin_dir).unwrap();
+        let mut config = Config::new(bin_dir.clone());
+        config
+            .groups
+            .insert("g1".to_string(), group_of(Origin::User, &["a", "b"]));
+        fs::write(bin_dir.join("b"), "mine").unwrap();
+
+        let err = config.activate(&["g1".to_string()]).unwrap_err();
+        assert!(err.to_string().contains("use --force-links"));
+        assert_eq!(fs::read_to_string(bin_dir.join("b")).unwrap(), "mine");
+        assert!(bin_dir.join("a").symlink_metadata().is_err());
+
+        let b = &config.groups["g1"].bins["b"];
+        assert!(b.install(&bin_dir).is_err());
+        assert!(b.uninstall(&bin_dir).is_err());
+        assert_eq!(fs::read_to_string(bin_dir.join("b")).unwrap(), "mine");
+    }
 }
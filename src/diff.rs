@@ -0,0 +1,138 @@
+//! What switching from one group to another changes in the bin directory
+
+use anyhow::Result;
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use crate::config::Config;
+use crate::process::probe_version;
+
+/// A name both groups link, to different targets or versions
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Changed {
+    pub name: String,
+    pub from: PathBuf,
+    pub to: PathBuf,
+    /// First line of `--version` of both targets, if they were probed
+    pub versions: Option<(Option<String>, Option<String>)>,
+}
+
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct GroupDiff {
+    /// Names linked by the first group only, gone after switching
+    pub removed: Vec<(String, PathBuf)>,
+    /// Names linked by the second group only, new after switching
+    pub added: Vec<(String, PathBuf)>,
+    pub changed: Vec<Changed>,
+    /// How many names link the same target in both groups
+    pub unchanged: usize,
+}
+
+impl GroupDiff {
+    pub fn is_empty(&self) -> bool {
+        self.removed.is_empty() && self.added.is_empty() && self.changed.is_empty()
+    }
+}
+
+/// Compare the executables `from` and `to` link, inherited ones included.
+/// Disabled executables are not linked and count as missing. With
+/// `probe`, both targets of a changed name are run with `--version`.
+pub fn diff(conf: &Config, from: &str, to: &str, probe: bool) -> Result<GroupDiff> {
+    let linked = |group: &str| -> Result<BTreeMap<String, PathBuf>> {
+        if !conf.group_exists(group) {
+            anyhow::bail!("group {} does not exist", group);
+        }
+        Ok(conf
+            .resolve(group)?
+            .into_iter()
+            .filter(|(_, (_, b))| b.enabled)
+            .map(|(bn, (_, b))| (bn, b.path.clone()))
+            .collect())
+    };
+    let from_bins = linked(from)?;
+    let mut to_bins = linked(to)?;
+
+    let mut diff = GroupDiff::default();
+    for (name, from_path) in from_bins {
+        let Some(to_path) = to_bins.remove(&name) else {
+            diff.removed.push((name, from_path));
+            continue;
+        };
+        if from_path == to_path {
+            diff.unchanged += 1;
+            continue;
+        }
+        let versions = probe.then(|| {
+            let timeout = Duration::from_secs(2);
+            (
+                probe_version(&from_path, timeout),
+                probe_version(&to_path, timeout),
+            )
+        });
+        diff.changed.push(Changed {
+            name,
+            from: from_path,
+            to: to_path,
+            versions,
+        });
+    }
+    diff.added = to_bins.into_iter().collect();
+    Ok(diff)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Bin;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_diff_groups() {
+        let temp_dir = TempDir::new().unwrap();
+        let mut conf = Config::new(temp_dir.path().join("bin"));
+        for (gn, bins) in [
+            (
+                "node16",
+                vec![
+                    ("node", "/n16/node"),
+                    ("npm", "/n16/npm"),
+                    ("jq", "/bin/jq"),
+                ],
+            ),
+            (
+                "node18",
+                vec![("node", "/n18/node"), ("corepack", "/n18/corepack")],
+            ),
+        ] {
+            conf.set_extends(gn, Vec::new(), Vec::new()).unwrap();
+            for (bn, path) in bins {
+                let bin = Bin::new(bn.into(), PathBuf::from(path), None, conf.layer);
+                conf.groups
+                    .get_mut(gn)
+                    .unwrap()
+                    .bins
+                    .insert(bn.to_string(), bin);
+            }
+        }
+        // node18 inherits jq from node16 and leaves out npm
+        conf.set_extends(
+            "node18",
+            vec!["node16".to_string()],
+            vec!["npm".to_string()],
+        )
+        .unwrap();
+
+        let d = diff(&conf, "node16", "node18", false).unwrap();
+        assert_eq!(d.removed, [("npm".to_string(), PathBuf::from("/n16/npm"))]);
+        assert_eq!(
+            d.added,
+            [("corepack".to_string(), PathBuf::from("/n18/corepack"))]
+        );
+        assert_eq!(d.changed.len(), 1);
+        assert_eq!(d.changed[0].name, "node");
+        assert_eq!(d.unchanged, 1);
+        assert!(diff(&conf, "node16", "node16", false).unwrap().is_empty());
+        assert!(diff(&conf, "node16", "node20", false).is_err());
+    }
+}
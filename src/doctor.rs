@@ -0,0 +1,219 @@
+//! Checks of the bin directory against the config for `x doctor`
+
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::path::PathBuf;
+
+use crate::config::{Bin, Config};
+use crate::shell::is_dir_in_current_path;
+
+/// Something in the bin directory that does not match the config
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Problem {
+    BinDirMissing(PathBuf),
+    NotOnPath(PathBuf),
+    /// A group whose executables cannot be worked out
+    Unresolved {
+        group: String,
+        error: String,
+    },
+    /// An executable of the active groups without a symlink
+    Missing {
+        name: String,
+    },
+    /// A symlink of x pointing at another executable than the config says
+    WrongTarget {
+        name: String,
+        target: PathBuf,
+        expected: PathBuf,
+    },
+    /// An executable whose target no longer exists
+    Dangling {
+        name: String,
+        target: PathBuf,
+    },
+    /// A file or symlink that x did not create, which x refuses to replace
+    /// or remove without `--force-links`
+    Foreign(PathBuf),
+    /// A symlink of x no longer in the active groups
+    Stale(PathBuf),
+    /// A temporary link or a moved aside file left by an interrupted change
+    Leftover(PathBuf),
+}
+
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Problem::BinDirMissing(dir) => write!(f, "bin dir {} does not exist", dir.display()),
+            Problem::NotOnPath(dir) => write!(f, "bin dir {} is not on PATH", dir.display()),
+            Problem::Unresolved { group, error } => {
+                write!(f, "group {} cannot be resolved: {}", group, error)
+            }
+            Problem::Missing { name } => write!(f, "{} is not linked", name),
+            Problem::WrongTarget {
+                name,
+                target,
+                expected,
+            } => write!(
+                f,
+                "{} points at {} instead of {}",
+                name,
+                target.display(),
+                expected.display()
+            ),
+            Problem::Dangling { name, target } => {
+                write!(
+                    f,
+                    "{} points at {}, which does not exist",
+                    name,
+                    target.display()
+                )
+            }
+            Problem::Foreign(path) => write!(
+                f,
+                "{} was not created by x, use --force-links to replace it",
+                path.display()
+            ),
+            Problem::Stale(path) => write!(f, "{} is not in an active group", path.display()),
+            Problem::Leftover(path) => write!(f, "{} is left over", path.display()),
+        }
+    }
+}
+
+/// Compare the bin directory with the executables of the active groups
+pub fn check(conf: &Config) -> Vec<Problem> {
+    let mut problems = Vec::new();
+    for name in conf.groups.keys() {
+        if let Err(e) = conf.resolve(name) {
+            problems.push(Problem::Unresolved {
+                group: name.clone(),
+                error: e.to_string(),
+            });
+        }
+    }
+    if !conf.bin_dir.is_dir() {
+        problems.push(Problem::BinDirMissing(conf.bin_dir.clone()));
+        return problems;
+    }
+    if !is_dir_in_current_path(&conf.bin_dir) {
+        problems.push(Problem::NotOnPath(conf.bin_dir.clone()));
+    }
+
+    let linked = conf.linked_bins().unwrap_or_default();
+    let owned = conf.known_targets(&linked);
+    let mut expected: HashMap<_, &Bin> = linked
+        .values()
+        .map(|b| (b.link_name().to_os_string(), b))
+        .collect();
+
+    let mut entries: Vec<_> = fs::read_dir(&conf.bin_dir)
+        .into_iter()
+        .flatten()
+        .flatten()
+        .map(|e| e.path())
+        .collect();
+    entries.sort();
+    for path in entries {
+        let file_name = path.file_name().unwrap_or_default().to_os_string();
+        let name = file_name.to_string_lossy();
+        if name.starts_with(".x-tmp-") || name.starts_with(".x-backup-") {
+            problems.push(Problem::Leftover(path));
+            continue;
+        }
+        let target = fs::read_link(&path).ok();
+        let owned_target = target.as_ref().filter(|t| owned.contains(*t));
+        match (expected.remove(&file_name), owned_target) {
+            (_, None) => problems.push(Problem::Foreign(path)),
+            (None, Some(_)) => problems.push(Problem::Stale(path)),
+            (Some(bin), Some(target)) if *target != bin.path => {
+                problems.push(Problem::WrongTarget {
+                    name: bin.name.clone(),
+                    target: target.clone(),
+                    expected: bin.path.clone(),
+                })
+            }
+            (Some(bin), Some(target)) => {
+                if !target.exists() {
+                    problems.push(Problem::Dangling {
+                        name: bin.name.clone(),
+                        target: target.clone(),
+                    });
+                }
+            }
+        }
+    }
+    let mut missing: Vec<_> = expected.into_values().map(|b| b.name.clone()).collect();
+    missing.sort();
+    problems.extend(missing.into_iter().map(|name| Problem::Missing { name }));
+    problems
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Origin;
+    use tempfile::TempDir;
+
+    #[cfg(unix)]
+    #[test]
+    fn test_check_bin_dir() {
+        let temp_dir = TempDir::new().unwrap();
+        let bin_dir = temp_dir.path().join("bin");
+        fs::create_dir_all(&bin_dir).unwrap();
+        let existing = temp_dir.path().join("jq");
+        fs::write(&existing, "").unwrap();
+        let mut conf = Config::new(bin_dir.clone());
+        conf.set_extends("tools", Vec::new(), Vec::new()).unwrap();
+        for (name, path) in [
+            ("jq", existing.clone()),
+            ("node", PathBuf::from("/gone/node")),
+            ("npm", PathBuf::from("/gone/npm")),
+            ("old", PathBuf::from("/gone/old")),
+        ] {
+            let bin = Bin::new(name.into(), path, None, Origin::User);
+            let group = conf.groups.get_mut("tools").unwrap();
+            group.bins.insert(name.to_string(), bin);
+        }
+        conf.activate(&["tools".to_string()]).unwrap();
+        let symlink = |target: &str, name: &str| {
+            let path = bin_dir.join(name);
+            let _ = fs::remove_file(&path);
+            std::os::unix::fs::symlink(target, path).unwrap();
+        };
+        symlink("/gone/node", "npm");
+        // disabled without relinking, so its link is left behind
+        conf.groups
+            .get_mut("tools")
+            .unwrap()
+            .bins
+            .get_mut("old")
+            .unwrap()
+            .enabled = false;
+        fs::write(bin_dir.join("script"), "mine").unwrap();
+        fs::write(bin_dir.join(".x-backup-jq"), "").unwrap();
+
+        let problems = check(&conf);
+        let problems: Vec<_> = problems
+            .into_iter()
+            .filter(|p| !matches!(p, Problem::NotOnPath(_)))
+            .collect();
+        assert_eq!(
+            problems,
+            [
+                Problem::Leftover(bin_dir.join(".x-backup-jq")),
+                Problem::Dangling {
+                    name: "node".to_string(),
+                    target: PathBuf::from("/gone/node"),
+                },
+                Problem::WrongTarget {
+                    name: "npm".to_string(),
+                    target: PathBuf::from("/gone/node"),
+                    expected: PathBuf::from("/gone/npm"),
+                },
+                Problem::Stale(bin_dir.join("old")),
+                Problem::Foreign(bin_dir.join("script")),
+            ]
+        );
+    }
+}
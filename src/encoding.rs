@@ -0,0 +1,179 @@
+//! Lossless JSON encoding of file names and paths that are not valid
+//! UTF-8. Valid names are written as strings, the others as arrays of
+//! bytes.
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::ffi::{OsStr, OsString};
+use std::path::{Path, PathBuf};
+
+#[derive(Serialize, Deserialize)]
+#[serde(untagged)]
+enum Encoded {
+    Text(String),
+    Bytes(Vec<u8>),
+}
+
+impl Encoded {
+    fn new(s: &OsStr) -> Self {
+        match s.to_str() {
+            Some(text) => Encoded::Text(text.to_string()),
+            None => Encoded::Bytes(to_bytes(s)),
+        }
+    }
+
+    fn into_os_string(self) -> OsString {
+        match self {
+            Encoded::Text(text) => text.into(),
+            Encoded::Bytes(bytes) => from_bytes(bytes),
+        }
+    }
+}
+
+#[cfg(unix)]
+fn to_bytes(s: &OsStr) -> Vec<u8> {
+    use std::os::unix::ffi::OsStrExt;
+    s.as_bytes().to_vec()
+}
+
+#[cfg(not(unix))]
+fn to_bytes(s: &OsStr) -> Vec<u8> {
+    s.to_string_lossy().into_owned().into_bytes()
+}
+
+#[cfg(unix)]
+fn from_bytes(bytes: Vec<u8>) -> OsString {
+    use std::os::unix::ffi::OsStringExt;
+    OsString::from_vec(bytes)
+}
+
+#[cfg(not(unix))]
+fn from_bytes(bytes: Vec<u8>) -> OsString {
+    String::from_utf8_lossy(&bytes).into_owned().into()
+}
+
+/// A printable form of `s` where bytes that are not UTF-8 are written as
+/// `\xHH`
+pub fn escape(s: &OsStr) -> String {
+    match s.to_str() {
+        Some(text) => text.to_string(),
+        None => escape_bytes(s, false),
+    }
+}
+
+fn escape_bytes(s: &OsStr, backslashes: bool) -> String {
+    let bytes = to_bytes(s);
+    let mut escaped = String::new();
+    for chunk in bytes.utf8_chunks() {
+        if backslashes {
+            escaped.push_str(&chunk.valid().replace('\\', "\\\\"));
+        } else {
+            escaped.push_str(chunk.valid());
+        }
+        for b in chunk.invalid() {
+            escaped.push_str(&format!("\\x{:02x}", b));
+        }
+    }
+    escaped
+}
+
+/// The name a bin is known by and, when that is not the file name
+/// itself, the raw file name. Backslashes are escaped too, so that a file
+/// literally named `caf\xe9` does not take the name of one whose name
+/// ends with the byte 0xe9.
+pub fn bin_name(raw: OsString) -> (String, Option<OsString>) {
+    match raw.to_str() {
+        Some(name) if !name.contains('\\') => (name.to_string(), None),
+        _ => (escape_bytes(&raw, true), Some(raw)),
+    }
+}
+
+pub mod path {
+    use super::*;
+
+    pub fn serialize<S: Serializer>(path: &Path, serializer: S) -> Result<S::Ok, S::Error> {
+        Encoded::new(path.as_os_str()).serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<PathBuf, D::Error> {
+        Ok(Encoded::deserialize(deserializer)?.into_os_string().into())
+    }
+}
+
+pub mod option_path {
+    use super::*;
+
+    pub fn serialize<S: Serializer>(
+        path: &Option<PathBuf>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        path.as_ref()
+            .map(|p| Encoded::new(p.as_os_str()))
+            .serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Option<PathBuf>, D::Error> {
+        Ok(Option::<Encoded>::deserialize(deserializer)?.map(|e| e.into_os_string().into()))
+    }
+}
+
+pub mod option_os_string {
+    use super::*;
+
+    pub fn serialize<S: Serializer>(
+        s: &Option<OsString>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        s.as_deref().map(Encoded::new).serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Option<OsString>, D::Error> {
+        Ok(Option::<Encoded>::deserialize(deserializer)?.map(Encoded::into_os_string))
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use std::os::unix::ffi::OsStringExt;
+
+    #[derive(Serialize, Deserialize, PartialEq, Debug)]
+    struct Paths {
+        #[serde(with = "path")]
+        path: PathBuf,
+        #[serde(with = "option_os_string")]
+        name: Option<OsString>,
+    }
+
+    #[test]
+    fn test_non_utf8_round_trip() {
+        let raw = OsString::from_vec(b"caf\xe9".to_vec());
+        let paths = Paths {
+            path: PathBuf::from("/opt/bin").join(&raw),
+            name: Some(raw.clone()),
+        };
+        let json = serde_json::to_string(&paths).unwrap();
+        assert!(json.contains("[47,111,112,116"));
+        assert_eq!(serde_json::from_str::<Paths>(&json).unwrap(), paths);
+
+        let json = r#"{"path":"/opt/bin/node","name":null}"#;
+        let paths: Paths = serde_json::from_str(json).unwrap();
+        assert_eq!(paths.path, PathBuf::from("/opt/bin/node"));
+
+        assert_eq!(
+            bin_name(raw),
+            (
+                "caf\\xe9".to_string(),
+                Some(OsString::from_vec(b"caf\xe9".to_vec()))
+            )
+        );
+        assert_eq!(bin_name("node".into()), ("node".to_string(), None));
+        assert_eq!(
+            bin_name("caf\\xe9".into()),
+            ("caf\\\\xe9".to_string(), Some("caf\\xe9".into()))
+        );
+    }
+}
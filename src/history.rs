@@ -0,0 +1,346 @@
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::config::{Config, get_config_path, get_state_dir, load_config};
+
+/// Older changes are forgotten once the history holds this many
+const MAX_ENTRIES: usize = 100;
+
+/// One change of the config file, kept as the file contents before and
+/// after it
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "kebab-case")]
+pub struct Entry {
+    #[serde(skip)]
+    pub seq: usize,
+    /// Seconds since the Unix epoch
+    pub time: u64,
+    /// The command line that made the change
+    pub command: String,
+    pub before: String,
+    pub after: String,
+}
+
+/// The changes of the current config, stored as one file per change in
+/// the state directory. `head` names the last change that is applied;
+/// the ones after it were undone and can be redone until a new change is
+/// recorded.
+pub struct History {
+    dir: PathBuf,
+}
+
+impl History {
+    pub fn open() -> Result<Self> {
+        Self::open_in(get_state_dir()?.join("history"))
+    }
+
+    fn open_in(dir: PathBuf) -> Result<Self> {
+        fs::create_dir_all(&dir)?;
+        Ok(History { dir })
+    }
+
+    fn entry_path(&self, seq: usize) -> PathBuf {
+        self.dir.join(format!("{:06}.json", seq))
+    }
+
+    fn seqs(&self) -> Result<Vec<usize>> {
+        let mut seqs = Vec::new();
+        for entry in fs::read_dir(&self.dir)? {
+            let path = entry?.path();
+            if path.extension().is_some_and(|ext| ext == "json")
+                && let Some(seq) = path
+                    .file_stem()
+                    .and_then(|s| s.to_str())
+                    .and_then(|s| s.parse().ok())
+            {
+                seqs.push(seq);
+            }
+        }
+        seqs.sort();
+        Ok(seqs)
+    }
+
+    /// The last applied change, 0 if every change was undone
+    pub fn head(&self) -> Result<usize> {
+        match fs::read_to_string(self.dir.join("head")) {
+            Ok(head) => Ok(head.trim().parse()?),
+            Err(_) => Ok(self.seqs()?.last().copied().unwrap_or(0)),
+        }
+    }
+
+    fn set_head(&self, head: usize) -> Result<()> {
+        fs::write(self.dir.join("head"), head.to_string())?;
+        Ok(())
+    }
+
+    fn read(&self, seq: usize) -> Result<Entry> {
+        let bytes = fs::read(self.entry_path(seq))?;
+        let mut entry: Entry = serde_json::from_slice(&bytes)?;
+        entry.seq = seq;
+        Ok(entry)
+    }
+
+    /// Every recorded change, oldest first
+    pub fn entries(&self) -> Result<Vec<Entry>> {
+        self.seqs()?.into_iter().map(|seq| self.read(seq)).collect()
+    }
+
+    /// Record a change, dropping the changes that were undone
+    pub fn record(&self, command: String, before: String, after: String) -> Result<()> {
+        let head = self.head()?;
+        let mut seqs = self.seqs()?;
+        for seq in seqs.iter().filter(|seq| **seq > head) {
+            fs::remove_file(self.entry_path(*seq))?;
+        }
+        seqs.retain(|seq| *seq <= head);
+
+        let entry = Entry {
+            seq: head + 1,
+            time: now(),
+            command,
+            before,
+            after,
+        };
+        fs::write(
+            self.entry_path(entry.seq),
+            serde_json::to_vec_pretty(&entry)?,
+        )?;
+        seqs.push(entry.seq);
+        self.set_head(entry.seq)?;
+
+        while seqs.len() > MAX_ENTRIES {
+            fs::remove_file(self.entry_path(seqs.remove(0)))?;
+        }
+        Ok(())
+    }
+
+    /// Record a change, folded into the last applied change if that was
+    /// made by the same command and left the config as `before`, so that
+    /// a run of them is undone in one step
+    pub fn record_collapsed(&self, command: String, before: String, after: String) -> Result<()> {
+        let Some(mut last) = self
+            .undo_target()?
+            .filter(|last| last.command == command && last.after == before)
+        else {
+            return self.record(command, before, after);
+        };
+        for seq in self.seqs()?.into_iter().filter(|seq| *seq > last.seq) {
+            fs::remove_file(self.entry_path(seq))?;
+        }
+        last.time = now();
+        last.after = after;
+        fs::write(self.entry_path(last.seq), serde_json::to_vec_pretty(&last)?)?;
+        Ok(())
+    }
+
+    /// The change `undo` would revert
+    fn undo_target(&self) -> Result<Option<Entry>> {
+        let head = self.head()?;
+        if head == 0 || !self.entry_path(head).exists() {
+            return Ok(None);
+        }
+        self.read(head).map(Some)
+    }
+
+    /// The change `redo` would apply again
+    fn redo_target(&self) -> Result<Option<Entry>> {
+        let head = self.head()?;
+        match self.seqs()?.into_iter().find(|seq| *seq > head) {
+            Some(seq) => self.read(seq).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    fn mark_undone(&self, entry: &Entry) -> Result<()> {
+        let previous = self.seqs()?.into_iter().rev().find(|seq| *seq < entry.seq);
+        self.set_head(previous.unwrap_or(0))
+    }
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+/// Save `conf` to the config file and record the change in the history.
+/// Failing to record is only reported, the config is saved anyway.
+pub fn save(conf: &Config) -> Result<()> {
+    save_with(conf, History::record)
+}
+
+/// `save` for changes made again and again by one command, such as the
+/// syncs of `x watch`, which take a single entry while nothing else
+/// changes the config
+pub fn save_collapsed(conf: &Config) -> Result<()> {
+    save_with(conf, History::record_collapsed)
+}
+
+fn save_with(
+    conf: &Config,
+    record: impl FnOnce(&History, String, String, String) -> Result<()>,
+) -> Result<()> {
+    let path = get_config_path()?;
+    let before = fs::read_to_string(&path).ok();
+    conf.save(&path)?;
+    let after = fs::read_to_string(&path)?;
+    if let Some(before) = before
+        && before != after
+        && let Err(e) = History::open().and_then(|h| record(&h, command_line(), before, after))
+    {
+        eprintln!("Warning: cannot record the change in the history: {}", e);
+    }
+    Ok(())
+}
+
+fn command_line() -> String {
+    let args: Vec<String> = std::env::args_os()
+        .skip(1)
+        .map(|arg| arg.to_string_lossy().into_owned())
+        .collect();
+    format!("x {}", args.join(" "))
+}
+
+/// Revert up to `steps` changes, restoring the config file and the
+/// symlinks. Returns the reverted changes, latest first.
+pub fn undo(steps: usize) -> Result<Vec<Entry>> {
+    let history = History::open()?;
+    let path = get_config_path()?;
+    let mut undone = Vec::new();
+    for _ in 0..steps {
+        let Some(entry) = history.undo_target()? else {
+            break;
+        };
+        ensure_unchanged(&path, &entry.after, &entry)?;
+        restore(&path, &entry.before)?;
+        history.mark_undone(&entry)?;
+        undone.push(entry);
+    }
+    Ok(undone)
+}
+
+/// Apply up to `steps` undone changes again
+pub fn redo(steps: usize) -> Result<Vec<Entry>> {
+    let history = History::open()?;
+    let path = get_config_path()?;
+    let mut redone = Vec::new();
+    for _ in 0..steps {
+        let Some(entry) = history.redo_target()? else {
+            break;
+        };
+        ensure_unchanged(&path, &entry.before, &entry)?;
+        restore(&path, &entry.after)?;
+        history.set_head(entry.seq)?;
+        redone.push(entry);
+    }
+    Ok(redone)
+}
+
+/// Refuse to overwrite a config that was edited outside of x
+fn ensure_unchanged(path: &Path, expected: &str, entry: &Entry) -> Result<()> {
+    let current = fs::read_to_string(path)?;
+    if current != expected {
+        anyhow::bail!(
+            "the config file was changed outside of x since `{}`",
+            entry.command
+        );
+    }
+    Ok(())
+}
+
+/// Write `contents` to the config file and relink. If the symlinks cannot
+/// be changed, the previous contents are written back, so that the file
+/// and the history keep matching.
+fn restore(path: &Path, contents: &str) -> Result<()> {
+    let current = load_config(false)?;
+    let previous = fs::read_to_string(path)?;
+    fs::write(path, contents)?;
+    let relinked = load_config(false).and_then(|restored| restored.relink_from(&current));
+    if relinked.is_err() {
+        fs::write(path, previous)?;
+    }
+    relinked
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_record_undo_redo_positions() {
+        let temp_dir = TempDir::new().unwrap();
+        let history = History::open_in(temp_dir.path().join("history")).unwrap();
+        let change = |from: &str, to: &str| {
+            history
+                .record(format!("x {}", to), from.to_string(), to.to_string())
+                .unwrap()
+        };
+        change("a", "b");
+        change("b", "c");
+        assert_eq!(history.head().unwrap(), 2);
+
+        let entry = history.undo_target().unwrap().unwrap();
+        assert_eq!(entry.before, "b");
+        history.mark_undone(&entry).unwrap();
+        assert_eq!(history.redo_target().unwrap().unwrap().after, "c");
+
+        // a new change drops the undone one
+        change("b", "d");
+        assert!(history.redo_target().unwrap().is_none());
+        let commands: Vec<String> = history
+            .entries()
+            .unwrap()
+            .into_iter()
+            .map(|e| e.command)
+            .collect();
+        assert_eq!(commands, vec!["x b", "x d"]);
+
+        for _ in 0..2 {
+            let entry = history.undo_target().unwrap().unwrap();
+            history.mark_undone(&entry).unwrap();
+        }
+        assert_eq!(history.head().unwrap(), 0);
+        assert!(history.undo_target().unwrap().is_none());
+        assert_eq!(history.redo_target().unwrap().unwrap().after, "b");
+    }
+
+    #[test]
+    fn test_record_collapsed() {
+        let temp_dir = TempDir::new().unwrap();
+        let history = History::open_in(temp_dir.path().join("history")).unwrap();
+        let watch = |from: &str, to: &str| {
+            history
+                .record_collapsed("x watch".to_string(), from.to_string(), to.to_string())
+                .unwrap()
+        };
+        watch("a", "b");
+        watch("b", "c");
+        history
+            .record("x add".to_string(), "c".to_string(), "d".to_string())
+            .unwrap();
+        watch("d", "e");
+        // only a change that continues the last one is folded into it
+        watch("x", "y");
+        let changes: Vec<(String, String)> = history
+            .entries()
+            .unwrap()
+            .into_iter()
+            .map(|e| (e.before, e.after))
+            .collect();
+        let pair = |a: &str, b: &str| (a.to_string(), b.to_string());
+        assert_eq!(
+            changes,
+            [
+                pair("a", "c"),
+                pair("c", "d"),
+                pair("d", "e"),
+                pair("x", "y")
+            ]
+        );
+    }
+}
@@ -1,7 +1,23 @@
+pub mod apply;
+pub mod archive;
 pub mod cli;
 pub mod config;
+pub mod diff;
+pub mod doctor;
+pub mod encoding;
+pub mod history;
+pub mod links;
+pub mod listing;
+pub mod managers;
+pub mod manifest;
+pub mod picker;
 pub mod process;
+pub mod profile;
+pub mod scan;
+pub mod search;
 pub mod shell;
+pub mod versions;
+pub mod watch;
 
 use std::io::Write;
 pub fn confirm(message: &str) -> bool {
@@ -0,0 +1,352 @@
+//! Changes to the symlinks in the bin directory
+
+use anyhow::{Result, anyhow};
+use std::collections::{HashMap, HashSet};
+use std::ffi::OsString;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::config::Bin;
+
+/// One symlink to create, remove or point elsewhere
+#[derive(Debug, Clone)]
+pub enum LinkChange {
+    Create(Bin),
+    Remove(Bin),
+    Retarget { from: Bin, to: Bin },
+}
+
+impl LinkChange {
+    pub fn name(&self) -> &str {
+        match self {
+            LinkChange::Create(b) | LinkChange::Remove(b) => &b.name,
+            LinkChange::Retarget { to, .. } => &to.name,
+        }
+    }
+
+    /// The symlink in `bin_dir` the change touches
+    pub fn link_path(&self, bin_dir: &Path) -> PathBuf {
+        match self {
+            LinkChange::Create(b) | LinkChange::Remove(b) => bin_dir.join(b.link_name()),
+            LinkChange::Retarget { to, .. } => bin_dir.join(to.link_name()),
+        }
+    }
+}
+
+/// The changes that bring the symlinks of `before` to those of `after`,
+/// both as returned by `Config::linked_bins`, ordered by name. Names
+/// linking the same target in both are left out.
+pub fn changes(before: &HashMap<String, Bin>, after: &HashMap<String, Bin>) -> Vec<LinkChange> {
+    let mut changes = Vec::new();
+    for (bn, b) in before {
+        if !after.contains_key(bn) {
+            changes.push(LinkChange::Remove(b.clone()));
+        }
+    }
+    for (bn, b) in after {
+        match before.get(bn) {
+            None => changes.push(LinkChange::Create(b.clone())),
+            Some(old) if old.path != b.path => changes.push(LinkChange::Retarget {
+                from: old.clone(),
+                to: b.clone(),
+            }),
+            Some(_) => {}
+        }
+    }
+    changes.sort_by(|a, b| a.name().cmp(b.name()));
+    changes
+}
+
+/// An executable of an active group hidden by one of a group with a
+/// higher priority
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Shadowed {
+    pub name: String,
+    pub group: String,
+    pub by: String,
+}
+
+/// What switching to other groups would do, see `Config::preview_activate`
+#[derive(Debug, Default)]
+pub struct SwitchPreview {
+    pub changes: Vec<LinkChange>,
+    /// How many symlinks stay as they are
+    pub unchanged: usize,
+    /// Executables that become hidden by the switch
+    pub shadowed: Vec<Shadowed>,
+    /// Files in the bin directory that x did not create and would have to
+    /// replace or remove
+    pub foreign: Vec<PathBuf>,
+}
+
+/// What was at a path before a transaction changed it
+#[derive(Debug)]
+enum Previous {
+    Nothing,
+    Link(PathBuf),
+    /// A file that is not a symlink, moved aside
+    Moved(PathBuf),
+}
+
+/// Symlink changes in the bin directory that are undone together if one
+/// of them fails. Links are replaced by renaming a new link over them, so
+/// a name never goes missing while it is retargeted; links that already
+/// point at the right target are left alone. Files that x did not create
+/// are only replaced or removed when forced.
+pub struct Transaction<'a> {
+    bin_dir: &'a Path,
+    /// Targets of the symlinks x may have created
+    owned: &'a HashSet<PathBuf>,
+    force: bool,
+    /// Each path changed so far with what was there before
+    done: Vec<(PathBuf, Previous)>,
+}
+
+/// A file name in `bin_dir` next to `path`, for a temporary link or a
+/// file moved aside
+fn sibling(path: &Path, prefix: &str) -> PathBuf {
+    let mut name = OsString::from(prefix);
+    name.push(path.file_name().unwrap_or_default());
+    path.with_file_name(name)
+}
+
+/// Point `path` at `target` by renaming a new symlink over it
+pub(crate) fn link_atomically(target: &Path, path: &Path) -> Result<()> {
+    let tmp = sibling(path, ".x-tmp-");
+    if tmp.symlink_metadata().is_ok() {
+        fs::remove_file(&tmp)?;
+    }
+    #[cfg(unix)]
+    std::os::unix::fs::symlink(target, &tmp)?;
+    #[cfg(windows)]
+    std::os::windows::fs::symlink_file(target, &tmp)?;
+    if let Err(e) = fs::rename(&tmp, path) {
+        let _ = fs::remove_file(&tmp);
+        return Err(e.into());
+    }
+    Ok(())
+}
+
+impl<'a> Transaction<'a> {
+    pub fn new(bin_dir: &'a Path, owned: &'a HashSet<PathBuf>, force: bool) -> Self {
+        Transaction {
+            bin_dir,
+            owned,
+            force,
+            done: Vec::new(),
+        }
+    }
+
+    /// Note what is at `path` before the first change to it, moving a
+    /// file that is not a symlink out of the way. Fails if the file was
+    /// not created by x, unless forced.
+    fn record(&mut self, path: &Path, verb: &str) -> Result<()> {
+        if self.done.iter().any(|(p, _)| p == path) {
+            return Ok(());
+        }
+        let not_owned = || {
+            anyhow!(
+                "{} was not created by x, use --force-links to {} it",
+                path.display(),
+                verb
+            )
+        };
+        // symlink_metadata also sees links whose target is gone
+        let previous = match path.symlink_metadata() {
+            Err(_) => Previous::Nothing,
+            Ok(m) if m.file_type().is_symlink() => {
+                let target = fs::read_link(path)?;
+                if !self.force && !self.owned.contains(&target) {
+                    return Err(not_owned());
+                }
+                Previous::Link(target)
+            }
+            Ok(m) if m.is_dir() => anyhow::bail!("{} is a directory", path.display()),
+            Ok(_) if !self.force => return Err(not_owned()),
+            Ok(_) => {
+                let backup = sibling(path, ".x-backup-");
+                fs::rename(path, &backup)?;
+                Previous::Moved(backup)
+            }
+        };
+        self.done.push((path.to_path_buf(), previous));
+        Ok(())
+    }
+
+    pub fn remove(&mut self, bin: &Bin) -> Result<()> {
+        let path = self.bin_dir.join(bin.link_name());
+        self.record(&path, "remove")?;
+        if path.symlink_metadata().is_ok() {
+            fs::remove_file(&path)?;
+        }
+        Ok(())
+    }
+
+    /// Link `bin` unless it is disabled or already linked
+    pub fn install(&mut self, bin: &Bin) -> Result<()> {
+        if !bin.enabled {
+            return Ok(());
+        }
+        let path = self.bin_dir.join(bin.link_name());
+        if fs::read_link(&path).is_ok_and(|target| target == bin.path) {
+            return Ok(());
+        }
+        self.record(&path, "replace")?;
+        link_atomically(&bin.path, &path)
+    }
+
+    /// Keep the changes and delete the files they replaced. A leftover
+    /// file is harmless, so failing to delete one does not fail the
+    /// changes.
+    pub fn commit(self) {
+        for (_, previous) in self.done {
+            if let Previous::Moved(backup) = previous {
+                let _ = fs::remove_file(backup);
+            }
+        }
+    }
+
+    /// Undo the changes, newest first, putting back what they replaced
+    pub fn rollback(self) -> Result<()> {
+        for (path, previous) in self.done.into_iter().rev() {
+            match previous {
+                Previous::Link(target) => link_atomically(&target, &path)?,
+                Previous::Nothing => {
+                    if path.symlink_metadata().is_ok() {
+                        fs::remove_file(&path)?;
+                    }
+                }
+                Previous::Moved(backup) => {
+                    if path.symlink_metadata().is_ok() {
+                        fs::remove_file(&path)?;
+                    }
+                    fs::rename(backup, &path)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Run `changes` in a transaction, committing it if they succeed and
+    /// rolling it back otherwise
+    pub fn run(
+        bin_dir: &Path,
+        owned: &HashSet<PathBuf>,
+        force: bool,
+        changes: impl FnOnce(&mut Transaction) -> Result<()>,
+    ) -> Result<()> {
+        let mut tx = Transaction::new(bin_dir, owned, force);
+        match changes(&mut tx) {
+            Ok(()) => {
+                tx.commit();
+                Ok(())
+            }
+            Err(e) => {
+                if let Err(undo) = tx.rollback() {
+                    anyhow::bail!("{}, and cannot undo the changes made so far: {}", e, undo);
+                }
+                Err(e)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Origin;
+
+    #[test]
+    fn test_link_changes() {
+        let bins = |entries: &[(&str, &str)]| -> HashMap<String, Bin> {
+            entries
+                .iter()
+                .map(|(name, path)| {
+                    let bin = Bin::new((*name).into(), PathBuf::from(path), None, Origin::User);
+                    (name.to_string(), bin)
+                })
+                .collect()
+        };
+        let before = bins(&[
+            ("node", "/n16/node"),
+            ("npm", "/n16/npm"),
+            ("jq", "/bin/jq"),
+        ]);
+        let after = bins(&[
+            ("node", "/n18/node"),
+            ("jq", "/bin/jq"),
+            ("corepack", "/n18/cp"),
+        ]);
+        let summary: Vec<String> = changes(&before, &after)
+            .iter()
+            .map(|c| match c {
+                LinkChange::Create(b) => format!("+{}", b.name),
+                LinkChange::Remove(b) => format!("-{}", b.name),
+                LinkChange::Retarget { from, to } => {
+                    format!("~{} {}", from.path.display(), to.path.display())
+                }
+            })
+            .collect();
+        assert_eq!(summary, ["+corepack", "~/n16/node /n18/node", "-npm"]);
+        assert!(changes(&after, &after).is_empty());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_transaction_retargets_in_place() {
+        use std::os::unix::fs::MetadataExt;
+
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let bin_dir = temp_dir.path();
+        let bin =
+            |name: &str, path: &str| Bin::new(name.into(), PathBuf::from(path), None, Origin::User);
+        let inode = |name: &str| bin_dir.join(name).symlink_metadata().unwrap().ino();
+        let owned: HashSet<PathBuf> = ["/bin/jq", "/n16/node", "/n18/node"]
+            .iter()
+            .map(PathBuf::from)
+            .collect();
+        Transaction::run(bin_dir, &owned, false, |tx| {
+            tx.install(&bin("jq", "/bin/jq"))?;
+            tx.install(&bin("node", "/n16/node"))
+        })
+        .unwrap();
+        let jq = inode("jq");
+
+        Transaction::run(bin_dir, &owned, false, |tx| {
+            tx.install(&bin("jq", "/bin/jq"))?;
+            tx.install(&bin("node", "/n18/node"))
+        })
+        .unwrap();
+        // the identical link is not touched, no temporary links are left
+        assert_eq!(inode("jq"), jq);
+        assert_eq!(
+            fs::read_link(bin_dir.join("node")).unwrap(),
+            PathBuf::from("/n18/node")
+        );
+        assert_eq!(fs::read_dir(bin_dir).unwrap().count(), 2);
+
+        // a failure puts the old target back
+        fs::create_dir(bin_dir.join("npm")).unwrap();
+        Transaction::run(bin_dir, &owned, false, |tx| {
+            tx.install(&bin("node", "/n20/node"))?;
+            tx.install(&bin("npm", "/n20/npm"))
+        })
+        .unwrap_err();
+        assert_eq!(
+            fs::read_link(bin_dir.join("node")).unwrap(),
+            PathBuf::from("/n18/node")
+        );
+
+        // files x did not create are only replaced when forced
+        fs::write(bin_dir.join("rg"), "mine").unwrap();
+        let rg = bin("rg", "/bin/rg");
+        Transaction::run(bin_dir, &owned, false, |tx| tx.install(&rg)).unwrap_err();
+        Transaction::run(bin_dir, &owned, false, |tx| tx.remove(&rg)).unwrap_err();
+        assert_eq!(fs::read_to_string(bin_dir.join("rg")).unwrap(), "mine");
+        Transaction::run(bin_dir, &owned, true, |tx| tx.install(&rg)).unwrap();
+        assert_eq!(
+            fs::read_link(bin_dir.join("rg")).unwrap(),
+            PathBuf::from("/bin/rg")
+        );
+    }
+}
@@ -0,0 +1,262 @@
+//! Order and layout of the executables shown by `x list`
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::config::{Bin, get_state_dir};
+use crate::process::probe_version;
+
+/// How the executables of a group are ordered; ties are broken by name
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum SortOrder {
+    #[default]
+    Name,
+    Path,
+    /// Oldest first
+    Added,
+    /// Most recently run with `x run` first
+    Used,
+}
+
+/// A column shown in addition to the name and path
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum Column {
+    /// First line of `--version`
+    Version,
+    /// Size of the target file
+    Size,
+    /// Directory the executable was added from
+    Source,
+    /// Whether the target still exists
+    Exists,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct ListOptions {
+    pub origin: bool,
+    pub sort: SortOrder,
+    pub columns: Vec<Column>,
+    /// Group the executables by the directory they were added from
+    pub tree: bool,
+    /// Truncate paths to fit this many characters
+    pub width: Option<usize>,
+}
+
+/// When each executable was last run, keyed by target path, stored in the
+/// state directory
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct Usage {
+    last_used: HashMap<String, u64>,
+}
+
+impl Usage {
+    fn path() -> Result<PathBuf> {
+        Ok(get_state_dir()?.join("usage.json"))
+    }
+
+    /// The recorded usage, empty if none was recorded yet
+    pub fn load() -> Usage {
+        Self::path()
+            .ok()
+            .and_then(|path| fs::read(path).ok())
+            .and_then(|bytes| serde_json::from_slice(&bytes).ok())
+            .unwrap_or_default()
+    }
+
+    /// Note that `target` was just run
+    pub fn record(target: &Path) -> Result<()> {
+        let mut usage = Self::load();
+        usage
+            .last_used
+            .insert(target.to_string_lossy().into_owned(), now());
+        fs::write(Self::path()?, serde_json::to_vec(&usage)?)?;
+        Ok(())
+    }
+
+    pub fn last_used(&self, target: &Path) -> Option<u64> {
+        self.last_used
+            .get(target.to_string_lossy().as_ref())
+            .copied()
+    }
+}
+
+/// Seconds since the Unix epoch
+pub fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+/// The resolved bins of a group in `order`, so that their position is the
+/// same on every run
+pub fn sort_bins<'a>(
+    bins: HashMap<String, (&'a str, &'a Bin)>,
+    order: SortOrder,
+    usage: &Usage,
+) -> Vec<(String, (&'a str, &'a Bin))> {
+    let mut bins: Vec<_> = bins.into_iter().collect();
+    bins.sort_by(|a, b| a.0.cmp(&b.0));
+    // stable, so ties keep the name order
+    match order {
+        SortOrder::Name => {}
+        SortOrder::Path => bins.sort_by(|a, b| a.1.1.path.cmp(&b.1.1.path)),
+        SortOrder::Added => bins.sort_by_key(|(_, (_, b))| b.added),
+        SortOrder::Used => {
+            bins.sort_by_key(|(_, (_, b))| std::cmp::Reverse(usage.last_used(&b.path)))
+        }
+    }
+    bins
+}
+
+/// How long `x list -c version` waits for each executable
+const VERSION_TIMEOUT: Duration = Duration::from_millis(500);
+
+/// How many executables are asked for their version at once
+const PARALLEL_PROBES: usize = 8;
+
+/// The first line of `--version` of each of `paths` that answers it,
+/// probed a few at a time
+pub fn probe_versions<'a>(paths: impl IntoIterator<Item = &'a Path>) -> HashMap<PathBuf, String> {
+    let mut paths: Vec<&Path> = paths.into_iter().collect();
+    paths.sort();
+    paths.dedup();
+    let mut versions = HashMap::new();
+    for chunk in paths.chunks(PARALLEL_PROBES) {
+        std::thread::scope(|scope| {
+            let probes: Vec<_> = chunk
+                .iter()
+                .map(|path| scope.spawn(move || probe_version(path, VERSION_TIMEOUT)))
+                .collect();
+            for (path, probe) in chunk.iter().zip(probes) {
+                if let Ok(Some(version)) = probe.join() {
+                    versions.insert(path.to_path_buf(), version);
+                }
+            }
+        });
+    }
+    versions
+}
+
+/// The text of `column` for a bin, with the versions from
+/// `probe_versions`
+pub fn cell(column: Column, bin: &Bin, versions: &HashMap<PathBuf, String>) -> String {
+    match column {
+        Column::Version => versions
+            .get(&bin.path)
+            .cloned()
+            .unwrap_or_else(|| "-".to_string()),
+        Column::Size => fs::metadata(&bin.path)
+            .map(|m| human_size(m.len()))
+            .unwrap_or_else(|_| "-".to_string()),
+        Column::Source => bin
+            .source_dir
+            .as_ref()
+            .map(|d| d.display().to_string())
+            .unwrap_or_else(|| "-".to_string()),
+        Column::Exists => if bin.path.exists() { "yes" } else { "no" }.to_string(),
+    }
+}
+
+fn human_size(bytes: u64) -> String {
+    let mut size = bytes as f64;
+    for unit in ["B", "K", "M", "G"] {
+        if size < 1024.0 {
+            return if unit == "B" {
+                format!("{}{}", bytes, unit)
+            } else {
+                format!("{:.1}{}", size, unit)
+            };
+        }
+        size /= 1024.0;
+    }
+    format!("{:.1}T", size)
+}
+
+/// `text` cut to `width` characters, keeping the end that tells paths
+/// apart
+pub fn truncate_start(text: &str, width: usize) -> String {
+    let len = text.chars().count();
+    if len <= width {
+        return text.to_string();
+    }
+    let keep = width.saturating_sub(1);
+    let tail: String = text.chars().skip(len - keep).collect();
+    format!("…{}", tail)
+}
+
+/// Pad `text` to `width` characters
+pub fn pad(text: &str, width: usize) -> String {
+    let len = text.chars().count();
+    format!("{}{}", text, " ".repeat(width.saturating_sub(len)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Origin;
+
+    #[test]
+    fn test_sort_bins_is_stable() {
+        let mut bins = Vec::new();
+        for (name, path, added) in [
+            ("zsh", "/bin/zsh", 30),
+            ("awk", "/usr/bin/awk", 20),
+            ("cat", "/bin/cat", 20),
+        ] {
+            let mut bin = Bin::new(name.into(), PathBuf::from(path), None, Origin::User);
+            bin.added = added;
+            bins.push(bin);
+        }
+        let resolved = || -> HashMap<String, (&str, &Bin)> {
+            bins.iter().map(|b| (b.name.clone(), ("g", b))).collect()
+        };
+        let mut usage = Usage::default();
+        usage.last_used.insert("/bin/zsh".to_string(), 5);
+        let names = |order: SortOrder| -> Vec<String> {
+            sort_bins(resolved(), order, &usage)
+                .into_iter()
+                .map(|(n, _)| n)
+                .collect()
+        };
+        assert_eq!(names(SortOrder::Name), ["awk", "cat", "zsh"]);
+        assert_eq!(names(SortOrder::Path), ["cat", "zsh", "awk"]);
+        assert_eq!(names(SortOrder::Added), ["awk", "cat", "zsh"]);
+        assert_eq!(names(SortOrder::Used), ["zsh", "awk", "cat"]);
+
+        assert_eq!(truncate_start("/usr/local/bin/node", 10), "…/bin/node");
+        assert_eq!(truncate_start("/bin/ls", 10), "/bin/ls");
+        assert_eq!(human_size(512), "512B");
+        assert_eq!(human_size(1536), "1.5K");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_probe_versions_skips_scripts() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let paths: Vec<PathBuf> = (0..10)
+            .map(|i| format!("tool{}", i))
+            .chain(["tool.sh".to_string()])
+            .map(|name| {
+                let path = temp_dir.path().join(&name);
+                fs::write(
+                    &path,
+                    format!("#!/bin/sh\nsleep 0.2\necho '{} 1.0'\n", name),
+                )
+                .unwrap();
+                fs::set_permissions(&path, fs::Permissions::from_mode(0o755)).unwrap();
+                path
+            })
+            .collect();
+        let versions = probe_versions(paths.iter().map(PathBuf::as_path));
+        assert_eq!(versions.len(), 10);
+        assert_eq!(versions[&paths[3]], "tool3 1.0");
+        assert!(!versions.contains_key(&paths[10]));
+    }
+}
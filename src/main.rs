@@ -1,63 +1,118 @@
+use x::apply;
 use x::cli::*;
-use x::config::{Config, GLOBAL_DEFAULT_GROUP_NAME, get_config_path, load_config};
+use x::config::{
+    AddOptions, Config, Conflict, DEFAULT_PROFILE_NAME, GLOBAL_DEFAULT_GROUP_NAME, Origin,
+    PROJECT_CONFIG_FILE_NAME, Resolution, SyncReport, current_profile, find_project_config,
+    force_mode, get_config_path, get_state_dir, load_config, load_config_from,
+    load_config_with_project, merge_trusted_project_config, set_force_mode, set_profile,
+    set_system_mode, set_trusted, system_config_path, system_mode,
+};
 use x::confirm;
+use x::diff;
+use x::doctor;
+use x::history;
+use x::links::LinkChange;
+use x::listing::{ListOptions, Usage};
+use x::manifest::{Manifest, Roots};
+use x::picker::{Action, Mode, Picker};
 use x::process;
+use x::profile;
+use x::scan::ScanRules;
+use x::search::SearchOptions;
 use x::shell;
+use x::versions;
+use x::watch::{self, WatchEvent};
 
 use clap::Parser;
 use colored::Colorize;
 
-use std::path::Path;
+use std::io::IsTerminal;
+use std::path::{Path, PathBuf};
 use std::process::exit;
+use std::time::Duration;
 
 pub fn run(cmd: RunCommand) {
-    let conf_path = get_config_path().unwrap_or_else(|e| {
-        eprintln!("Error: cannot get config path: {}", e);
-        exit(1);
-    });
-
-    if !conf_path.exists() {
-        eprintln!(
-            "x is not configured yet. Please run `{}` first.",
-            "x init".green()
-        );
-        exit(1);
+    let program_given = cmd
+        .args
+        .as_ref()
+        .is_some_and(|args| !args.is_empty() && !args[0].is_empty());
+    // the picker saves what it changes, so the project config is only
+    // merged once it is done
+    let mut conf = if program_given {
+        load_config_with_project()
+    } else {
+        load_config(false)
     }
-
-    let conf = Config::load(&conf_path).unwrap_or_else(|e| {
-        eprintln!(
-            "Error: cannot load config file {}: {}",
-            conf_path.display(),
-            e
-        );
+    .unwrap_or_else(|e| {
+        eprintln!("Error: cannot load config: {}", e);
         exit(1);
     });
 
-    let args = cmd.args.unwrap_or_else(|| {
-        eprintln!("Error: No program specified");
-        exit(1);
-    });
+    let (group, args) = match cmd
+        .args
+        .filter(|args| !args.is_empty() && !args[0].is_empty())
+    {
+        Some(args) => (cmd.group, args),
+        None => {
+            if !is_interactive() {
+                eprintln!("Error: No program specified");
+                exit(1);
+            }
+            let picked = match pick(&mut conf, Mode::Bins) {
+                Action::Run { group, name } => (Some(group), vec![name]),
+                Action::Switch(group_name) => {
+                    switch_to(&mut conf, &[group_name]);
+                    return;
+                }
+                Action::Quit => return,
+            };
+            merge_trusted_project_config(&mut conf).unwrap_or_else(|e| {
+                eprintln!("Error: cannot load config: {}", e);
+                exit(1);
+            });
+            picked
+        }
+    };
+    let mut group = group;
+    let mut program = args[0].clone();
+    let args = &args[1..];
 
-    if args.is_empty() || args[0].is_empty() {
-        eprintln!("Error: No program specified");
-        exit(1);
+    // a number runs the executable `x list` shows with it
+    if let Some((group_name, name)) = bin_ref(&conf, group.as_deref(), &program) {
+        group = Some(group_name);
+        program = name;
     }
-
-    let program = &args[0];
-    let args = &args[1..];
+    let program = &program;
 
     if Path::new(program).is_absolute() {
         if !Path::new(program).exists() {
             eprintln!("Error: Program path {} does not exist", program.red());
             exit(1);
         }
-        run_and_monitor(program, args);
+        run_and_monitor(Path::new(program), args);
         return;
     }
 
-    let group_name = cmd.group.unwrap_or(conf.active_group.clone());
+    let (group_name, r) = match group {
+        Some(group_name) => {
+            let r = conf.find(&group_name, program);
+            (group_name, r)
+        }
+        // groups selected by version files come before the active groups
+        None => match version_groups(&conf)
+            .into_iter()
+            .find_map(|gn| {
+                let r = conf.find(&gn, program).filter(|r| r.enabled)?;
+                Some((gn, r))
+            })
+            .or_else(|| conf.find_active(program))
+        {
+            Some((group_name, r)) => (group_name, Some(r)),
+            None => (conf.active_group.clone(), None),
+        },
+    };
 
-    let r = conf.find(&group_name, program).unwrap_or_else(|| {
+    let r = r.unwrap_or_else(|| {
         eprintln!(
             "Error: Program {} not found in group {}",
             program.red(),
@@ -78,54 +133,61 @@ pub fn run(cmd: RunCommand) {
     if !Path::new(&r.path).exists() {
         eprintln!(
             "Error: Program path {} does not exist",
-            r.path.to_str().unwrap().green()
+            r.path.display().to_string().green()
         );
         exit(1);
     }
 
-    let path = r.path.to_str().unwrap();
-    run_and_monitor(path, args);
+    // only used to order `x list --sort used`, never worth failing a run
+    let _ = Usage::record(&r.path);
+    run_and_monitor(&r.path, args);
+}
+
+/// Whether a picker can be shown
+fn is_interactive() -> bool {
+    std::io::stdin().is_terminal() && std::io::stderr().is_terminal()
+}
+
+/// Show the picker, saving the executables it enabled or disabled
+fn pick(conf: &mut Config, mode: Mode) -> Action {
+    let mut picker = Picker::new(conf, mode);
+    let action = picker.run(conf).unwrap_or_else(|e| {
+        eprintln!("Error: cannot show the picker: {}", e);
+        exit(1);
+    });
+    if picker.changed {
+        history::save(conf).unwrap_or_else(|e| {
+            eprintln!("Error: cannot save config: {}", e);
+            exit(1);
+        });
+    }
+    action
 }
 
-fn run_and_monitor(program: &str, args: &[String]) {
+fn run_and_monitor(program: &Path, args: &[String]) {
     let run = process::Run::new(program, args);
     let exit_code = run.run_and_monitor();
     exit(exit_code.unwrap_or(1));
 }
 
-pub fn add(cmd: AddCommand) {
-    let conf_path = match get_config_path() {
-        Ok(p) => p,
-        Err(e) => {
-            eprintln!("Error: cannot get config path: {}", e);
-            std::process::exit(1);
-        }
-    };
-
-    // check if the config file exists
-    if !conf_path.exists() {
-        let conf = Config::default();
-        conf.save(&conf_path).unwrap_or_else(|e| {
-            eprintln!(
-                "Error: cannot create config file {}: {}",
-                conf_path.display(),
-                e
-            );
-            std::process::exit(1);
-        });
+fn print_conflicts(conflicts: &[Conflict]) {
+    for c in conflicts {
+        let outcome = match &c.resolution {
+            Resolution::Skipped => format!("skipped {}", c.incoming.display()),
+            Resolution::Replaced => format!("replaced {}", c.existing.display()),
+            Resolution::Renamed(new_name) => {
+                format!("added {} as {}", c.incoming.display(), new_name.green())
+            }
+        };
+        println!("Conflict: {} {}", c.name.yellow(), outcome);
     }
+}
 
-    let mut conf = match Config::load(&conf_path) {
-        Ok(c) => c,
-        Err(e) => {
-            eprintln!(
-                "Error: cannot load config file {}: {}",
-                conf_path.display(),
-                e
-            );
-            std::process::exit(1);
-        }
-    };
+pub fn add(cmd: AddCommand) {
+    let mut conf = load_config(true).unwrap_or_else(|e| {
+        eprintln!("Error: cannot load config: {}", e);
+        std::process::exit(1);
+    });
 
     if cmd.path.is_empty() {
         eprintln!("Error: No path specified");
@@ -134,23 +196,40 @@ pub fn add(cmd: AddCommand) {
 
     let group_name = cmd.group.unwrap_or(conf.active_group.clone());
 
-    let nbins = conf
-        .add(&group_name, &cmd.path, cmd.name)
+    let options = AddOptions {
+        name: cmd.name,
+        on_conflict: cmd.on_conflict,
+        rules: ScanRules {
+            include: cmd.include,
+            exclude: cmd.exclude,
+            rename: cmd.rename,
+            prefix: cmd.prefix,
+            suffix: cmd.suffix,
+            strip_ext: cmd.strip_ext,
+            recursive: cmd.recursive,
+            max_depth: cmd.max_depth,
+            follow_symlinks: cmd.follow_symlinks,
+        },
+    };
+    let report = conf
+        .add_with_options(&group_name, &cmd.path, &options)
         .unwrap_or_else(|e| {
             eprintln!("Error: cannot add path {}: {}", cmd.path, e);
             std::process::exit(1);
         });
 
-    conf.save(&conf_path).unwrap_or_else(|e| {
-        eprintln!(
-            "Error: cannot save config file {}: {}",
-            conf_path.display(),
-            e
-        );
+    history::save(&conf).unwrap_or_else(|e| {
+        eprintln!("Error: cannot save config: {}", e);
         std::process::exit(1);
     });
-    if nbins == 0 {
+
+    print_conflicts(&report.conflicts);
+
+    let nbins = report.added;
+    if nbins == 0 && report.conflicts.is_empty() {
         println!("No executables found in {}", cmd.path.green());
+    } else if nbins == 0 {
+        println!("No executables added to group {}", group_name.cyan());
     } else if nbins == 1 {
         println!(
             "Added {} executable to group {}",
@@ -163,15 +242,50 @@ pub fn add(cmd: AddCommand) {
 }
 
 pub fn list(cmd: ListCommand) {
-    let conf = load_config(false).unwrap_or_else(|e| {
+    let conf = load_config_with_project().unwrap_or_else(|e| {
         eprintln!("Error: cannot load config: {}", e);
         std::process::exit(1);
     });
 
+    // paths are cut to the width of the terminal, not when piped
+    let width = std::io::stdout()
+        .is_terminal()
+        .then(|| crossterm::terminal::size().ok())
+        .flatten()
+        .map(|(columns, _)| columns as usize)
+        .filter(|columns| *columns > 0);
+    let options = ListOptions {
+        origin: cmd.origin,
+        sort: cmd.sort,
+        columns: cmd.columns,
+        tree: cmd.tree,
+        width,
+    };
     if cmd.all {
-        conf.pretty_print(None);
+        conf.pretty_print(None, &options);
     } else {
-        conf.pretty_print(Some(&conf.active_group));
+        conf.pretty_print(Some(&conf.active_groups()), &options);
+    }
+
+    if options.origin {
+        let mut layers = Vec::new();
+        if !system_mode() && system_config_path().exists() {
+            layers.push((Origin::System, system_config_path()));
+        }
+        if let Ok(conf_path) = get_config_path() {
+            layers.push((conf.layer, conf_path));
+        }
+        if let Some(project_config) = &conf.project_config {
+            layers.push((Origin::Project, project_config.clone()));
+        }
+        println!();
+        for (origin, path) in layers {
+            println!(
+                "{}: {}",
+                origin.to_string().dimmed(),
+                path.display().to_string().yellow()
+            );
+        }
     }
 }
 
@@ -204,8 +318,17 @@ pub fn init(cmd: InitCommand) {
         });
     }
 
-    let conf = Config::default();
-    conf.save(&conf_path).unwrap_or_else(|e| {
+    let conf = match cmd.bin_dir {
+        Some(bin_dir) => {
+            std::fs::create_dir_all(&bin_dir).unwrap_or_else(|e| {
+                eprintln!("Error: cannot create bin dir {}: {}", bin_dir.display(), e);
+                std::process::exit(1);
+            });
+            Config::new(bin_dir)
+        }
+        None => Config::default(),
+    };
+    history::save(&conf).unwrap_or_else(|e| {
         eprintln!(
             "Error: cannot create config file {}: {}",
             conf_path.display(),
@@ -218,6 +341,17 @@ pub fn init(cmd: InitCommand) {
         conf_path.display().to_string().color(colored::Color::Green)
     );
 
+    if system_mode() {
+        // the shell config of root is not where other users pick up PATH
+        println!(
+            "\nAdd the following to a file sourced by every user, e.g. {}:",
+            "/etc/profile.d/x.sh".green()
+        );
+        let msg = format!("export PATH=\"{}:$PATH\"", conf.bin_dir.display());
+        println!("{}", msg.color(colored::Color::Green));
+        return;
+    }
+
     match shell::maybe_copy_current_executable_to_local_x() {
         Ok(Some(copied_path)) => {
             println!(
@@ -234,7 +368,14 @@ pub fn init(cmd: InitCommand) {
         }
     }
 
-    let bin_dir = conf.bin_dir.to_str().unwrap();
+    let Some(bin_dir) = conf.bin_dir.to_str() else {
+        // shell config files are edited as text
+        eprintln!(
+            "\nWarning: bin dir {} is not valid UTF-8, please add it to your PATH manually",
+            conf.bin_dir.display().to_string().yellow()
+        );
+        return;
+    };
 
     // Detect shell and automatically add PATH
     let shell_type = shell::detect_shell();
@@ -341,7 +482,7 @@ pub fn rm(cmd: RmCommand) {
             std::process::exit(1);
         });
 
-    conf.save(get_config_path().unwrap()).unwrap_or_else(|e| {
+    history::save(&conf).unwrap_or_else(|e| {
         eprintln!("Error: cannot save config: {}", e);
         std::process::exit(1);
     });
@@ -353,18 +494,132 @@ pub fn switch(cmd: SwitchCommand) {
         std::process::exit(1);
     });
 
-    let need_active_group = cmd.group.as_deref().unwrap_or(GLOBAL_DEFAULT_GROUP_NAME);
+    if (cmd.add || cmd.remove) && cmd.groups.is_empty() {
+        eprintln!("Error: No group specified");
+        std::process::exit(1);
+    }
+
+    // numbers name the groups `x list --all` shows with them
+    let named: Vec<String> = cmd
+        .groups
+        .iter()
+        .map(|gn| conf.group_ref(gn))
+        .collect::<anyhow::Result<_>>()
+        .unwrap_or_else(|e| {
+            eprintln!("Error: {}", e);
+            std::process::exit(1);
+        });
+    let groups = if cmd.add {
+        let mut active = conf.active_groups();
+        active.extend(named);
+        active
+    } else if cmd.remove {
+        conf.active_groups()
+            .into_iter()
+            .filter(|g| !named.contains(g))
+            .collect()
+    } else if cmd.groups.is_empty() && is_interactive() {
+        match pick(&mut conf, Mode::Groups) {
+            Action::Switch(group_name) => vec![group_name],
+            _ => return,
+        }
+    } else if cmd.groups.is_empty() {
+        vec![GLOBAL_DEFAULT_GROUP_NAME.to_string()]
+    } else {
+        named
+    };
+    if cmd.dry_run {
+        preview_switch(&conf, &groups);
+        return;
+    }
+    switch_to(&mut conf, &groups);
+}
+
+fn preview_switch(conf: &Config, groups: &[String]) {
+    let preview = conf.preview_activate(groups).unwrap_or_else(|e| {
+        eprintln!("Error: cannot switch group: {}", e);
+        std::process::exit(1);
+    });
+
+    println!("Switching to {} would:", groups.join(", ").green());
+    for change in &preview.changes {
+        match change {
+            LinkChange::Remove(b) => println!(
+                "  {} remove   {} {}",
+                "-".red().bold(),
+                b.name.red(),
+                format!("-> {}", b.path.display()).dimmed()
+            ),
+            LinkChange::Create(b) => println!(
+                "  {} create   {} {}",
+                "+".green().bold(),
+                b.name.green(),
+                format!("-> {}", b.path.display()).dimmed()
+            ),
+            LinkChange::Retarget { from, to } => println!(
+                "  {} retarget {}: {} -> {}",
+                "~".yellow().bold(),
+                to.name.yellow(),
+                from.path.display(),
+                to.path.display()
+            ),
+        }
+    }
+    println!("  {} symlink(s) unchanged", preview.unchanged);
+
+    let removed: Vec<&str> = preview
+        .changes
+        .iter()
+        .filter(|c| matches!(c, LinkChange::Remove(_)))
+        .map(|c| c.name())
+        .collect();
+    if removed.is_empty() && preview.shadowed.is_empty() && preview.foreign.is_empty() {
+        return;
+    }
+    println!("{}", "Warnings:".yellow().bold());
+    for name in removed {
+        println!("  {} will no longer be on PATH", name.yellow());
+    }
+    for s in &preview.shadowed {
+        println!(
+            "  {} of group {} will be shadowed by group {}",
+            s.name.yellow(),
+            s.group.cyan(),
+            s.by.cyan()
+        );
+    }
+    for path in &preview.foreign {
+        let verb = if force_mode() {
+            "will be replaced"
+        } else {
+            "will not be replaced without --force-links"
+        };
+        println!(
+            "  {} was not created by x and {}",
+            path.display().to_string().yellow(),
+            verb
+        );
+    }
+}
 
-    conf.switch(need_active_group).unwrap_or_else(|e| {
+fn switch_to(conf: &mut Config, groups: &[String]) {
+    conf.activate(groups).unwrap_or_else(|e| {
         eprintln!("Error: cannot switch group: {}", e);
         std::process::exit(1);
     });
 
-    conf.save(get_config_path().unwrap()).unwrap_or_else(|e| {
+    history::save(conf).unwrap_or_else(|e| {
         eprintln!("Error: cannot save config: {}", e);
         std::process::exit(1);
     });
-    println!("Switched to group {}", conf.active_group.green());
+    if conf.also_active.is_empty() {
+        println!("Switched to group {}", conf.active_group.green());
+    } else {
+        println!(
+            "Active groups by priority: {}",
+            conf.active_groups().join(", ").green()
+        );
+    }
 }
 
 pub fn rename(cmd: RenameCommand) {
@@ -381,7 +636,7 @@ pub fn rename(cmd: RenameCommand) {
             std::process::exit(1);
         });
 
-    conf.save(get_config_path().unwrap()).unwrap_or_else(|e| {
+    history::save(&conf).unwrap_or_else(|e| {
         eprintln!("Error: cannot save config: {}", e);
         std::process::exit(1);
     });
@@ -395,19 +650,17 @@ pub fn rename(cmd: RenameCommand) {
 }
 
 pub fn info(cmd: InfoCommand) {
-    let conf = load_config(false).unwrap_or_else(|e| {
+    let conf = load_config_with_project().unwrap_or_else(|e| {
         eprintln!("Error: cannot load config: {}", e);
         std::process::exit(1);
     });
 
-    let group_name = cmd.group.unwrap_or(conf.active_group.clone());
+    let (group_name, name) = group_and_bin(&conf, cmd.group, cmd.name);
 
-    let bin = conf
-        .get_bin_info(&group_name, &cmd.name)
-        .unwrap_or_else(|e| {
-            eprintln!("Error: {}", e);
-            std::process::exit(1);
-        });
+    let bin = conf.get_bin_info(&group_name, &name).unwrap_or_else(|e| {
+        eprintln!("Error: {}", e);
+        std::process::exit(1);
+    });
 
     println!("{}", "Executable Information".bold().cyan());
     println!("  {}: {}", "Name".bold(), bin.name.green());
@@ -438,7 +691,7 @@ pub fn info(cmd: InfoCommand) {
     println!(
         "  {}: {}",
         "In Active Group".bold(),
-        if conf.active_group == group_name {
+        if conf.is_active(&group_name) {
             "yes".green()
         } else {
             "no".yellow()
@@ -454,110 +707,1033 @@ pub fn info(cmd: InfoCommand) {
     );
 }
 
-pub fn enable(cmd: EnableCommand) {
-    let mut conf = load_config(false).unwrap_or_else(|e| {
+/// The group and executable numbered `name` by `x list`, if it is a number
+fn bin_ref(conf: &Config, group: Option<&str>, name: &str) -> Option<(String, String)> {
+    conf.bin_ref(group, name).unwrap_or_else(|e| {
+        eprintln!("Error: {}", e);
+        std::process::exit(1);
+    })
+}
+
+/// The group and name of the executable given by `-g` and `name`, where
+/// `name` may be its number in `x list`
+fn group_and_bin(conf: &Config, group: Option<String>, name: String) -> (String, String) {
+    bin_ref(conf, group.as_deref(), &name)
+        .unwrap_or_else(|| (group.unwrap_or(conf.active_group.clone()), name))
+}
+
+/// The config that can be changed, with the group and name of the
+/// executable given by `-g` and `name`. A number is resolved with the
+/// project config merged, as `x list` and `x run` do.
+fn writable_bin(group: Option<String>, name: String) -> (Config, String, String) {
+    let merged = load_config_with_project().unwrap_or_else(|e| {
+        eprintln!("Error: cannot load config: {}", e);
+        std::process::exit(1);
+    });
+    let (group_name, name) = group_and_bin(&merged, group, name);
+    if let (Some(project_config), Some(bin)) =
+        (&merged.project_config, merged.find(&group_name, &name))
+        && bin.origin == Origin::Project
+    {
+        eprintln!(
+            "Error: {} in group {} comes from the project config {}, change it there",
+            name.red(),
+            group_name.red(),
+            project_config.display()
+        );
+        std::process::exit(1);
+    }
+    let conf = load_config(false).unwrap_or_else(|e| {
         eprintln!("Error: cannot load config: {}", e);
         std::process::exit(1);
     });
+    (conf, group_name, name)
+}
 
-    let group_name = cmd.group.unwrap_or(conf.active_group.clone());
+pub fn enable(cmd: EnableCommand) {
+    let (mut conf, group_name, name) = writable_bin(cmd.group, cmd.name);
 
-    conf.set_enabled(&group_name, &cmd.name, true)
+    conf.set_enabled(&group_name, &name, true)
         .unwrap_or_else(|e| {
             eprintln!("Error: cannot enable executable: {}", e);
             std::process::exit(1);
         });
 
-    conf.save(get_config_path().unwrap()).unwrap_or_else(|e| {
+    history::save(&conf).unwrap_or_else(|e| {
         eprintln!("Error: cannot save config: {}", e);
         std::process::exit(1);
     });
 
-    println!(
-        "Enabled {} in group {}",
-        cmd.name.green(),
-        group_name.cyan()
-    );
+    println!("Enabled {} in group {}", name.green(), group_name.cyan());
 }
 
 pub fn disable(cmd: DisableCommand) {
-    let mut conf = load_config(false).unwrap_or_else(|e| {
-        eprintln!("Error: cannot load config: {}", e);
-        std::process::exit(1);
-    });
-
-    let group_name = cmd.group.unwrap_or(conf.active_group.clone());
+    let (mut conf, group_name, name) = writable_bin(cmd.group, cmd.name);
 
-    conf.set_enabled(&group_name, &cmd.name, false)
+    conf.set_enabled(&group_name, &name, false)
         .unwrap_or_else(|e| {
             eprintln!("Error: cannot disable executable: {}", e);
             std::process::exit(1);
         });
 
-    conf.save(get_config_path().unwrap()).unwrap_or_else(|e| {
+    history::save(&conf).unwrap_or_else(|e| {
         eprintln!("Error: cannot save config: {}", e);
         std::process::exit(1);
     });
 
-    println!(
-        "Disabled {} in group {}",
-        cmd.name.green(),
-        group_name.cyan()
-    );
+    println!("Disabled {} in group {}", name.green(), group_name.cyan());
 }
 
 pub fn search(cmd: SearchCommand) {
-    let conf = load_config(false).unwrap_or_else(|e| {
+    let conf = load_config_with_project().unwrap_or_else(|e| {
         eprintln!("Error: cannot load config: {}", e);
         std::process::exit(1);
     });
 
-    let results = conf.search(&cmd.query);
+    let query = cmd.query.join(" ");
+    let options = SearchOptions {
+        regex: cmd.regex,
+        enabled: cmd.enabled,
+        disabled: cmd.disabled,
+        missing: cmd.missing,
+        sort: cmd.sort,
+    };
+    let results = conf.search(&query, &options).unwrap_or_else(|e| {
+        eprintln!("Error: {}", e);
+        std::process::exit(1);
+    });
 
     if results.is_empty() {
-        println!("No executables found matching '{}'", cmd.query.yellow());
+        println!("No executables found matching '{}'", query.yellow());
         return;
     }
 
     println!(
         "Found {} executable(s) matching '{}':",
         results.len(),
-        cmd.query.yellow()
+        query.yellow()
     );
-    for (group_name, bin_name, bin) in results {
-        let status = if bin.enabled { "" } else { " [disabled]" };
-        let active = if conf.active_group == group_name {
-            "*"
-        } else {
-            " "
-        };
+    let shown = cmd.limit.unwrap_or(results.len());
+    for hit in results.iter().take(shown) {
+        let mut status = String::new();
+        if !hit.bin.enabled {
+            status.push_str(" [disabled]");
+        }
+        if !hit.bin.path.exists() {
+            status.push_str(" [missing]");
+        }
+        let active = if conf.is_active(&hit.group) { "*" } else { " " };
         println!(
             "  {} {} / {} -> {}{}",
             active.green().bold(),
-            group_name.cyan(),
-            bin_name.green(),
-            bin.path.display().to_string().green(),
+            hit.group.cyan(),
+            hit.name.green(),
+            hit.bin.path.display().to_string().green(),
             status.red()
         );
     }
+    if results.len() > shown {
+        println!("  ... {} more, see --limit", results.len() - shown);
+    }
 }
 
-pub static AVAILABLE_SUBCOMMANDS: &[&str] = &[
-    "run", "r", "add", "rm", "list", "ls", "init", "s", "switch", "rename", "info", "enable",
-    "disable", "search", "help",
-];
+pub fn extend(cmd: ExtendCommand) {
+    let mut conf = load_config(false).unwrap_or_else(|e| {
+        eprintln!("Error: cannot load config: {}", e);
+        std::process::exit(1);
+    });
 
-fn main() {
-    let mut args = std::env::args();
-    if let Some(first_arg) = args.nth(1)
-        && !AVAILABLE_SUBCOMMANDS.contains(&first_arg.as_str())
+    conf.set_extends(&cmd.group, cmd.parents.clone(), cmd.exclude)
+        .unwrap_or_else(|e| {
+            eprintln!("Error: cannot extend group: {}", e);
+            std::process::exit(1);
+        });
+
+    history::save(&conf).unwrap_or_else(|e| {
+        eprintln!("Error: cannot save config: {}", e);
+        std::process::exit(1);
+    });
+
+    if cmd.parents.is_empty() {
+        println!("Group {} no longer extends other groups", cmd.group.cyan());
+    } else {
+        println!(
+            "Group {} extends {}",
+            cmd.group.cyan(),
+            cmd.parents.join(", ").cyan()
+        );
+    }
+}
+
+fn print_sync_report(group_name: &str, report: &SyncReport) {
+    for dir in &report.missing_dirs {
+        eprintln!(
+            "Warning: {} no longer exists, its executables are kept",
+            dir.display().to_string().yellow()
+        );
+    }
+    print_conflicts(&report.conflicts);
+    for name in &report.added {
+        println!(
+            "{} {} in group {}",
+            "+".green(),
+            name.green(),
+            group_name.cyan()
+        );
+    }
+    for name in &report.removed {
+        println!(
+            "{} {} in group {}",
+            "-".red(),
+            name.red(),
+            group_name.cyan()
+        );
+    }
+}
+
+pub fn sync(cmd: SyncCommand) {
+    let mut conf = load_config(false).unwrap_or_else(|e| {
+        eprintln!("Error: cannot load config: {}", e);
+        std::process::exit(1);
+    });
+
+    let groups = if cmd.all {
+        let mut names: Vec<String> = conf.groups.keys().cloned().collect();
+        names.sort();
+        names
+    } else {
+        vec![cmd.group.unwrap_or(conf.active_group.clone())]
+    };
+
+    for group_name in &groups {
+        let report = conf.sync(group_name, cmd.on_conflict).unwrap_or_else(|e| {
+            eprintln!("Error: cannot sync group {}: {}", group_name, e);
+            std::process::exit(1);
+        });
+        print_sync_report(group_name, &report);
+        if report.added.is_empty() && report.removed.is_empty() && !cmd.all {
+            println!("Group {} is up to date", group_name.cyan());
+        }
+    }
+
+    history::save(&conf).unwrap_or_else(|e| {
+        eprintln!("Error: cannot save config: {}", e);
+        std::process::exit(1);
+    });
+}
+
+pub fn watch(cmd: WatchCommand) {
+    println!("Watching source directories, press Ctrl-C to stop");
+    let result = watch::watch(Duration::from_millis(cmd.debounce), |event| match event {
+        WatchEvent::Watching(dir) => {
+            println!("Watching {}", dir.display().to_string().green());
+        }
+        WatchEvent::Unwatchable(dir, e) => {
+            eprintln!(
+                "Warning: cannot watch {}: {}",
+                dir.display().to_string().yellow(),
+                e
+            );
+        }
+        WatchEvent::Synced(group_name, report) => print_sync_report(group_name, &report),
+        WatchEvent::Failed(group_name, e) => {
+            eprintln!("Error: cannot sync group {}: {}", group_name, e);
+        }
+    });
+    if let Err(e) = result {
+        eprintln!("Error: cannot watch source directories: {}", e);
+        std::process::exit(1);
+    }
+}
+
+/// How long ago `time`, in seconds since the Unix epoch, was
+fn format_age(time: u64) -> String {
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(time);
+    let secs = now.saturating_sub(time);
+    match secs {
+        0..60 => "just now".to_string(),
+        60..3600 => format!("{} min ago", secs / 60),
+        3600..86400 => format!("{} h ago", secs / 3600),
+        _ => format!("{} days ago", secs / 86400),
+    }
+}
+
+pub fn show_history(cmd: HistoryCommand) {
+    let history = history::History::open().unwrap_or_else(|e| {
+        eprintln!("Error: cannot open history: {}", e);
+        std::process::exit(1);
+    });
+    let (entries, head) = history
+        .entries()
+        .and_then(|entries| Ok((entries, history.head()?)))
+        .unwrap_or_else(|e| {
+            eprintln!("Error: cannot read history: {}", e);
+            std::process::exit(1);
+        });
+    if entries.is_empty() {
+        println!("No changes recorded yet");
+        return;
+    }
+    for entry in entries.iter().rev().take(cmd.limit) {
+        let age = format!("{:>12}", format_age(entry.time));
+        if entry.seq > head {
+            println!(
+                "  {} {} {} {}",
+                format!("{:4}", entry.seq).dimmed(),
+                age.dimmed(),
+                entry.command.dimmed(),
+                "(undone)".yellow()
+            );
+        } else if entry.seq == head {
+            println!(
+                "{} {:4} {} {}",
+                "*".green().bold(),
+                entry.seq,
+                age.dimmed(),
+                entry.command.cyan()
+            );
+        } else {
+            println!("  {:4} {} {}", entry.seq, age.dimmed(), entry.command);
+        }
+    }
+}
+
+pub fn undo(cmd: UndoCommand) {
+    let undone = history::undo(cmd.steps).unwrap_or_else(|e| {
+        eprintln!("Error: cannot undo: {}", e);
+        std::process::exit(1);
+    });
+    if undone.is_empty() {
+        println!("Nothing to undo");
+    }
+    for entry in undone {
+        println!("Undid {}", entry.command.cyan());
+    }
+}
+
+pub fn redo(cmd: RedoCommand) {
+    let redone = history::redo(cmd.steps).unwrap_or_else(|e| {
+        eprintln!("Error: cannot redo: {}", e);
+        std::process::exit(1);
+    });
+    if redone.is_empty() {
+        println!("Nothing to redo");
+    }
+    for entry in redone {
+        println!("Redid {}", entry.command.cyan());
+    }
+}
+
+pub fn export(cmd: ExportCommand) {
+    let conf = load_config(false).unwrap_or_else(|e| {
+        eprintln!("Error: cannot load config: {}", e);
+        std::process::exit(1);
+    });
+
+    let mut roots = Roots::parse(&cmd.roots).unwrap_or_else(|e| {
+        eprintln!("Error: {}", e);
+        std::process::exit(1);
+    });
+    if cmd.relative_home
+        && let Some(home_dir) = dirs::home_dir()
+    {
+        roots.insert("HOME", home_dir);
+    }
+
+    let groups = if cmd.all {
+        let mut names: Vec<String> = conf.groups.keys().cloned().collect();
+        names.sort();
+        names
+    } else if cmd.groups.is_empty() {
+        vec![conf.active_group.clone()]
+    } else {
+        cmd.groups
+    };
+
+    let contents = Manifest::export(&conf, &groups, &roots)
+        .and_then(|manifest| manifest.render(cmd.output.as_deref()))
+        .unwrap_or_else(|e| {
+            eprintln!("Error: cannot export groups: {}", e);
+            std::process::exit(1);
+        });
+
+    match &cmd.output {
+        Some(path) => {
+            std::fs::write(path, contents).unwrap_or_else(|e| {
+                eprintln!("Error: cannot write {}: {}", path.display(), e);
+                std::process::exit(1);
+            });
+            println!(
+                "Exported {} group(s) to {}",
+                groups.len(),
+                path.display().to_string().green()
+            );
+        }
+        None => print!("{}", contents),
+    }
+}
+
+pub fn import(cmd: ImportCommand) {
+    let mut conf = load_config(true).unwrap_or_else(|e| {
+        eprintln!("Error: cannot load config: {}", e);
+        std::process::exit(1);
+    });
+
+    let (manifest, roots) = read_manifest(&cmd.file, &cmd.roots);
+    let report = manifest
+        .import(&mut conf, &roots, cmd.on_conflict)
+        .unwrap_or_else(|e| {
+            eprintln!("Error: cannot import manifest: {}", e);
+            std::process::exit(1);
+        });
+
+    history::save(&conf).unwrap_or_else(|e| {
+        eprintln!("Error: cannot save config: {}", e);
+        std::process::exit(1);
+    });
+
+    print_conflicts(&report.conflicts);
+    for (group_name, path) in &report.missing {
+        eprintln!(
+            "Missing: {} for group {}",
+            path.display().to_string().yellow(),
+            group_name.cyan()
+        );
+    }
+    for error in &report.errors {
+        eprintln!("Error: {}", error);
+    }
+    println!(
+        "Imported {} executable(s) into {} group(s)",
+        report.added,
+        report.groups.len()
+    );
+    if !report.missing.is_empty() || !report.errors.is_empty() {
+        std::process::exit(1);
+    }
+}
+
+/// Read a manifest along with the roots its paths are resolved with,
+/// relative paths being relative to the manifest
+fn read_manifest(file: &Path, roots: &[String]) -> (Manifest, Roots) {
+    let mut roots = Roots::parse(roots).unwrap_or_else(|e| {
+        eprintln!("Error: {}", e);
+        std::process::exit(1);
+    });
+    if let Some(dir) = file.parent() {
+        roots = roots.with_base(dir);
+    }
+    let manifest = std::fs::read_to_string(file)
+        .map_err(anyhow::Error::from)
+        .and_then(|contents| Manifest::parse(file, &contents))
+        .unwrap_or_else(|e| {
+            eprintln!("Error: cannot read manifest {}: {}", file.display(), e);
+            std::process::exit(1);
+        });
+    (manifest, roots)
+}
+
+pub fn apply(cmd: ApplyCommand) {
+    let mut conf = load_config(true).unwrap_or_else(|e| {
+        eprintln!("Error: cannot load config: {}", e);
+        std::process::exit(1);
+    });
+
+    let (manifest, roots) = read_manifest(&cmd.file, &cmd.roots);
+    // a plan that is only shown must not unpack archives
+    let plan = apply::plan(&conf, &manifest, &roots, cmd.prune, !cmd.plan).unwrap_or_else(|e| {
+        eprintln!("Error: cannot apply manifest: {}", e);
+        std::process::exit(1);
+    });
+
+    if plan.changes.is_empty() {
+        println!("Nothing to do, the config matches {}", cmd.file.display());
+        return;
+    }
+    for change in &plan.changes {
+        let sign = change.sign().to_string();
+        let sign = match change.sign() {
+            '+' => sign.green(),
+            '-' => sign.red(),
+            _ => sign.yellow(),
+        };
+        println!("{} {}", sign, change);
+    }
+    let count = plan.changes.len();
+    if cmd.plan {
+        if plan
+            .changes
+            .iter()
+            .any(|c| matches!(c, apply::Change::Unpack { .. }))
+        {
+            println!("The executables of archives are listed once they are unpacked");
+        }
+        println!("{} change(s) planned, run without --plan to apply", count);
+        return;
+    }
+
+    plan.apply(&mut conf).unwrap_or_else(|e| {
+        eprintln!("Error: cannot apply manifest: {}", e);
+        std::process::exit(1);
+    });
+    history::save(&conf).unwrap_or_else(|e| {
+        eprintln!("Error: cannot save config: {}", e);
+        std::process::exit(1);
+    });
+    println!("Applied {} change(s)", count);
+}
+
+pub fn import_from(cmd: ImportFromCommand) {
+    let mut conf = load_config(true).unwrap_or_else(|e| {
+        eprintln!("Error: cannot load config: {}", e);
+        std::process::exit(1);
+    });
+
+    let root = cmd
+        .root
+        .map(Ok)
+        .unwrap_or_else(|| cmd.manager.default_root())
+        .unwrap_or_else(|e| {
+            eprintln!("Error: {}", e);
+            std::process::exit(1);
+        });
+    let installs = cmd.manager.discover(&root).unwrap_or_else(|e| {
+        eprintln!("Error: cannot find {} installs: {}", cmd.manager, e);
+        std::process::exit(1);
+    });
+    if installs.is_empty() {
+        println!("No {} installs found in {}", cmd.manager, root.display());
+        return;
+    }
+
+    let options = AddOptions {
+        on_conflict: cmd.on_conflict,
+        ..Default::default()
+    };
+    let mut failed = false;
+    for install in &installs {
+        match conf.add_with_options(&install.group, &install.dir, &options) {
+            Ok(report) => {
+                print_conflicts(&report.conflicts);
+                println!(
+                    "Added {} executable(s) to group {}",
+                    report.added,
+                    install.group.cyan()
+                );
+            }
+            Err(e) => {
+                eprintln!("Error: cannot import group {}: {}", install.group, e);
+                failed = true;
+            }
+        }
+    }
+
+    history::save(&conf).unwrap_or_else(|e| {
+        eprintln!("Error: cannot save config: {}", e);
+        std::process::exit(1);
+    });
+    if failed {
+        std::process::exit(1);
+    }
+}
+
+/// The groups selected by the version files around the working directory
+fn version_groups(conf: &Config) -> Vec<String> {
+    std::env::current_dir()
+        .map(|cwd| {
+            versions::select(conf, &cwd)
+                .into_iter()
+                .filter_map(|(_, group)| group)
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+pub fn show_versions(cmd: VersionsCommand) {
+    let mut conf = load_config(false).unwrap_or_else(|e| {
+        eprintln!("Error: cannot load config: {}", e);
+        std::process::exit(1);
+    });
+
+    if !cmd.set_patterns.is_empty() || !cmd.unset_patterns.is_empty() {
+        for def in &cmd.set_patterns {
+            let (tool, pattern) = def
+                .split_once('=')
+                .filter(|(tool, _)| !tool.is_empty())
+                .unwrap_or_else(|| {
+                    eprintln!("Error: invalid pattern {}, expected TOOL=PATTERN", def);
+                    std::process::exit(1);
+                });
+            versions::validate_pattern(pattern).unwrap_or_else(|e| {
+                eprintln!("Error: {}", e);
+                std::process::exit(1);
+            });
+            conf.version_patterns
+                .insert(tool.to_string(), pattern.to_string());
+        }
+        for tool in &cmd.unset_patterns {
+            conf.version_patterns.remove(tool);
+        }
+        history::save(&conf).unwrap_or_else(|e| {
+            eprintln!("Error: cannot save config: {}", e);
+            std::process::exit(1);
+        });
+    }
+
+    for (tool, pattern) in &conf.version_patterns {
+        println!("Pattern for {}: {}", tool.cyan(), pattern);
+    }
+    let cwd = std::env::current_dir().unwrap_or_else(|e| {
+        eprintln!("Error: cannot get current directory: {}", e);
+        std::process::exit(1);
+    });
+    let selected = versions::select(&conf, &cwd);
+    if selected.is_empty() {
+        println!("No version files found");
+        return;
+    }
+    for (request, group) in selected {
+        let group = match group {
+            Some(group) => group.green(),
+            None => "no group".red(),
+        };
+        println!(
+            "{} {} ({}) -> {}",
+            request.tool.cyan(),
+            request.version,
+            request.file.display(),
+            group
+        );
+    }
+}
+
+pub fn hook(cmd: HookCommand) {
+    let shell_type = match cmd.shell.as_deref() {
+        Some("bash") => shell::ShellType::Bash,
+        Some("zsh") => shell::ShellType::Zsh,
+        Some("fish") => shell::ShellType::Fish,
+        _ => shell::detect_shell(),
+    };
+    let script = std::env::current_exe()
+        .map_err(anyhow::Error::from)
+        .and_then(|exe| shell::hook_script(&shell_type, &exe))
+        .unwrap_or_else(|e| {
+            eprintln!("Error: cannot write the hook: {}", e);
+            std::process::exit(1);
+        });
+    print!("{}", script);
+}
+
+pub fn auto(cmd: AutoCommand) {
+    let conf = load_config(false).unwrap_or_else(|e| {
+        eprintln!("Error: cannot load config: {}", e);
+        std::process::exit(1);
+    });
+    let cwd = std::env::current_dir().unwrap_or_else(|e| {
+        eprintln!("Error: cannot get current directory: {}", e);
+        std::process::exit(1);
+    });
+
+    // only the shell running the hook puts the directory on its PATH, the
+    // active groups and the bin dir stay as they are
+    let groups = versions::selected_groups(&conf, &cwd);
+    let dir = (!groups.is_empty()).then(|| {
+        get_state_dir()
+            .map(|state_dir| versions::auto_dir(&state_dir, &groups))
+            .and_then(|dir| versions::link_auto_dir(&conf, &groups, &dir).map(|_| dir))
+            .unwrap_or_else(|e| {
+                eprintln!("Error: cannot link the selected groups: {}", e);
+                std::process::exit(1);
+            })
+    });
+    let previous = std::env::var_os("X_AUTO_DIR")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from);
+    if !cmd.quiet && previous != dir {
+        // stderr keeps the output out of command substitutions
+        if groups.is_empty() {
+            eprintln!("x: no groups selected here");
+        } else {
+            eprintln!(
+                "x: groups {} come first in this shell",
+                groups.join(", ").green()
+            );
+        }
+    }
+    if let Some(dir) = dir {
+        println!("{}", dir.display());
+    }
+}
+
+pub fn trust(cmd: TrustCommand) {
+    let project_config = std::env::current_dir()
+        .ok()
+        .and_then(|cwd| find_project_config(&cwd))
+        .unwrap_or_else(|| {
+            eprintln!(
+                "Error: there is no {} here or in a parent directory",
+                PROJECT_CONFIG_FILE_NAME
+            );
+            std::process::exit(1);
+        });
+    set_trusted(&project_config, !cmd.revoke).unwrap_or_else(|e| {
+        eprintln!("Error: cannot change the trusted project configs: {}", e);
+        std::process::exit(1);
+    });
+    if cmd.revoke {
+        println!("No longer trusting {}", project_config.display());
+    } else {
+        println!(
+            "Trusted {}, until it changes",
+            project_config.display().to_string().green()
+        );
+    }
+}
+
+pub fn diff(cmd: DiffCommand) {
+    let conf = load_config(false).unwrap_or_else(|e| {
+        eprintln!("Error: cannot load config: {}", e);
+        std::process::exit(1);
+    });
+    let group_ref = |name: &str| {
+        conf.group_ref(name).unwrap_or_else(|e| {
+            eprintln!("Error: {}", e);
+            std::process::exit(1);
+        })
+    };
+    let (from, to) = (group_ref(&cmd.from), group_ref(&cmd.to));
+
+    let d = diff::diff(&conf, &from, &to, cmd.versions).unwrap_or_else(|e| {
+        eprintln!("Error: {}", e);
+        std::process::exit(1);
+    });
+    if d.is_empty() {
+        println!(
+            "Groups {} and {} link the same {} executable(s)",
+            from.cyan(),
+            to.cyan(),
+            d.unchanged
+        );
+        return;
+    }
+
+    println!("Switching from {} to {}:", from.cyan(), to.cyan());
+    for (name, path) in &d.removed {
+        println!(
+            "  {} {} {}",
+            "-".red().bold(),
+            name.red(),
+            format!("({})", path.display()).dimmed()
+        );
+    }
+    for (name, path) in &d.added {
+        println!(
+            "  {} {} {}",
+            "+".green().bold(),
+            name.green(),
+            format!("({})", path.display()).dimmed()
+        );
+    }
+    for c in &d.changed {
+        println!(
+            "  {} {}: {} -> {}",
+            "~".yellow().bold(),
+            c.name.yellow(),
+            c.from.display(),
+            c.to.display()
+        );
+        if let Some((from_version, to_version)) = &c.versions {
+            let unknown = || "unknown".to_string();
+            let (from_version, to_version) = (
+                from_version.clone().unwrap_or_else(unknown),
+                to_version.clone().unwrap_or_else(unknown),
+            );
+            if from_version == to_version {
+                println!(
+                    "      {}",
+                    format!("same version: {}", from_version).dimmed()
+                );
+            } else {
+                println!("      {} -> {}", from_version.red(), to_version.green());
+            }
+        }
+    }
+    println!(
+        "{} removed, {} added, {} changed, {} unchanged",
+        d.removed.len(),
+        d.added.len(),
+        d.changed.len(),
+        d.unchanged
+    );
+}
+
+pub fn doctor(_cmd: DoctorCommand) {
+    let conf = load_config(false).unwrap_or_else(|e| {
+        eprintln!("Error: cannot load config: {}", e);
+        std::process::exit(1);
+    });
+    let problems = doctor::check(&conf);
+    if problems.is_empty() {
+        let msg = format!(
+            "Bin dir {} matches the active groups",
+            conf.bin_dir.display()
+        );
+        println!("{}", msg.color(colored::Color::Green));
+        return;
+    }
+    println!("{}", "Problems:".red().bold());
+    for problem in &problems {
+        println!("  {}", problem);
+    }
+    println!("{} problem(s) found", problems.len());
+    std::process::exit(1);
+}
+
+pub fn group(cmd: GroupCommand) {
+    let mut conf = load_config(false).unwrap_or_else(|e| {
+        eprintln!("Error: cannot load config: {}", e);
+        std::process::exit(1);
+    });
+    let group_ref = |conf: &Config, name: &str| {
+        conf.group_ref(name).unwrap_or_else(|e| {
+            eprintln!("Error: {}", e);
+            std::process::exit(1);
+        })
+    };
+
+    let (result, message) = match cmd.action {
+        GroupAction::New {
+            name,
+            description,
+            tags,
+        } => (
+            conf.create_group(&name, description, tags),
+            format!("Created group {}", name.cyan()),
+        ),
+        GroupAction::Cp { from, to } => {
+            let from = group_ref(&conf, &from);
+            (
+                conf.copy_group(&from, &to),
+                format!("Copied group {} to {}", from.cyan(), to.cyan()),
+            )
+        }
+        GroupAction::Mv { from, to } => {
+            let from = group_ref(&conf, &from);
+            (
+                conf.rename_group(&from, &to),
+                format!("Renamed group {} to {}", from.cyan(), to.cyan()),
+            )
+        }
+        GroupAction::Rm { name, yes } => {
+            let name = group_ref(&conf, &name);
+            let message = format!("Are you sure you want to delete group {}?", name.green());
+            if conf.group_exists(&name) && !yes && !confirm(&message) {
+                return;
+            }
+            (
+                conf.delete_group(&name),
+                format!("Deleted group {}", name.cyan()),
+            )
+        }
+        GroupAction::Describe {
+            name,
+            description,
+            tag,
+            untag,
+        } => {
+            let name = group_ref(&conf, &name);
+            (
+                conf.describe_group(&name, description, &tag, &untag),
+                format!("Updated group {}", name.cyan()),
+            )
+        }
+        GroupAction::Show { name } => {
+            let name = match name {
+                Some(name) => group_ref(&conf, &name),
+                None => conf.active_group.clone(),
+            };
+            show_group(&conf, &name);
+            return;
+        }
+    };
+    result.unwrap_or_else(|e| {
+        eprintln!("Error: {}", e);
+        std::process::exit(1);
+    });
+    history::save(&conf).unwrap_or_else(|e| {
+        eprintln!("Error: cannot save config: {}", e);
+        std::process::exit(1);
+    });
+    println!("{}", message);
+}
+
+fn show_group(conf: &Config, name: &str) {
+    let Some((number, g)) = conf
+        .ordered_groups()
+        .into_iter()
+        .zip(1..)
+        .find_map(|((gn, g), number)| (gn == name).then_some((number, g)))
+    else {
+        eprintln!("group {} does not exist", name.green());
+        std::process::exit(1);
+    };
+
+    println!("{}", "Group Information".bold().cyan());
+    println!("  {}: {}", "Name".bold(), name.cyan());
+    println!("  {}: {}", "Number".bold(), number);
+    if let Some(description) = &g.description {
+        println!("  {}: {}", "Description".bold(), description);
+    }
+    if !g.tags.is_empty() {
+        println!("  {}: {}", "Tags".bold(), g.tags.join(", ").green());
+    }
+    if g.created != 0 {
+        println!("  {}: {}", "Created".bold(), format_age(g.created));
+    }
+    println!("  {}: {}", "Config".bold(), g.origin);
+    if let Some(priority) = conf.active_groups().iter().position(|a| a == name) {
+        println!(
+            "  {}: priority {}",
+            "Active".bold(),
+            (priority + 1).to_string().green()
+        );
+    }
+    if !g.extends.is_empty() {
+        println!("  {}: {}", "Extends".bold(), g.extends.join(", ").cyan());
+    }
+    if !g.exclude.is_empty() {
+        println!("  {}: {}", "Excludes".bold(), g.exclude.join(", "));
+    }
+    for source in &g.sources {
+        println!(
+            "  {}: {}",
+            "Directory".bold(),
+            source.dir.display().to_string().green()
+        );
+    }
+    match conf.resolve(name) {
+        Ok(bins) => {
+            let disabled = bins.values().filter(|(_, b)| !b.enabled).count();
+            let inherited = bins.values().filter(|(p, _)| *p != name).count();
+            println!(
+                "  {}: {} ({} inherited, {} disabled)",
+                "Executables".bold(),
+                bins.len(),
+                inherited,
+                disabled
+            );
+        }
+        Err(e) => println!(
+            "  {}: {}",
+            "Executables".bold(),
+            format!("error: {}", e).red()
+        ),
+    }
+}
+
+pub fn profile(cmd: ProfileCommand) {
+    match cmd.action {
+        ProfileAction::List => {
+            let names = profile::list().unwrap_or_else(|e| {
+                eprintln!("Error: cannot list profiles: {}", e);
+                std::process::exit(1);
+            });
+            let current = current_profile().unwrap_or(DEFAULT_PROFILE_NAME);
+            for name in names {
+                if name == current {
+                    println!("{} {}", "*".green().bold(), name.cyan().bold());
+                } else {
+                    println!("  {}", name.cyan());
+                }
+            }
+        }
+        ProfileAction::Create { name } => {
+            let conf = profile::create(&name).unwrap_or_else(|e| {
+                eprintln!("Error: cannot create profile: {}", e);
+                std::process::exit(1);
+            });
+            println!("Created profile {}", name.cyan());
+            println!(
+                "Add {} to your PATH to use its executables",
+                conf.bin_dir.display().to_string().green()
+            );
+        }
+        ProfileAction::Delete { name, yes } => {
+            let exists = profile::exists(&name).unwrap_or_else(|e| {
+                eprintln!("Error: {}", e);
+                std::process::exit(1);
+            });
+            if !exists {
+                eprintln!("profile {} does not exist", name.green());
+                std::process::exit(1);
+            }
+            let message = format!("Are you sure you want to delete profile {}?", name.green());
+            if !yes && !confirm(&message) {
+                return;
+            }
+            profile::delete(&name).unwrap_or_else(|e| {
+                eprintln!("Error: cannot delete profile: {}", e);
+                std::process::exit(1);
+            });
+            println!("Deleted profile {}", name.cyan());
+        }
+        ProfileAction::Copy { from, to } => {
+            let conf = profile::copy(&from, &to).unwrap_or_else(|e| {
+                eprintln!("Error: cannot copy profile: {}", e);
+                std::process::exit(1);
+            });
+            println!("Copied profile {} to {}", from.cyan(), to.cyan());
+            println!(
+                "Add {} to your PATH to use its executables",
+                conf.bin_dir.display().to_string().green()
+            );
+        }
+    }
+}
+
+fn apply_global_args(global: GlobalArgs) {
+    set_profile(global.profile)
+        .and_then(|_| set_system_mode(global.system))
+        .and_then(|_| set_force_mode(global.force_links))
+        .unwrap_or_else(|e| {
+            eprintln!("Error: {}", e);
+            exit(1);
+        });
+}
+
+/// Whether an executable of the active groups of `profile` is called
+/// `name`, looked up before the profile is selected
+fn is_active_bin(profile: Option<&str>, name: &str) -> bool {
+    profile::config_path(profile.unwrap_or(DEFAULT_PROFILE_NAME))
+        .ok()
+        .flatten()
+        .and_then(|path| load_config_from(&path, false).ok())
+        .is_some_and(|conf| conf.find_active(name).is_some())
+}
+
+fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if is_short_run(&args) {
+        let short_run = ShortRunCommand::parse();
+        apply_global_args(short_run.global);
+        run(short_run.run);
+        return;
+    }
+    // a newer subcommand gives way to an executable of the same name
+    if let Some(name) = newer_subcommand(&args)
+        && let Ok(short_run) = ShortRunCommand::try_parse()
+        && is_active_bin(short_run.global.profile.as_deref(), name)
     {
-        let run_cmd = RunCommand::parse();
-        run(run_cmd);
+        apply_global_args(short_run.global);
+        run(short_run.run);
         return;
     }
 
     let cli = Cli::parse();
+    apply_global_args(cli.global);
     match cli.command {
         Commands::Run(r) => run(r),
         Commands::Add(a) => add(a),
@@ -570,5 +1746,23 @@ fn main() {
         Commands::Enable(e) => enable(e),
         Commands::Disable(d) => disable(d),
         Commands::Search(s) => search(s),
+        Commands::Profile(p) => profile(p),
+        Commands::Group(g) => group(g),
+        Commands::Diff(d) => diff(d),
+        Commands::Doctor(d) => doctor(d),
+        Commands::Extend(e) => extend(e),
+        Commands::Sync(s) => sync(s),
+        Commands::Watch(w) => watch(w),
+        Commands::History(h) => show_history(h),
+        Commands::Undo(u) => undo(u),
+        Commands::Redo(r) => redo(r),
+        Commands::Export(e) => export(e),
+        Commands::Import(i) => import(i),
+        Commands::Apply(a) => apply(a),
+        Commands::ImportFrom(i) => import_from(i),
+        Commands::Versions(v) => show_versions(v),
+        Commands::Hook(h) => hook(h),
+        Commands::Auto(a) => auto(a),
+        Commands::Trust(t) => trust(t),
     }
 }
@@ -0,0 +1,194 @@
+//! Toolchains installed by other version managers, found on disk so that
+//! each version can become a group
+
+use anyhow::{Result, anyhow};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// A version manager whose installs `x import-from` understands
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum Manager {
+    /// Node.js versions in `~/.nvm/versions/node`
+    Nvm,
+    /// Python versions in `~/.pyenv/versions`
+    Pyenv,
+    /// Rust toolchains in `~/.rustup/toolchains`
+    Rustup,
+    /// SDKs in `~/.sdkman/candidates`
+    Sdkman,
+    /// The base environment and the environments of a conda install
+    Conda,
+}
+
+impl std::fmt::Display for Manager {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Manager::Nvm => write!(f, "nvm"),
+            Manager::Pyenv => write!(f, "pyenv"),
+            Manager::Rustup => write!(f, "rustup"),
+            Manager::Sdkman => write!(f, "sdkman"),
+            Manager::Conda => write!(f, "conda"),
+        }
+    }
+}
+
+/// One installed version and the directory holding its executables
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Install {
+    /// Name of the group created for it, e.g. `node-20.11.0`
+    pub group: String,
+    pub dir: PathBuf,
+}
+
+impl Manager {
+    /// Where the manager keeps its installs, honouring the environment
+    /// variable the manager itself uses
+    pub fn default_root(&self) -> Result<PathBuf> {
+        let home = dirs::home_dir().ok_or_else(|| anyhow!("cannot get home dir"))?;
+        let from_env = |var: &str| std::env::var_os(var).filter(|v| !v.is_empty());
+        let root = match self {
+            Manager::Nvm => from_env("NVM_DIR").map(PathBuf::from),
+            Manager::Pyenv => from_env("PYENV_ROOT").map(PathBuf::from),
+            Manager::Rustup => from_env("RUSTUP_HOME").map(PathBuf::from),
+            Manager::Sdkman => from_env("SDKMAN_DIR").map(PathBuf::from),
+            // CONDA_EXE is <root>/bin/conda
+            Manager::Conda => from_env("CONDA_EXE")
+                .map(PathBuf::from)
+                .and_then(|exe| Some(exe.parent()?.parent()?.to_path_buf()))
+                .or_else(|| {
+                    ["miniconda3", "anaconda3", "miniforge3", "mambaforge"]
+                        .iter()
+                        .map(|dir| home.join(dir))
+                        .find(|dir| dir.is_dir())
+                }),
+        };
+        Ok(root.unwrap_or_else(|| match self {
+            Manager::Nvm => home.join(".nvm"),
+            Manager::Pyenv => home.join(".pyenv"),
+            Manager::Rustup => home.join(".rustup"),
+            Manager::Sdkman => home.join(".sdkman"),
+            Manager::Conda => home.join("miniconda3"),
+        }))
+    }
+
+    /// Every install below `root`, sorted by group name. Installs without
+    /// a `bin` directory are left out.
+    pub fn discover(&self, root: &Path) -> Result<Vec<Install>> {
+        if !root.is_dir() {
+            anyhow::bail!("{} is not installed in {}", self, root.display());
+        }
+        let mut installs = Vec::new();
+        match self {
+            Manager::Nvm => {
+                for (version, dir) in subdirs(&root.join("versions").join("node"))? {
+                    let version = version.strip_prefix('v').unwrap_or(&version);
+                    installs.push(install(format!("node-{}", version), &dir));
+                }
+            }
+            Manager::Pyenv => {
+                for (version, dir) in subdirs(&root.join("versions"))? {
+                    installs.push(install(format!("python-{}", version), &dir));
+                }
+            }
+            Manager::Rustup => {
+                for (toolchain, dir) in subdirs(&root.join("toolchains"))? {
+                    installs.push(install(format!("rust-{}", toolchain), &dir));
+                }
+            }
+            Manager::Sdkman => {
+                for (candidate, candidate_dir) in subdirs(&root.join("candidates"))? {
+                    for (version, dir) in subdirs(&candidate_dir)? {
+                        // `current` links to one of the versions
+                        if version == "current" {
+                            continue;
+                        }
+                        installs.push(install(format!("{}-{}", candidate, version), &dir));
+                    }
+                }
+            }
+            Manager::Conda => {
+                installs.push(install("conda-base".to_string(), root));
+                for (env, dir) in subdirs(&root.join("envs"))? {
+                    installs.push(install(format!("conda-{}", env), &dir));
+                }
+            }
+        }
+        installs.retain(|i| i.dir.is_dir());
+        installs.sort_by(|a, b| a.group.cmp(&b.group));
+        Ok(installs)
+    }
+}
+
+fn install(group: String, dir: &Path) -> Install {
+    Install {
+        group,
+        dir: dir.join("bin"),
+    }
+}
+
+/// The directories in `dir` by name, nothing if `dir` does not exist.
+/// Names that are not UTF-8 cannot be group names and are skipped.
+fn subdirs(dir: &Path) -> Result<Vec<(String, PathBuf)>> {
+    if !dir.is_dir() {
+        return Ok(Vec::new());
+    }
+    let mut dirs = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if !path.is_dir() {
+            continue;
+        }
+        if let Some(name) = path.file_name().and_then(|n| n.to_str()) {
+            dirs.push((name.to_string(), path.clone()));
+        }
+    }
+    dirs.sort();
+    Ok(dirs)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_discover_installs() {
+        let temp_dir = TempDir::new().unwrap();
+        let root = temp_dir.path();
+        for dir in [
+            "nvm/versions/node/v20.11.0/bin",
+            "nvm/versions/node/v18.19.0/bin",
+            "sdkman/candidates/java/17.0.2-tem/bin",
+            "sdkman/candidates/java/current/bin",
+            "sdkman/candidates/maven/3.9.6/bin",
+            "conda/bin",
+            "conda/envs/ml/bin",
+            "conda/envs/broken",
+        ] {
+            fs::create_dir_all(root.join(dir)).unwrap();
+        }
+
+        let groups = |manager: Manager, dir: &str| -> Vec<String> {
+            manager
+                .discover(&root.join(dir))
+                .unwrap()
+                .into_iter()
+                .map(|i| i.group)
+                .collect()
+        };
+        assert_eq!(
+            groups(Manager::Nvm, "nvm"),
+            ["node-18.19.0", "node-20.11.0"]
+        );
+        assert_eq!(
+            groups(Manager::Sdkman, "sdkman"),
+            ["java-17.0.2-tem", "maven-3.9.6"]
+        );
+        assert_eq!(groups(Manager::Conda, "conda"), ["conda-base", "conda-ml"]);
+        assert_eq!(
+            Manager::Nvm.discover(&root.join("nvm")).unwrap()[0].dir,
+            root.join("nvm/versions/node/v18.19.0/bin")
+        );
+        assert!(Manager::Pyenv.discover(&root.join("pyenv")).is_err());
+    }
+}
@@ -0,0 +1,447 @@
+use anyhow::{Result, anyhow};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use crate::archive;
+use crate::config::{AddOptions, Config, Conflict, OnConflict};
+use crate::encoding::escape;
+use crate::scan::ScanRules;
+
+pub static MANIFEST_VERSION: u32 = 1;
+
+/// Groups written by `x export`, recreated by `x import` and enforced by
+/// `x apply`
+#[derive(Serialize, Deserialize, Debug, Default, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub struct Manifest {
+    pub version: u32,
+    /// Groups to activate, the first one with the highest priority; used
+    /// by `x apply`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub active: Vec<String>,
+    #[serde(default)]
+    pub groups: BTreeMap<String, ManifestGroup>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub struct ManifestGroup {
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub extends: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub exclude: Vec<String>,
+    /// Directories added with their rules
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub sources: Vec<ManifestSource>,
+    /// Executables added one by one
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub bins: Vec<ManifestBin>,
+    /// Executables that are disabled after importing
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub disabled: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub struct ManifestSource {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub dir: Option<String>,
+    /// A `.tar`, `.tar.gz` or `.tgz` archive unpacked in place of `dir`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub archive: Option<String>,
+    /// Checksum the archive must match
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sha256: Option<String>,
+    /// Directory inside the archive holding the executables
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub subdir: Option<String>,
+    #[serde(flatten)]
+    pub rules: ScanRules,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub struct ManifestBin {
+    pub name: String,
+    pub path: String,
+}
+
+impl ManifestSource {
+    /// The directory to add, unpacking the archive first if the source
+    /// is one
+    pub fn resolve(&self, roots: &Roots) -> Result<PathBuf> {
+        self.locate(roots, true).map(|(dir, _)| dir)
+    }
+
+    /// The directory to add and, for an archive that is not unpacked yet,
+    /// the archive. The archive is only unpacked with `unpack`.
+    pub fn locate(&self, roots: &Roots, unpack: bool) -> Result<(PathBuf, Option<PathBuf>)> {
+        let (unpacked, pending) = match (&self.dir, &self.archive) {
+            (Some(dir), None) => return Ok((roots.expand(dir)?, None)),
+            (None, Some(archive)) => {
+                let sha256 = self
+                    .sha256
+                    .as_deref()
+                    .ok_or_else(|| anyhow!("archive {} has no sha256 checksum", archive))?;
+                let archive = roots.expand(archive)?;
+                if unpack {
+                    (archive::unpack(&archive, sha256)?, None)
+                } else {
+                    let dir = archive::unpacked_dir(sha256)?;
+                    let pending = (!dir.is_dir()).then_some(archive);
+                    (dir, pending)
+                }
+            }
+            _ => anyhow::bail!("a source needs either a dir or an archive"),
+        };
+        let dir = match &self.subdir {
+            Some(subdir) => unpacked.join(subdir),
+            None => unpacked,
+        };
+        Ok((dir, pending))
+    }
+}
+
+/// Path prefixes written as variables, e.g. `$HOME/.cargo/bin`, so that a
+/// manifest works on machines where the same tools live under another
+/// root
+#[derive(Debug, Default, Clone)]
+pub struct Roots {
+    vars: Vec<(String, PathBuf)>,
+    /// Directory relative paths are resolved against
+    base: Option<PathBuf>,
+}
+
+impl Roots {
+    /// Parse `NAME=PATH` definitions
+    pub fn parse(defs: &[String]) -> Result<Self> {
+        let mut roots = Roots::default();
+        for def in defs {
+            let (name, path) = def
+                .split_once('=')
+                .ok_or_else(|| anyhow!("invalid root {}, expected NAME=PATH", def))?;
+            if name.is_empty() || !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+                anyhow::bail!("invalid root variable name {}", name);
+            }
+            roots.insert(name, PathBuf::from(path));
+        }
+        Ok(roots)
+    }
+
+    /// Resolve relative paths against `dir`, usually the directory of the
+    /// manifest
+    pub fn with_base(mut self, dir: &Path) -> Self {
+        self.base = Some(dir.to_path_buf());
+        self
+    }
+
+    pub fn insert(&mut self, name: &str, path: PathBuf) {
+        self.vars.retain(|(n, _)| n != name);
+        self.vars.push((name.to_string(), path));
+        // the most specific root wins
+        self.vars
+            .sort_by_key(|(_, p)| std::cmp::Reverse(p.components().count()));
+    }
+
+    /// Write `path` relative to the longest matching root
+    pub fn collapse(&self, path: &Path) -> String {
+        for (name, root) in &self.vars {
+            if let Ok(rest) = path.strip_prefix(root) {
+                if rest.as_os_str().is_empty() {
+                    return format!("${}", name);
+                }
+                return format!("${}/{}", name, escape(rest.as_os_str()));
+            }
+        }
+        escape(path.as_os_str())
+    }
+
+    /// Replace a leading `$NAME` or `${NAME}` with the root of that name,
+    /// or else the environment variable
+    pub fn expand(&self, path: &str) -> Result<PathBuf> {
+        let Some(rest) = path.strip_prefix('$') else {
+            return Ok(match &self.base {
+                Some(base) => base.join(path),
+                None => PathBuf::from(path),
+            });
+        };
+        let (name, rest) = match rest.strip_prefix('{') {
+            Some(braced) => braced
+                .split_once('}')
+                .ok_or_else(|| anyhow!("unterminated variable in {}", path))?,
+            None => rest.split_at(rest.find('/').unwrap_or(rest.len())),
+        };
+        let root = match self.vars.iter().find(|(n, _)| n == name) {
+            Some((_, root)) => root.clone(),
+            None => std::env::var_os(name)
+                .map(PathBuf::from)
+                .ok_or_else(|| anyhow!("variable {} used by {} is not set", name, path))?,
+        };
+        Ok(root.join(rest.trim_start_matches('/')))
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct ImportReport {
+    pub groups: Vec<String>,
+    pub added: usize,
+    pub conflicts: Vec<Conflict>,
+    /// Paths of the manifest that do not exist here, by group
+    pub missing: Vec<(String, PathBuf)>,
+    /// Other problems, the rest of the manifest is still imported
+    pub errors: Vec<String>,
+}
+
+impl Manifest {
+    /// Describe `groups` of `conf`, writing paths relative to `roots`
+    pub fn export(conf: &Config, groups: &[String], roots: &Roots) -> Result<Self> {
+        let mut manifest = Manifest {
+            version: MANIFEST_VERSION,
+            ..Default::default()
+        };
+        for gn in groups {
+            let g = conf
+                .groups
+                .get(gn)
+                .ok_or_else(|| anyhow!("group {} does not exist", gn))?;
+            let mut bins: Vec<ManifestBin> = g
+                .bins
+                .values()
+                .filter(|b| b.source_dir.is_none())
+                .map(|b| ManifestBin {
+                    name: b.name.clone(),
+                    path: roots.collapse(&b.path),
+                })
+                .collect();
+            bins.sort_by(|a, b| a.name.cmp(&b.name));
+            let mut disabled: Vec<String> = g
+                .bins
+                .values()
+                .filter(|b| !b.enabled)
+                .map(|b| b.name.clone())
+                .collect();
+            disabled.sort();
+            let mut sources: Vec<ManifestSource> = conf
+                .source_dirs()
+                .into_iter()
+                .filter(|(sgn, _)| sgn == gn)
+                .map(|(_, s)| ManifestSource {
+                    dir: Some(roots.collapse(&s.dir)),
+                    rules: s.rules,
+                    ..Default::default()
+                })
+                .collect();
+            // groups from other layers carry their sources too
+            for s in g.sources.iter().filter(|s| s.origin != conf.layer) {
+                sources.push(ManifestSource {
+                    dir: Some(roots.collapse(&s.dir)),
+                    rules: s.rules.clone(),
+                    ..Default::default()
+                });
+            }
+            manifest.groups.insert(
+                gn.clone(),
+                ManifestGroup {
+                    extends: g.extends.clone(),
+                    exclude: g.exclude.clone(),
+                    sources,
+                    bins,
+                    disabled,
+                },
+            );
+        }
+        Ok(manifest)
+    }
+
+    /// Parse a manifest, TOML if `path` ends in `.toml` and JSON otherwise
+    pub fn parse(path: &Path, contents: &str) -> Result<Self> {
+        let manifest: Manifest = if is_toml(path) {
+            toml::from_str(contents)?
+        } else {
+            serde_json::from_str(contents)?
+        };
+        if manifest.version > MANIFEST_VERSION {
+            anyhow::bail!(
+                "manifest version {} is newer than the supported version {}",
+                manifest.version,
+                MANIFEST_VERSION
+            );
+        }
+        Ok(manifest)
+    }
+
+    /// Serialize the manifest, as TOML if `path` ends in `.toml`
+    pub fn render(&self, path: Option<&Path>) -> Result<String> {
+        if path.is_some_and(is_toml) {
+            Ok(toml::to_string_pretty(self)?)
+        } else {
+            Ok(serde_json::to_string_pretty(self)? + "\n")
+        }
+    }
+
+    /// Recreate the groups in `conf` by adding their directories and
+    /// executables. Paths that do not exist are reported and skipped.
+    pub fn import(
+        &self,
+        conf: &mut Config,
+        roots: &Roots,
+        on_conflict: OnConflict,
+    ) -> Result<ImportReport> {
+        let mut report = ImportReport::default();
+        for (gn, g) in &self.groups {
+            report.groups.push(gn.clone());
+            let mut additions: Vec<(Result<PathBuf>, AddOptions)> = g
+                .sources
+                .iter()
+                .map(|s| {
+                    let options = AddOptions {
+                        on_conflict,
+                        rules: s.rules.clone(),
+                        ..Default::default()
+                    };
+                    (s.resolve(roots), options)
+                })
+                .collect();
+            additions.extend(g.bins.iter().map(|b| {
+                let options = AddOptions {
+                    name: Some(b.name.clone()),
+                    on_conflict,
+                    ..Default::default()
+                };
+                (roots.expand(&b.path), options)
+            }));
+
+            for (path, options) in additions {
+                let path = match path {
+                    Ok(path) => path,
+                    Err(e) => {
+                        report.errors.push(format!("group {}: {}", gn, e));
+                        continue;
+                    }
+                };
+                if !path.exists() {
+                    report.missing.push((gn.clone(), path));
+                    continue;
+                }
+                match conf.add_with_options(gn, &path, &options) {
+                    Ok(add_report) => {
+                        report.added += add_report.added;
+                        report.conflicts.extend(add_report.conflicts);
+                    }
+                    Err(e) => report.errors.push(format!(
+                        "group {}: cannot add {}: {}",
+                        gn,
+                        path.display(),
+                        e
+                    )),
+                }
+            }
+            if !conf.group_exists(gn) {
+                // a group that only inherits still has to exist
+                conf.set_extends(gn, Vec::new(), Vec::new())?;
+            }
+            for name in &g.disabled {
+                if let Err(e) = conf.set_enabled(gn, name, false) {
+                    report.errors.push(format!("group {}: {}", gn, e));
+                }
+            }
+        }
+        // parents may come later in the manifest
+        for (gn, g) in &self.groups {
+            if (g.extends.is_empty() && g.exclude.is_empty())
+                || conf
+                    .groups
+                    .get(gn)
+                    .is_some_and(|cg| cg.extends == g.extends && cg.exclude == g.exclude)
+            {
+                continue;
+            }
+            if let Err(e) = conf.set_extends(gn, g.extends.clone(), g.exclude.clone()) {
+                report.errors.push(format!("group {}: {}", gn, e));
+            }
+        }
+        Ok(report)
+    }
+}
+
+fn is_toml(path: &Path) -> bool {
+    path.extension().is_some_and(|ext| ext == "toml")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::GLOBAL_DEFAULT_GROUP_NAME;
+    use std::fs;
+    use tempfile::TempDir;
+
+    fn write_script(path: &Path) {
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, "#!/bin/sh\n").unwrap();
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            fs::set_permissions(path, fs::Permissions::from_mode(0o755)).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_export_import_with_roots() {
+        let temp_dir = TempDir::new().unwrap();
+        let root = temp_dir.path().canonicalize().unwrap();
+        let (here, there) = (root.join("here"), root.join("there"));
+        for root in [&here, &there] {
+            write_script(&root.join("llvm").join("clang"));
+            write_script(&root.join("llvm").join("lld"));
+        }
+        write_script(&here.join("scripts").join("deploy.sh"));
+
+        let mut conf = Config::new(temp_dir.path().join("bin"));
+        let options = AddOptions {
+            rules: ScanRules {
+                exclude: vec!["lld".to_string()],
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        conf.add_with_options("llvm", here.join("llvm"), &options)
+            .unwrap();
+        conf.add("llvm", here.join("scripts").join("deploy.sh"), None)
+            .unwrap();
+        conf.set_enabled("llvm", "clang", false).unwrap();
+        conf.set_extends("dev", vec!["llvm".to_string()], Vec::new())
+            .unwrap();
+
+        let mut roots = Roots::default();
+        roots.insert("TOOLS", here.clone());
+        let groups = vec!["dev".to_string(), "llvm".to_string()];
+        let manifest = Manifest::export(&conf, &groups, &roots).unwrap();
+        let llvm = &manifest.groups["llvm"];
+        assert_eq!(llvm.sources[0].dir.as_deref(), Some("$TOOLS/llvm"));
+        assert_eq!(llvm.bins[0].path, "$TOOLS/scripts/deploy.sh");
+
+        // both formats read back the same manifest
+        for file in ["m.json", "m.toml"] {
+            let text = manifest.render(Some(Path::new(file))).unwrap();
+            assert_eq!(Manifest::parse(Path::new(file), &text).unwrap(), manifest);
+        }
+
+        let mut imported = Config::new(temp_dir.path().join("bin2"));
+        let roots = Roots::parse(&[format!("TOOLS={}", there.display())]).unwrap();
+        let report = manifest
+            .import(&mut imported, &roots, OnConflict::Error)
+            .unwrap();
+        assert_eq!(report.added, 1);
+        assert_eq!(
+            report.missing,
+            vec![("llvm".to_string(), there.join("scripts").join("deploy.sh"))]
+        );
+        assert!(report.errors.is_empty(), "{:?}", report.errors);
+        let clang = imported.find("dev", "clang").unwrap();
+        assert!(!clang.enabled);
+        assert!(clang.path.starts_with(&there));
+        assert!(imported.find("llvm", "lld").is_none());
+        assert!(!imported.group_exists(GLOBAL_DEFAULT_GROUP_NAME));
+    }
+}
@@ -0,0 +1,419 @@
+//! Interactive fuzzy picker over groups or executables, drawn on stderr
+//! so that the output of the program that is run stays untouched
+
+use anyhow::Result;
+use crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
+use crossterm::style::{Attribute, Print, SetAttribute};
+use crossterm::terminal::{self, ClearType, EnterAlternateScreen, LeaveAlternateScreen};
+use crossterm::{cursor, execute, queue};
+use std::collections::HashMap;
+use std::io::{self, Write};
+use std::path::PathBuf;
+use std::time::Duration;
+
+use crate::config::Config;
+use crate::process::probe_version;
+use crate::search::{SearchOptions, fuzzy_score};
+
+/// What the picker lists
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Mode {
+    Groups,
+    Bins,
+}
+
+/// How the picker was left
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Action {
+    Switch(String),
+    Run { group: String, name: String },
+    Quit,
+}
+
+struct Entry {
+    group: String,
+    bin: Option<String>,
+    /// What the query is matched against
+    text: String,
+}
+
+pub struct Picker {
+    mode: Mode,
+    entries: Vec<Entry>,
+    query: String,
+    /// Indexes of the entries matching the query, best first
+    matches: Vec<usize>,
+    selected: usize,
+    offset: usize,
+    versions: HashMap<PathBuf, Option<String>>,
+    message: Option<String>,
+    /// Whether an executable was enabled or disabled, the caller saves
+    pub changed: bool,
+}
+
+impl Picker {
+    pub fn new(conf: &Config, mode: Mode) -> Self {
+        let mut entries: Vec<Entry> = match mode {
+            Mode::Groups => conf
+                .groups
+                .keys()
+                .map(|gn| Entry {
+                    group: gn.clone(),
+                    bin: None,
+                    text: gn.clone(),
+                })
+                .collect(),
+            // an empty query finds every executable
+            Mode::Bins => conf
+                .search("", &SearchOptions::default())
+                .unwrap_or_default()
+                .into_iter()
+                .map(|h| Entry {
+                    text: format!("{}/{} {}", h.group, h.name, h.bin.path.display()),
+                    group: h.group,
+                    bin: Some(h.name),
+                })
+                .collect(),
+        };
+        entries.sort_by(|a, b| (&a.group, &a.bin).cmp(&(&b.group, &b.bin)));
+        let mut picker = Picker {
+            mode,
+            entries,
+            query: String::new(),
+            matches: Vec::new(),
+            selected: 0,
+            offset: 0,
+            versions: HashMap::new(),
+            message: None,
+            changed: false,
+        };
+        picker.filter();
+        picker
+    }
+
+    fn filter(&mut self) {
+        let mut scored: Vec<(i64, usize)> = self
+            .entries
+            .iter()
+            .enumerate()
+            .filter_map(|(i, e)| fuzzy_score(&self.query, &e.text).map(|s| (s, i)))
+            .collect();
+        // stable, so equal scores keep the sorted order
+        scored.sort_by_key(|(score, _)| std::cmp::Reverse(*score));
+        self.matches = scored.into_iter().map(|(_, i)| i).collect();
+        self.selected = 0;
+        self.offset = 0;
+    }
+
+    fn current(&self) -> Option<&Entry> {
+        self.matches.get(self.selected).map(|i| &self.entries[*i])
+    }
+
+    fn handle_key(&mut self, conf: &mut Config, key: KeyEvent) -> Result<Option<Action>> {
+        let ctrl = key.modifiers.contains(KeyModifiers::CONTROL);
+        self.message = None;
+        match key.code {
+            KeyCode::Esc => return Ok(Some(Action::Quit)),
+            KeyCode::Char('c') if ctrl => return Ok(Some(Action::Quit)),
+            KeyCode::Up | KeyCode::BackTab => self.selected = self.selected.saturating_sub(1),
+            KeyCode::Char('p' | 'k') if ctrl => self.selected = self.selected.saturating_sub(1),
+            KeyCode::Down | KeyCode::Tab => self.move_down(),
+            KeyCode::Char('n' | 'j') if ctrl => self.move_down(),
+            KeyCode::Enter => {
+                let Some(entry) = self.current() else {
+                    return Ok(None);
+                };
+                return Ok(Some(match &entry.bin {
+                    None => Action::Switch(entry.group.clone()),
+                    Some(name) => Action::Run {
+                        group: entry.group.clone(),
+                        name: name.clone(),
+                    },
+                }));
+            }
+            KeyCode::Char('s') if ctrl => {
+                if let Some(entry) = self.current() {
+                    return Ok(Some(Action::Switch(entry.group.clone())));
+                }
+            }
+            KeyCode::Char('t') if ctrl => self.toggle(conf),
+            KeyCode::Char('v') if ctrl => self.probe(conf),
+            KeyCode::Backspace => {
+                self.query.pop();
+                self.filter();
+            }
+            KeyCode::Char(c) if !ctrl => {
+                self.query.push(c);
+                self.filter();
+            }
+            _ => {}
+        }
+        Ok(None)
+    }
+
+    fn move_down(&mut self) {
+        if self.selected + 1 < self.matches.len() {
+            self.selected += 1;
+        }
+    }
+
+    /// Enable or disable the selected executable
+    fn toggle(&mut self, conf: &mut Config) {
+        let Some(Entry {
+            group,
+            bin: Some(name),
+            ..
+        }) = self.current()
+        else {
+            return;
+        };
+        let (group, name) = (group.clone(), name.clone());
+        let enabled = conf
+            .groups
+            .get(&group)
+            .and_then(|g| g.bins.get(&name))
+            .is_some_and(|b| b.enabled);
+        self.message = Some(match conf.set_enabled(&group, &name, !enabled) {
+            Ok(()) => {
+                self.changed = true;
+                let state = if enabled { "Disabled" } else { "Enabled" };
+                format!("{} {} in group {}", state, name, group)
+            }
+            Err(e) => format!("Error: {}", e),
+        });
+    }
+
+    /// Ask the selected executable for its version, which is then shown
+    /// with its details. Only done on request, since it runs the program.
+    fn probe(&mut self, conf: &Config) {
+        let Some(Entry {
+            group,
+            bin: Some(name),
+            ..
+        }) = self.current()
+        else {
+            return;
+        };
+        let Some(bin) = conf.groups.get(group).and_then(|g| g.bins.get(name)) else {
+            return;
+        };
+        let path = bin.path.clone();
+        self.versions
+            .entry(path.clone())
+            .or_insert_with(|| probe_version(&path, Duration::from_millis(500)));
+    }
+
+    /// Details of the selected entry: path, state and version of an
+    /// executable, or the executables of a group
+    fn details(&self, conf: &Config) -> String {
+        let Some(entry) = self.current() else {
+            return String::new();
+        };
+        let Some(g) = conf.groups.get(&entry.group) else {
+            return String::new();
+        };
+        let Some(name) = &entry.bin else {
+            let mut names: Vec<&String> = g.bins.keys().collect();
+            names.sort();
+            let names: Vec<&str> = names.into_iter().map(String::as_str).collect();
+            return format!("{} executable(s): {}", names.len(), names.join(" "));
+        };
+        let Some(bin) = g.bins.get(name) else {
+            return String::new();
+        };
+        let state = if bin.enabled { "enabled" } else { "disabled" };
+        let version = match self.versions.get(&bin.path) {
+            Some(Some(version)) => version.as_str(),
+            Some(None) => "version unknown",
+            None => "ctrl-v: version",
+        };
+        format!("{}  [{}]  {}", bin.path.display(), state, version)
+    }
+
+    fn draw(&mut self, conf: &Config, out: &mut impl Write) -> Result<()> {
+        // some terminals report no size at all
+        let (width, height) = match terminal::size()? {
+            (0, _) | (_, 0) => (80, 24),
+            size => size,
+        };
+        let width = width as usize;
+        let rows = (height as usize).saturating_sub(4).max(1);
+        if self.selected < self.offset {
+            self.offset = self.selected;
+        } else if self.selected >= self.offset + rows {
+            self.offset = self.selected + 1 - rows;
+        }
+
+        queue!(out, terminal::Clear(ClearType::All), cursor::MoveTo(0, 0))?;
+        let prompt = match self.mode {
+            Mode::Groups => "switch",
+            Mode::Bins => "run",
+        };
+        queue!(
+            out,
+            Print(truncate(&format!("{}> {}", prompt, self.query), width))
+        )?;
+        let visible: Vec<usize> = self
+            .matches
+            .iter()
+            .skip(self.offset)
+            .take(rows)
+            .copied()
+            .collect();
+        for (row, i) in visible.into_iter().enumerate() {
+            let entry = &self.entries[i];
+            let g = conf.groups.get(&entry.group);
+            let line = match &entry.bin {
+                None => {
+                    let marker = if conf.is_active(&entry.group) {
+                        "*"
+                    } else {
+                        " "
+                    };
+                    let count = g.map(|g| g.bins.len()).unwrap_or(0);
+                    format!("{} {} ({})", marker, entry.group, count)
+                }
+                Some(name) => {
+                    let bin = g.and_then(|g| g.bins.get(name));
+                    let marker = if bin.is_some_and(|b| b.enabled) {
+                        " "
+                    } else {
+                        "-"
+                    };
+                    let path = bin
+                        .map(|b| b.path.display().to_string())
+                        .unwrap_or_default();
+                    format!("{} {}/{}  {}", marker, entry.group, name, path)
+                }
+            };
+            queue!(out, cursor::MoveTo(0, row as u16 + 1))?;
+            if self.offset + row == self.selected {
+                queue!(
+                    out,
+                    SetAttribute(Attribute::Reverse),
+                    Print(truncate(&line, width)),
+                    SetAttribute(Attribute::Reset)
+                )?;
+            } else {
+                queue!(out, Print(truncate(&line, width)))?;
+            }
+        }
+
+        let details = match self.message.clone() {
+            Some(message) => message,
+            None => self.details(conf),
+        };
+        let help = match self.mode {
+            Mode::Groups => "enter: switch  esc: quit",
+            Mode::Bins => {
+                "enter: run  ctrl-t: enable/disable  ctrl-v: version  ctrl-s: switch to group  esc: quit"
+            }
+        };
+        queue!(
+            out,
+            cursor::MoveTo(0, height.saturating_sub(2)),
+            Print(truncate(&details, width)),
+            cursor::MoveTo(0, height.saturating_sub(1)),
+            SetAttribute(Attribute::Dim),
+            Print(truncate(
+                &format!("{}/{}  {}", self.matches.len(), self.entries.len(), help),
+                width
+            )),
+            SetAttribute(Attribute::Reset)
+        )?;
+        out.flush()?;
+        Ok(())
+    }
+
+    /// Show the picker until an entry is chosen or it is closed
+    pub fn run(&mut self, conf: &mut Config) -> Result<Action> {
+        let mut out = io::stderr();
+        terminal::enable_raw_mode()?;
+        execute!(out, EnterAlternateScreen, cursor::Hide)?;
+        let result = (|| loop {
+            self.draw(conf, &mut out)?;
+            if let Event::Key(key) = event::read()?
+                && key.kind == KeyEventKind::Press
+                && let Some(action) = self.handle_key(conf, key)?
+            {
+                return Ok(action);
+            }
+        })();
+        // restore the terminal even when drawing failed
+        let _ = execute!(out, cursor::Show, LeaveAlternateScreen);
+        let _ = terminal::disable_raw_mode();
+        result
+    }
+}
+
+fn truncate(line: &str, width: usize) -> String {
+    line.chars().take(width).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Bin;
+
+    fn key(code: KeyCode) -> KeyEvent {
+        KeyEvent::new(code, KeyModifiers::NONE)
+    }
+
+    #[test]
+    fn test_fuzzy_picker_keys() {
+        let mut conf = Config::new(PathBuf::from("/nonexistent/bin"));
+        for (gn, bn) in [("node18", "node"), ("node20", "node"), ("py3", "python")] {
+            let bin = Bin::new(
+                bn.into(),
+                PathBuf::from("/opt").join(gn).join(bn),
+                None,
+                conf.layer,
+            );
+            conf.groups
+                .entry(gn.to_string())
+                .or_default()
+                .bins
+                .insert(bn.to_string(), bin);
+        }
+
+        let mut picker = Picker::new(&conf, Mode::Bins);
+        assert_eq!(picker.matches.len(), 3);
+        for c in "n20".chars() {
+            picker.handle_key(&mut conf, key(KeyCode::Char(c))).unwrap();
+        }
+        assert_eq!(picker.current().unwrap().group, "node20");
+        // the version is only asked for with ctrl-v
+        assert!(picker.details(&conf).ends_with("ctrl-v: version"));
+        let ctrl = |c| KeyEvent::new(KeyCode::Char(c), KeyModifiers::CONTROL);
+        picker.handle_key(&mut conf, ctrl('v')).unwrap();
+        assert!(picker.details(&conf).ends_with("version unknown"));
+
+        // ctrl-t disables the selected executable, ctrl-s switches to
+        // its group
+        picker.handle_key(&mut conf, ctrl('t')).unwrap();
+        assert!(picker.changed);
+        assert!(!conf.groups["node20"].bins["node"].enabled);
+        assert_eq!(
+            picker.handle_key(&mut conf, ctrl('s')).unwrap(),
+            Some(Action::Switch("node20".to_string()))
+        );
+        assert_eq!(
+            picker.handle_key(&mut conf, key(KeyCode::Enter)).unwrap(),
+            Some(Action::Run {
+                group: "node20".to_string(),
+                name: "node".to_string()
+            })
+        );
+
+        let mut picker = Picker::new(&conf, Mode::Groups);
+        picker.handle_key(&mut conf, key(KeyCode::Down)).unwrap();
+        assert_eq!(
+            picker.handle_key(&mut conf, key(KeyCode::Enter)).unwrap(),
+            Some(Action::Switch("node20".to_string()))
+        );
+        assert_eq!(
+            picker.handle_key(&mut conf, key(KeyCode::Esc)).unwrap(),
+            Some(Action::Quit)
+        );
+    }
+}
@@ -1,14 +1,23 @@
+use std::ffi::OsStr;
+use std::io::Read;
 use std::path::Path;
-use std::process::Command;
+use std::process::{Command, Stdio};
+use std::sync::mpsc::{self, Receiver};
+use std::time::{Duration, Instant};
+
+use crate::scan::answers_version;
 
 pub struct Run<'a> {
-    pub command: &'a str,
+    pub command: &'a OsStr,
     pub args: &'a [String],
 }
 
 impl<'a> Run<'a> {
-    pub fn new(command: &'a str, args: &'a [String]) -> Self {
-        Run { command, args }
+    pub fn new<S: AsRef<OsStr> + ?Sized>(command: &'a S, args: &'a [String]) -> Self {
+        Run {
+            command: command.as_ref(),
+            args,
+        }
     }
 
     pub fn run_and_monitor(&self) -> Option<i32> {
@@ -49,9 +58,67 @@ impl<'a> Run<'a> {
     }
 }
 
+/// The first line `program --version` prints, or `None` if it fails or
+/// takes longer than `timeout`. Scripts are never run.
+pub fn probe_version(program: &Path, timeout: Duration) -> Option<String> {
+    if !answers_version(program) {
+        return None;
+    }
+    let mut child = Command::new(program)
+        .arg("--version")
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .ok()?;
+    // the pipes are read while waiting, a program filling one would
+    // otherwise block until the timeout
+    let stdout = read_in_background(child.stdout.take());
+    let stderr = read_in_background(child.stderr.take());
+    let deadline = Instant::now() + timeout;
+    while child.try_wait().ok()?.is_none() {
+        if Instant::now() > deadline {
+            let _ = child.kill();
+            let _ = child.wait();
+            return None;
+        }
+        std::thread::sleep(Duration::from_millis(10));
+    }
+    if !child.wait().ok()?.success() {
+        return None;
+    }
+    // a program that started others may leave the pipes open
+    let output = [stdout, stderr].map(|receiver| {
+        receiver
+            .recv_timeout(deadline.saturating_duration_since(Instant::now()))
+            .unwrap_or_default()
+    });
+    // some programs print their version to stderr
+    output.iter().find_map(|bytes| {
+        String::from_utf8_lossy(bytes)
+            .lines()
+            .map(str::trim)
+            .find(|line| !line.is_empty())
+            .map(str::to_string)
+    })
+}
+
+/// Everything `pipe` gives until it is closed, read on another thread
+fn read_in_background<R: Read + Send + 'static>(pipe: Option<R>) -> Receiver<Vec<u8>> {
+    let (sender, receiver) = mpsc::channel();
+    std::thread::spawn(move || {
+        let mut bytes = Vec::new();
+        if let Some(mut pipe) = pipe {
+            let _ = pipe.read_to_end(&mut bytes);
+        }
+        let _ = sender.send(bytes);
+    });
+    receiver
+}
+
 #[cfg(test)]
 mod tests {
-    use super::Run;
+    use super::{Run, probe_version};
     use std::fs;
     use std::process::Command;
     use tempfile::TempDir;
@@ -78,6 +145,46 @@ mod tests {
         assert_eq!(run.run_and_monitor(), Some(0));
     }
 
+    #[test]
+    #[cfg(unix)]
+    fn probe_version_reads_first_line_and_gives_up_on_slow_programs() {
+        use std::os::unix::fs::PermissionsExt;
+        use std::path::Path;
+        use std::time::Duration;
+
+        let temp_dir = TempDir::new().expect("failed to create temp dir");
+        let write_script = |name: &str, body: &str| {
+            let path = temp_dir.path().join(name);
+            fs::write(&path, format!("#!/bin/sh\n{}\n", body)).expect("failed to write script");
+            fs::set_permissions(&path, fs::Permissions::from_mode(0o755))
+                .expect("failed to set script permissions");
+            path
+        };
+
+        let tool = write_script("tool", "echo\necho 'tool 1.2.3'\necho more");
+        assert_eq!(
+            probe_version(&tool, Duration::from_secs(5)),
+            Some("tool 1.2.3".to_string())
+        );
+        // more output than a pipe holds
+        let chatty = write_script("chatty", "echo 'chatty 2.0'\nhead -c 1000000 /dev/zero");
+        assert_eq!(
+            probe_version(&chatty, Duration::from_secs(5)),
+            Some("chatty 2.0".to_string())
+        );
+        let script = write_script("tool.sh", "echo 'tool 1.2.3'");
+        assert_eq!(probe_version(&script, Duration::from_secs(5)), None);
+        let slow = write_script("slow", "sleep 5");
+        assert_eq!(probe_version(&slow, Duration::from_millis(100)), None);
+        assert_eq!(
+            probe_version(
+                Path::new("/x_command_should_not_exist_12345"),
+                Duration::from_secs(1)
+            ),
+            None
+        );
+    }
+
     #[test]
     #[cfg(unix)]
     fn run_non_executable_shell_script_with_sh_fallback() {
@@ -90,7 +197,10 @@ mod tests {
             .expect("failed to set script permissions");
 
         let args = vec![];
-        let run = Run::new(script_path.to_str().expect("script path is not utf-8"), &args);
+        let run = Run::new(
+            script_path.to_str().expect("script path is not utf-8"),
+            &args,
+        );
         assert_eq!(run.run_and_monitor(), Some(0));
     }
 
@@ -117,7 +227,10 @@ mod tests {
         }
 
         let args = vec![];
-        let run = Run::new(script_path.to_str().expect("script path is not utf-8"), &args);
+        let run = Run::new(
+            script_path.to_str().expect("script path is not utf-8"),
+            &args,
+        );
         assert_eq!(run.run_and_monitor(), Some(0));
     }
 }
@@ -0,0 +1,145 @@
+use anyhow::{Result, anyhow};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::config::{
+    Config, DEFAULT_PROFILE_NAME, config_dir_in, get_profile_bin_dir, get_profile_config_path,
+    load_config_from, profiles_dir_in, state_dir_in, validate_profile_name,
+};
+
+/// Map a user supplied profile name to the internal representation,
+/// where the default profile is `None`
+fn normalize(name: &str) -> Result<Option<&str>> {
+    if name == DEFAULT_PROFILE_NAME {
+        return Ok(None);
+    }
+    validate_profile_name(name)?;
+    Ok(Some(name))
+}
+
+fn list_in(home_dir: &Path) -> Result<Vec<String>> {
+    let mut names = vec![DEFAULT_PROFILE_NAME.to_string()];
+    let profiles_dir = profiles_dir_in(home_dir);
+    if profiles_dir.is_dir() {
+        let mut found = Vec::new();
+        for entry in fs::read_dir(&profiles_dir)? {
+            let entry = entry?;
+            if !entry.path().join("config.json").is_file() {
+                continue;
+            }
+            if let Ok(name) = entry.file_name().into_string() {
+                found.push(name);
+            }
+        }
+        found.sort();
+        names.extend(found);
+    }
+    Ok(names)
+}
+
+fn home_dir() -> Result<PathBuf> {
+    dirs::home_dir().ok_or_else(|| anyhow!("cannot get home dir"))
+}
+
+/// Config path of an existing profile, without creating any directory
+fn existing_config_path(profile: Option<&str>) -> Result<Option<PathBuf>> {
+    let path = config_dir_in(&home_dir()?, profile).join("config.json");
+    Ok(path.exists().then_some(path))
+}
+
+/// The config file of profile `name`, if it exists
+pub fn config_path(name: &str) -> Result<Option<PathBuf>> {
+    existing_config_path(normalize(name)?)
+}
+
+/// All profiles that have a config file, the default profile first
+pub fn list() -> Result<Vec<String>> {
+    list_in(&home_dir()?)
+}
+
+pub fn exists(name: &str) -> Result<bool> {
+    let profile = normalize(name)?;
+    Ok(existing_config_path(profile)?.is_some())
+}
+
+/// Create an empty profile with its own config file and bin dir
+pub fn create(name: &str) -> Result<Config> {
+    let profile = normalize(name)?;
+    let conf_path = get_profile_config_path(profile)?;
+    if conf_path.exists() {
+        anyhow::bail!("profile {} already exists", name);
+    }
+    let conf = Config::new(get_profile_bin_dir(profile)?);
+    conf.save(&conf_path)?;
+    Ok(conf)
+}
+
+/// Remove the symlinks and the config of a profile
+pub fn delete(name: &str) -> Result<()> {
+    let profile = normalize(name)?.ok_or_else(|| anyhow!("cannot delete the default profile"))?;
+    let conf_path = existing_config_path(Some(profile))?
+        .ok_or_else(|| anyhow!("profile {} does not exist", name))?;
+    let conf = Config::load(&conf_path)?;
+    conf.uninstall_active()?;
+    // only remove the bin dir when x was the only one using it
+    if conf.bin_dir.is_dir() && fs::read_dir(&conf.bin_dir)?.next().is_none() {
+        fs::remove_dir(&conf.bin_dir)?;
+    }
+    if let Some(config_dir) = conf_path.parent() {
+        fs::remove_dir_all(config_dir)?;
+    }
+    let state_dir = state_dir_in(&home_dir()?, Some(profile));
+    if state_dir.is_dir() {
+        fs::remove_dir_all(state_dir)?;
+    }
+    Ok(())
+}
+
+/// Copy the groups and active group of `from` into a new profile `to`,
+/// which gets its own bin dir
+pub fn copy(from: &str, to: &str) -> Result<Config> {
+    let from_profile = normalize(from)?;
+    let to_profile = normalize(to)?;
+    let from_path = existing_config_path(from_profile)?
+        .ok_or_else(|| anyhow!("profile {} does not exist", from))?;
+    if existing_config_path(to_profile)?.is_some() {
+        anyhow::bail!("profile {} already exists", to);
+    }
+    // layered like `load_config`, so groups extending system groups
+    // resolve, and loaded before any directory of `to` is created
+    let mut conf = load_config_from(&from_path, false)?;
+    let to_path = get_profile_config_path(to_profile)?;
+    conf.bin_dir = get_profile_bin_dir(to_profile)?;
+    conf.install_active()?;
+    conf.save(&to_path)?;
+    Ok(conf)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_list_in_only_reports_profiles_with_config() {
+        let temp_dir = TempDir::new().unwrap();
+        let home_dir = temp_dir.path();
+        let profiles_dir = profiles_dir_in(home_dir);
+
+        fs::create_dir_all(profiles_dir.join("work")).unwrap();
+        fs::write(profiles_dir.join("work").join("config.json"), "{}").unwrap();
+        fs::create_dir_all(profiles_dir.join("ci")).unwrap();
+        fs::write(profiles_dir.join("ci").join("config.json"), "{}").unwrap();
+        fs::create_dir_all(profiles_dir.join("empty")).unwrap();
+
+        assert_eq!(list_in(home_dir).unwrap(), vec!["default", "ci", "work"]);
+    }
+
+    #[test]
+    fn test_normalize() {
+        assert_eq!(normalize("default").unwrap(), None);
+        assert_eq!(normalize("work").unwrap(), Some("work"));
+        assert!(normalize("../etc").is_err());
+        assert!(normalize("").is_err());
+    }
+}
@@ -0,0 +1,391 @@
+use anyhow::{Result, anyhow};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::ffi::{OsStr, OsString};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::config::Origin;
+use crate::encoding::escape;
+
+/// Which executables of a directory become bins and how they are named
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub struct ScanRules {
+    /// Only file names matching one of these patterns are added. Patterns
+    /// are globs, or regular expressions when prefixed with `re:`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub include: Vec<String>,
+    /// File names matching one of these patterns are left out
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub exclude: Vec<String>,
+    /// Template for the bin name using `{name}`, `{stem}` and `{ext}`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rename: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub prefix: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub suffix: Option<String>,
+    /// Use the file stem instead of the file name for `{name}`
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub strip_ext: bool,
+    /// Look for executables in subdirectories too
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub recursive: bool,
+    /// How many directory levels are scanned, 1 being the directory
+    /// itself; implies `recursive`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_depth: Option<usize>,
+    /// Descend into symlinked directories
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub follow_symlinks: bool,
+}
+
+/// A directory added to a group, scanned again by `x sync`
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(rename_all = "kebab-case")]
+pub struct SourceDir {
+    #[serde(with = "crate::encoding::path")]
+    pub dir: PathBuf,
+    #[serde(flatten)]
+    pub rules: ScanRules,
+    #[serde(skip)]
+    pub origin: Origin,
+}
+
+enum Pattern {
+    Glob(glob::Pattern),
+    Regex(Regex),
+}
+
+impl Pattern {
+    fn parse(pattern: &str) -> Result<Self> {
+        match pattern.strip_prefix("re:") {
+            Some(re) => Regex::new(re)
+                .map(Pattern::Regex)
+                .map_err(|e| anyhow!("invalid regex {}: {}", re, e)),
+            None => glob::Pattern::new(pattern)
+                .map(Pattern::Glob)
+                .map_err(|e| anyhow!("invalid glob {}: {}", pattern, e)),
+        }
+    }
+
+    fn matches(&self, file_name: &str) -> bool {
+        match self {
+            Pattern::Glob(g) => g.matches(file_name),
+            Pattern::Regex(re) => re.is_match(file_name),
+        }
+    }
+}
+
+const PLACEHOLDERS: [&str; 3] = ["{name}", "{stem}", "{ext}"];
+
+/// `ScanRules` with the patterns compiled
+pub struct Matcher<'a> {
+    rules: &'a ScanRules,
+    include: Vec<Pattern>,
+    exclude: Vec<Pattern>,
+}
+
+impl ScanRules {
+    pub fn is_empty(&self) -> bool {
+        *self == ScanRules::default()
+    }
+
+    /// Check the patterns and the rename template
+    pub fn compile(&self) -> Result<Matcher<'_>> {
+        if let Some(template) = &self.rename {
+            let mut rest = template.clone();
+            for p in PLACEHOLDERS {
+                rest = rest.replace(p, "");
+            }
+            if rest.contains('{') || rest.contains('}') {
+                anyhow::bail!(
+                    "invalid rename template {}, the placeholders are {}",
+                    template,
+                    PLACEHOLDERS.join(", ")
+                );
+            }
+        }
+        let parse_all = |patterns: &[String]| -> Result<Vec<Pattern>> {
+            patterns.iter().map(|p| Pattern::parse(p)).collect()
+        };
+        Ok(Matcher {
+            rules: self,
+            include: parse_all(&self.include)?,
+            exclude: parse_all(&self.exclude)?,
+        })
+    }
+}
+
+impl Matcher<'_> {
+    /// Patterns see file names that are not UTF-8 in their escaped form
+    pub fn matches(&self, file_name: &OsStr) -> bool {
+        let file_name = escape(file_name);
+        (self.include.is_empty() || self.include.iter().any(|p| p.matches(&file_name)))
+            && !self.exclude.iter().any(|p| p.matches(&file_name))
+    }
+
+    /// The bin name of the file `file_name`
+    pub fn bin_name(&self, file_name: &OsStr) -> OsString {
+        let path = Path::new(file_name);
+        let stem = path.file_stem().unwrap_or(file_name);
+        let ext = path.extension().unwrap_or_default();
+        let name = if self.rules.strip_ext {
+            stem
+        } else {
+            file_name
+        };
+        let mut bin_name = OsString::from(self.rules.prefix.as_deref().unwrap_or(""));
+        match &self.rules.rename {
+            Some(template) => {
+                let mut rest = template.as_str();
+                while let Some((pos, placeholder)) = PLACEHOLDERS
+                    .iter()
+                    .filter_map(|p| rest.find(p).map(|pos| (pos, *p)))
+                    .min()
+                {
+                    bin_name.push(&rest[..pos]);
+                    bin_name.push(match placeholder {
+                        "{name}" => name,
+                        "{stem}" => stem,
+                        _ => ext,
+                    });
+                    rest = &rest[pos + placeholder.len()..];
+                }
+                bin_name.push(rest);
+            }
+            None => bin_name.push(name),
+        }
+        bin_name.push(self.rules.suffix.as_deref().unwrap_or(""));
+        bin_name
+    }
+}
+
+impl SourceDir {
+    pub fn new(dir: PathBuf, rules: ScanRules, origin: Origin) -> Self {
+        SourceDir { dir, rules, origin }
+    }
+
+    /// Names and canonical paths of the executables selected by the rules
+    pub fn scan(&self) -> Result<Vec<(OsString, PathBuf)>> {
+        let matcher = self.rules.compile()?;
+        Ok(collect_executables(&self.dir, &self.rules)?
+            .into_iter()
+            .filter(|(file_name, _)| matcher.matches(file_name))
+            .map(|(file_name, path)| (matcher.bin_name(&file_name), path))
+            .collect())
+    }
+}
+
+#[cfg(unix)]
+fn is_executable(p: &Path) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+    if let Ok(meta) = p.metadata() {
+        meta.permissions().mode() & 0o111 != 0
+    } else {
+        false
+    }
+}
+
+#[cfg(windows)]
+fn is_executable(p: &Path) -> bool {
+    p.extension()
+        .and_then(|s| s.to_str())
+        .map(|ext| {
+            matches!(
+                ext.to_ascii_lowercase().as_str(),
+                "exe" | "bat" | "cmd" | "ps1" | "com"
+            )
+        })
+        .unwrap_or(false)
+}
+
+/// File names and paths of the runnable files in `dir`, sorted by path.
+/// Symlinks to executables are kept as they are, so that a bin follows
+/// the link when it is changed to point somewhere else.
+fn collect_executables(dir: &Path, rules: &ScanRules) -> Result<Vec<(OsString, PathBuf)>> {
+    let max_depth = match rules.max_depth {
+        Some(depth) => depth.max(1),
+        None if rules.recursive => usize::MAX,
+        None => 1,
+    };
+    let mut walk = Walk {
+        max_depth,
+        follow_symlinks: rules.follow_symlinks,
+        visited: HashSet::new(),
+        found: Vec::new(),
+    };
+    walk.visit(&dir.canonicalize()?, 1)?;
+    Ok(walk.found)
+}
+
+struct Walk {
+    max_depth: usize,
+    follow_symlinks: bool,
+    /// Canonical paths of the directories scanned so far, a symlink
+    /// leading back to one of them is a loop
+    visited: HashSet<PathBuf>,
+    found: Vec<(OsString, PathBuf)>,
+}
+
+impl Walk {
+    fn visit(&mut self, dir: &Path, depth: usize) -> Result<()> {
+        if !self.visited.insert(dir.canonicalize()?) {
+            return Ok(());
+        }
+        let mut entries = Vec::new();
+        for entry in fs::read_dir(dir)? {
+            match entry {
+                Ok(entry) => entries.push(entry),
+                Err(e) => warn_skipped(dir, &e),
+            }
+        }
+        entries.sort_by_key(|entry| entry.file_name());
+        for entry in entries {
+            let path = entry.path();
+            let is_symlink = match entry.file_type() {
+                Ok(file_type) => file_type.is_symlink(),
+                Err(e) => {
+                    warn_skipped(&path, &e);
+                    continue;
+                }
+            };
+            // follows symlinks, dangling ones are skipped
+            let Ok(meta) = fs::metadata(&path) else {
+                continue;
+            };
+            if meta.is_dir() {
+                if depth < self.max_depth && (!is_symlink || self.follow_symlinks) {
+                    // an unreadable subdirectory does not stop the scan
+                    if let Err(e) = self.visit(&path, depth + 1) {
+                        warn_skipped(&path, &*e);
+                    }
+                }
+                continue;
+            }
+            if meta.is_file() && is_runnable_file(&path) {
+                self.found.push((entry.file_name(), path));
+            }
+        }
+        Ok(())
+    }
+}
+
+fn warn_skipped(path: &Path, e: &dyn std::fmt::Display) {
+    eprintln!("Warning: skipping {}: {}", escape(path.as_os_str()), e);
+}
+
+fn is_supported_script(path: &Path) -> bool {
+    path.extension()
+        .and_then(|s| s.to_str())
+        .map(|ext| matches!(ext.to_ascii_lowercase().as_str(), "sh" | "py"))
+        .unwrap_or(false)
+}
+
+pub(crate) fn is_runnable_file(path: &Path) -> bool {
+    is_executable(path) || is_supported_script(path)
+}
+
+/// Whether `path` is a program that can be asked for its `--version`.
+/// Scripts often ignore the flag and do their real work instead.
+pub(crate) fn answers_version(path: &Path) -> bool {
+    path.is_file() && is_executable(path) && !is_supported_script(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_matcher_filters_and_names() {
+        let rules = ScanRules {
+            include: vec!["clang*".to_string(), "re:^lld$".to_string()],
+            exclude: vec!["*-cpp".to_string()],
+            rename: Some("{stem}-18".to_string()),
+            ..Default::default()
+        };
+        let matcher = rules.compile().unwrap();
+        assert!(matcher.matches("clang".as_ref()));
+        assert!(matcher.matches("lld".as_ref()));
+        assert!(!matcher.matches("clang-cpp".as_ref()));
+        assert!(!matcher.matches("lld-link".as_ref()));
+        assert_eq!(matcher.bin_name("clang".as_ref()), "clang-18");
+
+        let rules = ScanRules {
+            prefix: Some("py-".to_string()),
+            strip_ext: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            rules.compile().unwrap().bin_name("tool.py".as_ref()),
+            "py-tool"
+        );
+
+        let rules = ScanRules {
+            rename: Some("{version}".to_string()),
+            ..Default::default()
+        };
+        assert!(rules.compile().is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_scan_recursion_and_symlinks() {
+        use std::os::unix::fs::{PermissionsExt, symlink};
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let root = temp_dir.path().canonicalize().unwrap();
+        let write = |path: &Path| {
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, "#!/bin/sh\n").unwrap();
+            fs::set_permissions(path, fs::Permissions::from_mode(0o755)).unwrap();
+        };
+        write(&root.join("python3.11"));
+        symlink("python3.11", root.join("python3")).unwrap();
+        symlink("missing", root.join("dangling")).unwrap();
+        write(&root.join("libexec").join("helper"));
+        write(&root.join("libexec").join("deep").join("tool"));
+        symlink(&root, root.join("libexec").join("loop")).unwrap();
+        let other = tempfile::TempDir::new().unwrap();
+        write(&other.path().join("extra"));
+        symlink(other.path(), root.join("linked")).unwrap();
+        fs::create_dir_all(root.join("src")).unwrap();
+        symlink("../src", root.join("src").join("up")).unwrap();
+
+        let names = |rules: ScanRules| -> Vec<String> {
+            let source = SourceDir::new(root.clone(), rules, Origin::User);
+            source
+                .scan()
+                .unwrap()
+                .into_iter()
+                .map(|(n, _)| escape(&n))
+                .collect()
+        };
+        assert_eq!(names(ScanRules::default()), vec!["python3", "python3.11"]);
+        assert_eq!(
+            names(ScanRules {
+                max_depth: Some(2),
+                ..Default::default()
+            }),
+            vec!["helper", "python3", "python3.11"]
+        );
+        assert_eq!(
+            names(ScanRules {
+                recursive: true,
+                follow_symlinks: true,
+                ..Default::default()
+            }),
+            vec!["tool", "helper", "extra", "python3", "python3.11"]
+        );
+
+        // the symlink is linked, not its target
+        let source = SourceDir::new(root.clone(), ScanRules::default(), Origin::User);
+        assert!(
+            source
+                .scan()
+                .unwrap()
+                .contains(&("python3".into(), root.join("python3")))
+        );
+    }
+}
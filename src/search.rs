@@ -0,0 +1,295 @@
+//! Ranked search over the executables of every group for `x search` and
+//! the picker
+
+use anyhow::{Result, anyhow};
+use regex::{Regex, RegexBuilder};
+use std::cmp::Reverse;
+
+use crate::config::{Bin, Config};
+
+/// How search results are ordered; ties are broken by group, then name
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum SortBy {
+    /// Best match first
+    #[default]
+    Score,
+    Name,
+    Group,
+    Path,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct SearchOptions {
+    /// Terms are regular expressions rather than fuzzy patterns
+    pub regex: bool,
+    /// Keep only enabled executables
+    pub enabled: bool,
+    /// Keep only disabled executables
+    pub disabled: bool,
+    /// Keep only executables whose path no longer exists
+    pub missing: bool,
+    pub sort: SortBy,
+}
+
+/// An executable matching a search
+#[derive(Debug, Clone)]
+pub struct Hit<'a> {
+    pub group: String,
+    pub name: String,
+    pub bin: &'a Bin,
+    pub score: i64,
+}
+
+/// What a term of the query is matched against
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Field {
+    /// The name, or else the path
+    Any,
+    Name,
+    Path,
+    Group,
+}
+
+#[derive(Debug)]
+enum Pattern {
+    Fuzzy(String),
+    Regex(Regex),
+}
+
+#[derive(Debug)]
+struct Term {
+    field: Field,
+    pattern: Pattern,
+}
+
+/// A parsed query: whitespace separated terms that must all match, each
+/// optionally restricted to a field with `name:`, `path:` or `group:`
+#[derive(Debug)]
+pub struct Query {
+    terms: Vec<Term>,
+}
+
+/// Score `text` for a query whose characters appear in it in order, or
+/// `None` if they don't. Consecutive characters, characters at the start
+/// of a word and prefixes score higher, shorter texts win ties. Every
+/// text matches an empty query equally.
+pub fn fuzzy_score(query: &str, text: &str) -> Option<i64> {
+    if query.trim().is_empty() {
+        return Some(0);
+    }
+    let query: String = query
+        .to_lowercase()
+        .chars()
+        .filter(|c| !c.is_whitespace())
+        .collect();
+    let lower = text.to_lowercase();
+    let text: Vec<char> = lower.chars().collect();
+    let mut score = 0;
+    let mut next = 0;
+    let mut previous: Option<usize> = None;
+    for qc in query.chars() {
+        let pos = (next..text.len()).find(|i| text[*i] == qc)?;
+        score += 1;
+        if previous.is_some_and(|p| p + 1 == pos) {
+            score += 5;
+        }
+        if pos == 0 || !text[pos - 1].is_alphanumeric() {
+            score += 3;
+        }
+        previous = Some(pos);
+        next = pos + 1;
+    }
+    if lower == query {
+        score += 50;
+    } else if lower.starts_with(&query) {
+        score += 20;
+    }
+    Some(score * 1000 - text.len() as i64)
+}
+
+/// Paths are long enough for nearly any query to be a subsequence of
+/// them, so they only match where the term appears as a whole; such
+/// matches rank below matches on the name
+fn path_score(term: &str, path: &str) -> Option<i64> {
+    path.to_lowercase()
+        .contains(&term.to_lowercase())
+        .then(|| -(path.len() as i64))
+}
+
+impl Query {
+    pub fn parse(query: &str, regex: bool) -> Result<Self> {
+        let mut terms = Vec::new();
+        for word in query.split_whitespace() {
+            let (field, pattern) = match word.split_once(':') {
+                Some(("name", p)) => (Field::Name, p),
+                Some(("path", p)) => (Field::Path, p),
+                Some(("group", p)) => (Field::Group, p),
+                _ => (Field::Any, word),
+            };
+            if pattern.is_empty() {
+                continue;
+            }
+            let pattern = if regex {
+                Pattern::Regex(
+                    RegexBuilder::new(pattern)
+                        .case_insensitive(true)
+                        .build()
+                        .map_err(|e| anyhow!("invalid regex {}: {}", pattern, e))?,
+                )
+            } else {
+                Pattern::Fuzzy(pattern.to_string())
+            };
+            terms.push(Term { field, pattern });
+        }
+        Ok(Query { terms })
+    }
+
+    /// The score of an executable, `None` unless every term matches
+    pub fn score(&self, group: &str, name: &str, bin: &Bin) -> Option<i64> {
+        let path = bin.path.to_string_lossy();
+        let mut total = 0;
+        for term in &self.terms {
+            total += match &term.pattern {
+                Pattern::Fuzzy(p) => match term.field {
+                    Field::Name => fuzzy_score(p, name),
+                    Field::Group => fuzzy_score(p, group),
+                    Field::Path => path_score(p, &path),
+                    Field::Any => fuzzy_score(p, name).or_else(|| path_score(p, &path)),
+                },
+                // earlier and shorter matches rank higher
+                Pattern::Regex(re) => {
+                    let found = |text: &str, weight: i64| {
+                        re.find(text)
+                            .map(|m| weight - m.start() as i64 - text.len() as i64)
+                    };
+                    match term.field {
+                        Field::Name => found(name, 1000),
+                        Field::Group => found(group, 1000),
+                        Field::Path => found(&path, 0),
+                        Field::Any => found(name, 1000).or_else(|| found(&path, 0)),
+                    }
+                }
+            }?;
+        }
+        Some(total)
+    }
+}
+
+/// The executables of every group matching `query` and the filters of
+/// `options`, in a deterministic order
+pub fn search<'a>(conf: &'a Config, query: &str, options: &SearchOptions) -> Result<Vec<Hit<'a>>> {
+    let query = Query::parse(query, options.regex)?;
+    let mut hits = Vec::new();
+    for (group_name, group) in &conf.groups {
+        for (bin_name, bin) in &group.bins {
+            if (options.enabled && !bin.enabled)
+                || (options.disabled && bin.enabled)
+                || (options.missing && bin.path.exists())
+            {
+                continue;
+            }
+            if let Some(score) = query.score(group_name, bin_name, bin) {
+                hits.push(Hit {
+                    group: group_name.clone(),
+                    name: bin_name.clone(),
+                    bin,
+                    score,
+                });
+            }
+        }
+    }
+    hits.sort_by(|a, b| (&a.group, &a.name).cmp(&(&b.group, &b.name)));
+    // stable, so ties keep the group and name order
+    match options.sort {
+        SortBy::Score => hits.sort_by_key(|h| Reverse(h.score)),
+        SortBy::Name => hits.sort_by(|a, b| a.name.cmp(&b.name)),
+        SortBy::Group => {}
+        SortBy::Path => hits.sort_by(|a, b| a.bin.path.cmp(&b.bin.path)),
+    }
+    Ok(hits)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_search_ranks_and_filters() {
+        assert!(fuzzy_score("nd", "node").is_some());
+        assert!(fuzzy_score("dn", "node").is_none());
+        assert!(fuzzy_score("node", "node").unwrap() > fuzzy_score("node", "nxoxdxe").unwrap());
+        assert!(fuzzy_score("py", "python").unwrap() > fuzzy_score("py", "happy").unwrap());
+        assert!(fuzzy_score("node", "node").unwrap() > fuzzy_score("node", "nodemon").unwrap());
+
+        let temp_dir = TempDir::new().unwrap();
+        let existing = temp_dir.path().join("node");
+        std::fs::write(&existing, "").unwrap();
+        let mut conf = Config::new(temp_dir.path().join("bin"));
+        for (gn, bn, path) in [
+            ("node20", "node", existing.clone()),
+            ("node20", "nodemon", PathBuf::from("/opt/node/bin/nodemon")),
+            ("node18", "node", PathBuf::from("/opt/node18/bin/node")),
+            (
+                "py3",
+                "python",
+                PathBuf::from("/srv/python/usr/bin/python3"),
+            ),
+            ("tools", "npx", PathBuf::from("/opt/node/bin/npx")),
+        ] {
+            conf.set_extends(gn, Vec::new(), Vec::new()).unwrap();
+            let bin = Bin::new(bn.into(), path, None, conf.layer);
+            let group = conf.groups.get_mut(gn).unwrap();
+            group.bins.insert(bn.to_string(), bin);
+        }
+        conf.groups
+            .get_mut("node18")
+            .unwrap()
+            .bins
+            .get_mut("node")
+            .unwrap()
+            .enabled = false;
+
+        let found = |query: &str, options: &SearchOptions| -> Vec<String> {
+            search(&conf, query, options)
+                .unwrap()
+                .into_iter()
+                .map(|h| format!("{}/{}", h.group, h.name))
+                .collect()
+        };
+        let default = SearchOptions::default();
+        // exact names first, then by group; paths rank last
+        assert_eq!(
+            found("node", &default),
+            ["node18/node", "node20/node", "node20/nodemon", "tools/npx"]
+        );
+        assert_eq!(found("group:py name:pt", &default), ["py3/python"]);
+        assert_eq!(found("path:/usr", &default), ["py3/python"]);
+        let regex = SearchOptions {
+            regex: true,
+            ..Default::default()
+        };
+        // shorter names rank first
+        assert_eq!(
+            found("name:^n.*[xn]$", &regex),
+            ["tools/npx", "node20/nodemon"]
+        );
+        assert!(search(&conf, "(", &regex).is_err());
+
+        let disabled = SearchOptions {
+            disabled: true,
+            ..Default::default()
+        };
+        assert_eq!(found("", &disabled), ["node18/node"]);
+        let missing = SearchOptions {
+            missing: true,
+            sort: SortBy::Path,
+            ..Default::default()
+        };
+        assert_eq!(
+            found("", &missing),
+            ["node20/nodemon", "tools/npx", "node18/node", "py3/python"]
+        );
+    }
+}
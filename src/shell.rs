@@ -117,6 +117,39 @@ pub fn add_path_to_config(shell_type: &ShellType, config_path: &Path, bin_dir: &
     Ok(())
 }
 
+/// Shell code that runs `x auto` whenever the working directory changes,
+/// meant to be evaluated from the shell config file. The directory `x auto`
+/// prints replaces the previous one at the front of PATH, so the selected
+/// groups only apply to the shell the hook runs in.
+pub fn hook_script(shell_type: &ShellType, exe: &Path) -> Result<String> {
+    let exe = exe
+        .to_str()
+        .ok_or_else(|| anyhow!("the path of x is not valid UTF-8"))?;
+    let quoted = format!("'{}'", exe.replace('\'', "'\\''"));
+    // bash and zsh share the PATH update, X_AUTO_DIR tells `x auto` what
+    // the shell used before
+    let update = format!(
+        "  local dir\n  dir=\"$({} auto)\"\n  if [ -n \"$X_AUTO_DIR\" ]; then\n    PATH=\":$PATH:\"\n    PATH=\"${{PATH//\":$X_AUTO_DIR:\"/:}}\"\n    PATH=\"${{PATH#:}}\"\n    PATH=\"${{PATH%:}}\"\n  fi\n  if [ -n \"$dir\" ]; then\n    PATH=\"$dir:$PATH\"\n  fi\n  export X_AUTO_DIR=\"$dir\"\n",
+        quoted
+    );
+    let script = match shell_type {
+        ShellType::Bash => format!(
+            "_x_auto() {{\n  [ \"$PWD\" = \"$_X_AUTO_PWD\" ] && return\n  _X_AUTO_PWD=\"$PWD\"\n{}}}\nif [[ \";$PROMPT_COMMAND;\" != *\";_x_auto;\"* ]]; then\n  PROMPT_COMMAND=\"_x_auto${{PROMPT_COMMAND:+;$PROMPT_COMMAND}}\"\nfi\n",
+            update
+        ),
+        ShellType::Zsh => format!(
+            "_x_auto() {{\n{}}}\nautoload -U add-zsh-hook\nadd-zsh-hook chpwd _x_auto\n_x_auto\n",
+            update
+        ),
+        ShellType::Fish => format!(
+            "function _x_auto --on-variable PWD\n    set -l dir ({} auto)\n    if set -q X_AUTO_DIR[1]\n        set -l i (contains -i -- $X_AUTO_DIR $PATH)\n        and set -e PATH[$i]\n    end\n    set -gx X_AUTO_DIR $dir\n    if test -n \"$dir\"\n        set -gx PATH $dir $PATH\n    end\nend\n_x_auto\n",
+            quoted
+        ),
+        ShellType::Unknown => return Err(anyhow!("cannot write a hook for an unknown shell type")),
+    };
+    Ok(script)
+}
+
 fn path_contains_dir(path_var: &std::ffi::OsStr, dir: &Path) -> bool {
     let target = dir.canonicalize().ok();
     env::split_paths(path_var).any(|entry| {
@@ -172,7 +205,11 @@ fn maybe_copy_executable_if_dir_not_in_path(
 pub fn maybe_copy_current_executable_to_local_x() -> Result<Option<PathBuf>> {
     let current_exe = env::current_exe()?;
     let home_dir = dirs::home_dir().ok_or_else(|| anyhow!("cannot get home directory"))?;
-    maybe_copy_executable_if_dir_not_in_path(&current_exe, env::var_os("PATH").as_deref(), &home_dir)
+    maybe_copy_executable_if_dir_not_in_path(
+        &current_exe,
+        env::var_os("PATH").as_deref(),
+        &home_dir,
+    )
 }
 
 #[cfg(test)]
@@ -245,6 +282,26 @@ mod tests {
         assert_eq!(zsh_path, home_dir.join(".zshrc"));
     }
 
+    #[test]
+    fn test_hook_script() {
+        let exe = Path::new("/opt/x/bin/x");
+        let bash = hook_script(&ShellType::Bash, exe).unwrap();
+        assert!(bash.contains("dir=\"$('/opt/x/bin/x' auto)\""));
+        assert!(bash.contains("PROMPT_COMMAND=\"_x_auto"));
+        assert!(bash.contains("export X_AUTO_DIR"));
+        assert!(
+            hook_script(&ShellType::Zsh, exe)
+                .unwrap()
+                .contains("add-zsh-hook chpwd _x_auto")
+        );
+        assert!(
+            hook_script(&ShellType::Fish, exe)
+                .unwrap()
+                .contains("--on-variable PWD")
+        );
+        assert!(hook_script(&ShellType::Unknown, exe).is_err());
+    }
+
     #[test]
     fn test_path_contains_dir() {
         let temp_dir = TempDir::new().unwrap();
@@ -270,7 +327,10 @@ mod tests {
         let copied_path = copy_executable_to_local_x(&exe_path, &home_dir).unwrap();
         assert_eq!(copied_path, home_dir.join(".local").join("x").join("x"));
         assert!(copied_path.exists());
-        assert_eq!(fs::read(&copied_path).unwrap(), fs::read(&exe_path).unwrap());
+        assert_eq!(
+            fs::read(&copied_path).unwrap(),
+            fs::read(&exe_path).unwrap()
+        );
     }
 
     #[test]
@@ -0,0 +1,361 @@
+//! Per-project version files of other tools (asdf and mise
+//! `.tool-versions`, `.nvmrc`, `.python-version`, `rust-toolchain.toml`)
+//! and the groups they select
+
+use anyhow::Result;
+use sha2::{Digest, Sha256};
+use std::cmp::Ordering;
+use std::collections::HashSet;
+use std::ffi::OsStr;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::config::Config;
+use crate::links::link_atomically;
+
+/// How a requested version is turned into a group name unless the config
+/// has a pattern for the tool
+pub static DEFAULT_PATTERN: &str = "{tool}-{version}";
+
+/// Key of the pattern used for tools without a pattern of their own
+pub static ANY_TOOL: &str = "*";
+
+/// A version asked for by a version file
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VersionRequest {
+    pub tool: String,
+    pub version: String,
+    pub file: PathBuf,
+}
+
+/// The version files in `start` and its ancestors. The nearest file
+/// wins for each tool; requests are ordered from the nearest file out.
+pub fn find_requests(start: &Path) -> Vec<VersionRequest> {
+    let mut requests: Vec<VersionRequest> = Vec::new();
+    for dir in start.ancestors() {
+        for file_name in [
+            ".tool-versions",
+            ".nvmrc",
+            ".node-version",
+            ".python-version",
+            "rust-toolchain.toml",
+            "rust-toolchain",
+        ] {
+            let file = dir.join(file_name);
+            let Ok(contents) = fs::read_to_string(&file) else {
+                continue;
+            };
+            for (tool, version) in parse(file_name, &contents) {
+                if !requests.iter().any(|r| r.tool == tool) {
+                    requests.push(VersionRequest {
+                        tool,
+                        version,
+                        file: file.clone(),
+                    });
+                }
+            }
+        }
+    }
+    requests
+}
+
+/// The tools and versions a version file asks for
+fn parse(file_name: &str, contents: &str) -> Vec<(String, String)> {
+    let first_line = || {
+        contents
+            .lines()
+            .map(|l| l.split('#').next().unwrap_or("").trim())
+            .find(|l| !l.is_empty())
+            .map(str::to_string)
+    };
+    match file_name {
+        ".tool-versions" => contents
+            .lines()
+            .filter_map(|line| {
+                let mut words = line.split('#').next()?.split_whitespace();
+                let tool = match words.next()? {
+                    // asdf calls it nodejs, mise node
+                    "nodejs" => "node",
+                    tool => tool,
+                };
+                Some((tool.to_string(), words.next()?.to_string()))
+            })
+            .collect(),
+        ".nvmrc" | ".node-version" => first_line()
+            .map(|v| ("node".to_string(), v.trim_start_matches('v').to_string()))
+            .into_iter()
+            .collect(),
+        ".python-version" => first_line()
+            .map(|v| ("python".to_string(), v))
+            .into_iter()
+            .collect(),
+        // the legacy `rust-toolchain` holds either TOML or a bare channel
+        _ => toml::from_str::<toml::Table>(contents)
+            .ok()
+            .and_then(|t| {
+                t.get("toolchain")?
+                    .get("channel")?
+                    .as_str()
+                    .map(str::to_string)
+            })
+            .or_else(|| first_line().filter(|l| !l.contains('=')))
+            .map(|channel| ("rust".to_string(), channel))
+            .into_iter()
+            .collect(),
+    }
+}
+
+/// Fail on placeholders other than `{tool}`, `{version}`, `{major}` and
+/// `{minor}`
+pub fn validate_pattern(pattern: &str) -> Result<()> {
+    let mut rest = pattern;
+    while let Some(start) = rest.find('{') {
+        let Some(end) = rest[start..].find('}') else {
+            anyhow::bail!("unterminated placeholder in pattern {}", pattern);
+        };
+        let placeholder = &rest[start + 1..start + end];
+        if !["tool", "version", "major", "minor"].contains(&placeholder) {
+            anyhow::bail!(
+                "unknown placeholder {{{}}} in pattern {}, expected {{tool}}, {{version}}, {{major}} or {{minor}}",
+                placeholder,
+                pattern
+            );
+        }
+        rest = &rest[start + end + 1..];
+    }
+    Ok(())
+}
+
+/// The group name `pattern` gives for a version of a tool
+pub fn group_name(pattern: &str, tool: &str, version: &str) -> String {
+    let mut parts = version.split('.');
+    let major = parts.next().unwrap_or("");
+    let minor = parts.next().unwrap_or("");
+    pattern
+        .replace("{tool}", tool)
+        .replace("{version}", version)
+        .replace("{major}", major)
+        .replace("{minor}", minor)
+}
+
+/// The group a request selects: the group named by the pattern, or else
+/// the newest group that name is a prefix of, so that `.nvmrc` asking for
+/// `20` selects `node-20.11.0`
+pub fn resolve(conf: &Config, request: &VersionRequest) -> Option<String> {
+    let pattern = conf
+        .version_patterns
+        .get(&request.tool)
+        .or_else(|| conf.version_patterns.get(ANY_TOOL))
+        .map(String::as_str)
+        .unwrap_or(DEFAULT_PATTERN);
+    let name = group_name(pattern, &request.tool, &request.version);
+    if conf.group_exists(&name) {
+        return Some(name);
+    }
+    conf.groups
+        .keys()
+        .filter(|gn| {
+            gn.strip_prefix(&name)
+                .is_some_and(|rest| rest.starts_with(['.', '-']))
+        })
+        .max_by(|a, b| compare_versions(a, b))
+        .cloned()
+}
+
+/// Every request of the version files around `start` with the group it
+/// selects, if any
+pub fn select(conf: &Config, start: &Path) -> Vec<(VersionRequest, Option<String>)> {
+    find_requests(start)
+        .into_iter()
+        .map(|request| {
+            let group = resolve(conf, &request);
+            (request, group)
+        })
+        .collect()
+}
+
+/// Compare names by their numbers rather than their digits, so that
+/// `node-20.9` comes before `node-20.11`
+fn compare_versions(a: &str, b: &str) -> Ordering {
+    let split = |s: &str| -> Vec<(bool, String)> {
+        let mut chunks: Vec<(bool, String)> = Vec::new();
+        for c in s.chars() {
+            let digit = c.is_ascii_digit();
+            match chunks.last_mut() {
+                Some((d, chunk)) if *d == digit => chunk.push(c),
+                _ => chunks.push((digit, c.to_string())),
+            }
+        }
+        chunks
+    };
+    for (x, y) in split(a).iter().zip(split(b).iter()) {
+        let ordering = match (x, y) {
+            ((true, x), (true, y)) => x
+                .trim_start_matches('0')
+                .len()
+                .cmp(&y.trim_start_matches('0').len())
+                .then_with(|| x.trim_start_matches('0').cmp(y.trim_start_matches('0'))),
+            ((_, x), (_, y)) => x.cmp(y),
+        };
+        if ordering != Ordering::Equal {
+            return ordering;
+        }
+    }
+    a.len().cmp(&b.len())
+}
+
+/// The groups the version files around `cwd` select, nearest first
+pub fn selected_groups(conf: &Config, cwd: &Path) -> Vec<String> {
+    let mut selected: Vec<String> = Vec::new();
+    for (_, group) in select(conf, cwd) {
+        if let Some(group) = group
+            && !selected.contains(&group)
+        {
+            selected.push(group);
+        }
+    }
+    selected
+}
+
+/// The directory in `state_dir` that `x auto` links the executables of
+/// `groups` into. Each selection has its own, so shells in different
+/// projects never change each other's.
+pub fn auto_dir(state_dir: &Path, groups: &[String]) -> PathBuf {
+    let digest = Sha256::digest(groups.join("\n").as_bytes());
+    let name: String = digest[..8].iter().map(|b| format!("{:02x}", b)).collect();
+    state_dir.join("auto").join(name)
+}
+
+/// Link the enabled executables of `groups` into `dir`, the first group
+/// with a name winning it, and remove every other link there. Returns
+/// how many are linked.
+pub fn link_auto_dir(conf: &Config, groups: &[String], dir: &Path) -> Result<usize> {
+    let Some((first, rest)) = groups.split_first() else {
+        anyhow::bail!("no group is selected");
+    };
+    let mut selected = conf.clone();
+    selected.active_group = first.clone();
+    selected.also_active = rest.to_vec();
+    let wanted = selected.linked_bins()?;
+
+    fs::create_dir_all(dir)?;
+    let names: HashSet<&OsStr> = wanted.values().map(|b| b.link_name()).collect();
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        let file_name = path.file_name().unwrap_or_default();
+        // a shell in the same project may be linking at the same time
+        if !names.contains(file_name) && !file_name.to_string_lossy().starts_with(".x-tmp-") {
+            fs::remove_file(&path)?;
+        }
+    }
+    for b in wanted.values() {
+        let path = dir.join(b.link_name());
+        if !fs::read_link(&path).is_ok_and(|target| target == b.path) {
+            link_atomically(&b.path, &path)?;
+        }
+    }
+    Ok(wanted.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{Bin, Origin};
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_version_files_select_groups() {
+        let temp_dir = TempDir::new().unwrap();
+        let project = temp_dir.path().join("project");
+        let nested = project.join("web");
+        fs::create_dir_all(&nested).unwrap();
+        fs::write(
+            project.join(".tool-versions"),
+            "nodejs 18.19.0\npython 3.12.1 3.11.7 # main\n",
+        )
+        .unwrap();
+        fs::write(nested.join(".nvmrc"), "v20\n").unwrap();
+        fs::write(
+            project.join("rust-toolchain.toml"),
+            "[toolchain]\nchannel = \"stable\"\n",
+        )
+        .unwrap();
+
+        let requests: Vec<(String, String)> = find_requests(&nested)
+            .into_iter()
+            .map(|r| (r.tool, r.version))
+            .collect();
+        assert_eq!(
+            requests,
+            [
+                ("node".to_string(), "20".to_string()),
+                ("python".to_string(), "3.12.1".to_string()),
+                ("rust".to_string(), "stable".to_string()),
+            ]
+        );
+
+        let mut conf = Config::new(temp_dir.path().join("bin"));
+        for gn in [
+            "node-20.9.0",
+            "node-20.11.0",
+            "py3.12",
+            "rust-stable-x86_64-unknown-linux-gnu",
+        ] {
+            conf.set_extends(gn, Vec::new(), Vec::new()).unwrap();
+        }
+        conf.version_patterns
+            .insert("python".to_string(), "py{major}.{minor}".to_string());
+        let groups: Vec<Option<String>> =
+            select(&conf, &nested).into_iter().map(|(_, g)| g).collect();
+        assert_eq!(
+            groups,
+            [
+                Some("node-20.11.0".to_string()),
+                Some("py3.12".to_string()),
+                Some("rust-stable-x86_64-unknown-linux-gnu".to_string()),
+            ]
+        );
+        assert!(validate_pattern("{tool}@{patch}").is_err());
+
+        // entering the project links its groups, node 18 has none
+        let selected = selected_groups(&conf, &project);
+        assert_eq!(selected, ["py3.12", "rust-stable-x86_64-unknown-linux-gnu"]);
+        let state_dir = temp_dir.path().join("state");
+        let dir = auto_dir(&state_dir, &selected);
+        assert_ne!(dir, auto_dir(&state_dir, &selected[..1]));
+        assert!(link_auto_dir(&conf, &[], &dir).is_err());
+        assert!(selected_groups(&conf, temp_dir.path()).is_empty());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_link_auto_dir() {
+        let temp_dir = TempDir::new().unwrap();
+        let tool = temp_dir.path().join("tool");
+        fs::write(&tool, "").unwrap();
+        let mut conf = Config::new(temp_dir.path().join("bin"));
+        for gn in ["node-20", "node-18"] {
+            conf.set_extends(gn, Vec::new(), Vec::new()).unwrap();
+            let bin = Bin::new("node".into(), temp_dir.path().join(gn), None, Origin::User);
+            let group = conf.groups.get_mut(gn).unwrap();
+            group.bins.insert("node".to_string(), bin);
+        }
+        let bin = Bin::new("tool".into(), tool.clone(), None, Origin::User);
+        let group = conf.groups.get_mut("node-18").unwrap();
+        group.bins.insert("tool".to_string(), bin);
+
+        let dir = temp_dir.path().join("auto");
+        let groups = ["node-20".to_string(), "node-18".to_string()];
+        assert_eq!(link_auto_dir(&conf, &groups, &dir).unwrap(), 2);
+        assert_eq!(
+            fs::read_link(dir.join("node")).unwrap(),
+            temp_dir.path().join("node-20")
+        );
+        assert_eq!(fs::read_link(dir.join("tool")).unwrap(), tool);
+
+        // links of groups no longer selected go away
+        assert_eq!(link_auto_dir(&conf, &groups[..1], &dir).unwrap(), 1);
+        assert!(dir.join("tool").symlink_metadata().is_err());
+        assert!(conf.bin_dir.read_dir().is_err());
+    }
+}
@@ -0,0 +1,128 @@
+use anyhow::Result;
+use notify::{Event, EventKind, RecursiveMode, Watcher};
+use std::collections::BTreeSet;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc;
+use std::time::Duration;
+
+use crate::config::{OnConflict, SyncReport, get_config_path, load_config};
+use crate::history;
+
+/// What `watch` reports while it runs
+pub enum WatchEvent<'a> {
+    /// A source directory is now watched
+    Watching(&'a Path),
+    /// A source directory could not be watched, e.g. because it is gone
+    Unwatchable(&'a Path, notify::Error),
+    /// A group was synced after its directories changed
+    Synced(&'a str, SyncReport),
+    /// Syncing a group failed, the other groups are still watched
+    Failed(&'a str, anyhow::Error),
+}
+
+/// Keep every group in sync with its source directories until the
+/// process is interrupted. Changes are collected until nothing happened
+/// for `debounce`, so that a build dropping many files causes one sync.
+/// The config is read again on every change, which also picks up
+/// directories added by other `x` commands meanwhile.
+pub fn watch(debounce: Duration, mut report: impl FnMut(WatchEvent)) -> Result<()> {
+    let conf_path = get_config_path()?;
+    let (tx, rx) = mpsc::channel::<notify::Result<Event>>();
+    // inotify on Linux, the native backend or polling elsewhere
+    let mut watcher = notify::recommended_watcher(tx)?;
+    if let Some(conf_dir) = conf_path.parent() {
+        watcher.watch(conf_dir, RecursiveMode::NonRecursive)?;
+    }
+
+    let mut watched: BTreeSet<(PathBuf, bool)> = BTreeSet::new();
+    let mut unwatchable: BTreeSet<PathBuf> = BTreeSet::new();
+    // the first round syncs everything that changed while not watching
+    let mut changed: Option<Vec<PathBuf>> = None;
+    loop {
+        let mut conf = load_config(false)?;
+        let sources = conf.source_dirs();
+
+        let mut groups: BTreeSet<&str> = BTreeSet::new();
+        for (gn, source) in &sources {
+            let affected = match &changed {
+                None => true,
+                Some(paths) => paths.iter().any(|p| p.starts_with(&source.dir)),
+            };
+            if affected {
+                groups.insert(gn);
+            }
+        }
+        let mut synced = false;
+        for gn in groups {
+            match conf.sync(gn, OnConflict::Skip) {
+                Ok(sync_report) => {
+                    synced = true;
+                    if !sync_report.added.is_empty() || !sync_report.removed.is_empty() {
+                        report(WatchEvent::Synced(gn, sync_report));
+                    }
+                }
+                Err(e) => report(WatchEvent::Failed(gn, e)),
+            }
+        }
+        // a build replacing its tools should not push the changes made by
+        // hand out of the history
+        if synced {
+            history::save_collapsed(&conf)?;
+        }
+
+        let wanted: BTreeSet<(PathBuf, bool)> = sources
+            .iter()
+            .map(|(_, s)| {
+                let recursive = s.rules.recursive || s.rules.max_depth.is_some_and(|d| d > 1);
+                (s.dir.clone(), recursive)
+            })
+            .collect();
+        for (dir, _) in watched.difference(&wanted) {
+            let _ = watcher.unwatch(dir);
+        }
+        watched.retain(|w| wanted.contains(w));
+        for (dir, recursive) in wanted {
+            // a deleted directory loses its watch and is watched again
+            // once it is back
+            if watched.contains(&(dir.clone(), recursive)) && dir.is_dir() {
+                continue;
+            }
+            let mode = if recursive {
+                RecursiveMode::Recursive
+            } else {
+                RecursiveMode::NonRecursive
+            };
+            match watcher.watch(&dir, mode) {
+                Ok(()) => {
+                    if !watched.contains(&(dir.clone(), recursive)) {
+                        report(WatchEvent::Watching(&dir));
+                    }
+                    unwatchable.remove(&dir);
+                    watched.insert((dir, recursive));
+                }
+                Err(e) => {
+                    watched.remove(&(dir.clone(), recursive));
+                    if unwatchable.insert(dir.clone()) {
+                        report(WatchEvent::Unwatchable(&dir, e));
+                    }
+                }
+            }
+        }
+
+        let mut paths = Vec::new();
+        collect_paths(rx.recv()?, &mut paths);
+        while let Ok(event) = rx.recv_timeout(debounce) {
+            collect_paths(event, &mut paths);
+        }
+        changed = Some(paths);
+    }
+}
+
+/// Paths whose contents or permissions changed; reads are ignored
+fn collect_paths(event: notify::Result<Event>, paths: &mut Vec<PathBuf>) {
+    if let Ok(event) = event
+        && !matches!(event.kind, EventKind::Access(_))
+    {
+        paths.extend(event.paths);
+    }
+}